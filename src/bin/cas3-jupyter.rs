@@ -0,0 +1,74 @@
+//! `cas3-jupyter CONNECTION_FILE` is what jupyter runs, `cas3-jupyter --install` tells jupyter
+//! about it.
+
+use cas3::cli::Cli;
+use cas3::jupyter::{kernel_spec, ConnectionInfo, Handler, Server};
+use cas3::*;
+use std::path::PathBuf;
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const USAGE: &str = "usage: cas3-jupyter CONNECTION_FILE
+       cas3-jupyter --install    write the kernelspec to $JUPYTER_DATA_DIR/kernels/cas3
+";
+
+/// where jupyter looks for kernelspecs
+fn kernels_dir() -> Option<PathBuf> {
+    let data = match std::env::var_os("JUPYTER_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/share/jupyter"),
+    };
+    Some(data.join("kernels"))
+}
+
+fn install() -> std::io::Result<PathBuf> {
+    let dir = kernels_dir().ok_or(std::io::ErrorKind::NotFound)?.join("cas3");
+    std::fs::create_dir_all(&dir)?;
+    let spec = kernel_spec(&std::env::current_exe()?);
+    let path = dir.join("kernel.json");
+    std::fs::write(&path, format!("{spec:#}\n"))?;
+    Ok(path)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let file = match args.as_slice() {
+        [flag] if flag == "--install" => match install() {
+            Ok(path) => return println!("wrote {}", path.display()),
+            Err(err) => {
+                eprintln!("cannot install the kernelspec: {err}");
+                std::process::exit(2);
+            }
+        },
+        [file] if !file.starts_with('-') => file,
+        _ => {
+            eprint!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    let info = match std::fs::read_to_string(file).map_err(|e| e.to_string()).and_then(|s| ConnectionInfo::parse(&s)) {
+        Ok(info) => info,
+        Err(err) => {
+            eprintln!("cannot read {file}: {err}");
+            std::process::exit(2);
+        }
+    };
+
+    let mut ctx = Context2::new();
+    // interrupting a cell in the notebook sends SIGINT
+    signal_hook::flag::register(signal_hook::consts::SIGINT, ctx.abort.handle()).unwrap();
+    startup_attrs(&mut ctx);
+    if let Err(err) = Cli::default().prepare(&mut ctx) {
+        eprintln!("{err}");
+        std::process::exit(2);
+    }
+    let server = match Server::bind(info) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("cannot listen: {err}");
+            std::process::exit(2);
+        }
+    };
+    server.run(&mut Handler::new(Kernel::with_context(ctx, KernelSettings::default())));
+}
@@ -0,0 +1,15 @@
+//! `cas3-lsp` is a language server for .sexp files, talking LSP over stdin and stdout.
+
+use std::io;
+
+fn main() {
+    match cas3::lsp::run(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => {}
+        // exiting without being asked to shut down first
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("cas3-lsp: {err}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! the `cas3` command line. with nothing to run (and a terminal on stdin) it starts the REPL,
+//! otherwise it runs a script, `-e` expressions or whatever comes in on stdin, and the exit
+//! status says whether anything printed a message.
+
+use crate::{
+    contexts, evaluate, format_expr, liste, message_count, parse_source, protect_system, run_file, run_file_with, sym, Context2, Expr,
+    Form, Syntax,
+};
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "usage: cas3 [options] [script]
+       cas3 fmt [--width N] [--check] [file...]    format source, see cas3 fmt --help
+
+  script             run a file and exit (.wl and .m files are infix)
+  -                  read expressions from stdin, even from a terminal
+  -e, --eval EXPR    evaluate EXPR and print the result (repeatable)
+  --no-prelude       skip lang/attrs.sexp, startup.sexp and calculus.sexp
+  --prelude PATH     load PATH instead, a file or a directory with those three files
+  --load FILE        load FILE after the prelude (repeatable)
+  --syntax NAME      read input as \"sexp\" or \"infix\"
+  -h, --help         show this
+
+with no script or -e and stdin not a terminal, reads stdin. exits 1 if anything printed
+a message, 2 on bad arguments or unreadable files. without --prelude the prelude is
+$CAS3_PRELUDE, or lang/ in the current directory or next to the executable.
+";
+
+/// the files loaded before anything else
+pub const PRELUDE_FILES: &[&str] = &["attrs.sexp", "startup.sexp", "calculus.sexp"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prelude {
+    /// `$CAS3_PRELUDE`, or `lang/` in the current directory or next to the executable
+    Default,
+    None,
+    Path(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    pub script: Option<PathBuf>,
+    pub exprs: Vec<String>,
+    pub prelude: Prelude,
+    pub loads: Vec<PathBuf>,
+    pub syntax: Option<Syntax>,
+    /// `-` was given
+    pub stdin: bool,
+    pub help: bool,
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        Self {
+            script: None,
+            exprs: vec![],
+            prelude: Prelude::Default,
+            loads: vec![],
+            syntax: None,
+            stdin: false,
+            help: false,
+        }
+    }
+}
+
+/// where the prelude is when no option says. like `--prelude`, `$CAS3_PRELUDE` can be a file
+/// or a directory
+pub fn default_prelude() -> Result<PathBuf, String> {
+    if let Some(p) = std::env::var_os("CAS3_PRELUDE") {
+        return Ok(p.into());
+    }
+    let beside_exe = std::env::current_exe().ok().and_then(|exe| Some(exe.parent()?.join("lang")));
+    [Some(PathBuf::from("lang")), beside_exe].into_iter().flatten().find(|dir| dir.is_dir()).ok_or_else(|| {
+        "cannot find the prelude, there's no lang/ here or next to the executable. \
+         set CAS3_PRELUDE to it, or pass --prelude or --no-prelude"
+            .to_string()
+    })
+}
+
+impl Cli {
+    /// the arguments after the program name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Cli::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // --opt=value is the same as --opt value
+            let (flag, inline) = match arg.split_once('=') {
+                Some((f, v)) if f.starts_with("--") => (f.to_string(), Some(v.to_string())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| inline.clone().or_else(|| args.next()).ok_or(format!("{name} needs a value"));
+            match flag.as_str() {
+                "-h" | "--help" => cli.help = true,
+                "-e" | "--eval" => cli.exprs.push(value(&flag)?),
+                "--no-prelude" => cli.prelude = Prelude::None,
+                "--prelude" => cli.prelude = Prelude::Path(value(&flag)?.into()),
+                "--load" => cli.loads.push(value(&flag)?.into()),
+                "--syntax" => {
+                    let name = value(&flag)?;
+                    cli.syntax = Some(Syntax::from_name(&name).ok_or(format!("unknown syntax {name}, expected sexp or infix"))?);
+                }
+                "-" => cli.stdin = true,
+                f if f.starts_with('-') => return Err(format!("unknown option {f}")),
+                _ if cli.script.is_none() => cli.script = Some(arg.into()),
+                _ => return Err(format!("unexpected argument {arg}")),
+            }
+        }
+        Ok(cli)
+    }
+
+    /// the REPL, unless there's something else to do
+    pub fn interactive(&self) -> bool {
+        self.script.is_none() && self.exprs.is_empty() && !self.stdin && std::io::stdin().is_terminal()
+    }
+
+    /// the prelude files in the order they load
+    pub fn prelude_files(&self) -> Result<Vec<PathBuf>, String> {
+        let path = match &self.prelude {
+            Prelude::None => return Ok(vec![]),
+            Prelude::Path(p) => p.clone(),
+            Prelude::Default => default_prelude()?,
+        };
+        if !path.is_dir() {
+            return Ok(vec![path]);
+        }
+        Ok(PRELUDE_FILES.iter().map(|f| path.join(f)).collect())
+    }
+
+    /// loads the prelude and `--load` files and applies `--syntax`
+    pub fn prepare(&self, ctx: &mut Context2) -> Result<(), String> {
+        // the prelude's names are system names, so packages see them
+        ctx.set_ownvalue("$Context", Expr::Str(contexts::SYSTEM.into()));
+        for file in self.prelude_files()? {
+            run_file(ctx, &file).map_err(|err| format!("cannot load {}: {err}", file.display()))?;
+        }
+        ctx.set_ownvalue("$Context", Expr::Str(contexts::GLOBAL.into()));
+        protect_system(ctx);
+        for file in &self.loads {
+            run_file(ctx, file).map_err(|err| format!("cannot load {}: {err}", file.display()))?;
+        }
+        if let Some(syntax) = self.syntax {
+            ctx.set_ownvalue("$Syntax", Expr::Str(syntax.name().into()));
+        }
+        Ok(())
+    }
+
+    /// everything but the REPL. gives the exit status, `ABORTED_STATUS` if evaluation was aborted
+    pub fn run_batch(&self, ctx: &mut Context2) -> i32 {
+        let before = message_count();
+        if let Some(script) = &self.script {
+            let syntax = self.syntax.unwrap_or_else(|| Syntax::for_path(script));
+            if let Err(err) = run_file_with(ctx, script, syntax) {
+                eprintln!("cannot read {}: {err}", script.display());
+                return 2;
+            }
+            if ctx.abort.take() {
+                return ABORTED_STATUS;
+            }
+        }
+        for (i, src) in self.exprs.iter().enumerate() {
+            if !eval_source(ctx, &format!("-e {}", i + 1), src) {
+                return ABORTED_STATUS;
+            }
+        }
+        if self.stdin || (self.script.is_none() && self.exprs.is_empty()) {
+            let mut src = String::new();
+            if let Err(err) = std::io::stdin().read_to_string(&mut src) {
+                eprintln!("cannot read stdin: {err}");
+                return 2;
+            }
+            if !eval_source(ctx, "stdin", &src) {
+                return ABORTED_STATUS;
+            }
+        }
+        if message_count() > before {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// what a batch run exits with when it's aborted, the same as being killed by ctrl-c
+pub const ABORTED_STATUS: i32 = 130;
+
+/// evaluates each top level form of `src` as its own numbered line, like the REPL, and prints
+/// the results that aren't Null. stops and gives false if evaluation was aborted
+pub fn eval_source(ctx: &mut Context2, name: &str, src: &str) -> bool {
+    let (exprs, errors) = parse_source(name, src, Syntax::of_context(ctx));
+    for err in &errors {
+        message!("{err}");
+    }
+    for expr in exprs {
+        let n = ctx.line();
+        let expr = contexts::resolve(ctx, &expr);
+        let res = evaluate(&mut liste(vec![]), ctx, &expr);
+        if ctx.abort.take() {
+            return false;
+        }
+        ctx.record_history(&n, &expr, &res);
+        if res != sym("Null") {
+            println!("{}", format_expr(&contexts::shorten(ctx, &res), Form::of_context(ctx)));
+        }
+    }
+    true
+}
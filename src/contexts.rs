@@ -0,0 +1,321 @@
+//! wolfram style contexts, so a package's helpers don't collide with user code.
+//!
+//! a symbol's full name is its context and its short name, like ``systems`succ``. `System``
+//! (builtins and the prelude) and `Global`` (everything typed at the prompt) are where symbols
+//! always lived, so their symbols keep plain names and nothing changes for code that never
+//! mentions a context.
+//!
+//! every top level form goes through `resolve` before it's evaluated. ``p`x`` is taken as is,
+//! `` `x`` is relative to `$Context`, and a short name is looked for in `$Context` and then each
+//! context of `$ContextPath`. one found nowhere is made in `$Context`. `BeginPackage` and `Begin`
+//! move `$Context` (and `BeginPackage` the path), `EndPackage` leaves the package on the path so
+//! the symbols it made before any `Begin` are what callers see, and `Needs` finds a package's
+//! file in `$Path` and loads it once.
+
+use crate::{get_ownvalue, help, liste, run_file, sym, Context2, Expr};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+pub const SYSTEM: &str = "System`";
+pub const GLOBAL: &str = "Global`";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Scope {
+    Package { context: String, previous: String, path: Expr },
+    Begin { previous: String },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Contexts {
+    /// the short names made in each context so far
+    names: HashMap<String, HashSet<String>>,
+    /// open `BeginPackage`s and `Begin`s, innermost last
+    scopes: Vec<Scope>,
+}
+
+impl Default for Contexts {
+    fn default() -> Self {
+        let system = help::BUILTINS
+            .iter()
+            .map(|(n, _, _)| *n)
+            .chain(crate::ATTRIBUTES.iter().copied());
+        let names = HashMap::from([(SYSTEM.to_string(), system.map(String::from).collect())]);
+        Contexts { names, scopes: vec![] }
+    }
+}
+
+/// the ownvalues this all lives in
+pub fn defaults() -> [(&'static str, Expr); 4] {
+    let strs = |ss: &[&str]| liste(std::iter::once(sym("List")).chain(ss.iter().map(|s| Expr::Str(s.to_string()))).collect());
+    [
+        ("$Context", Expr::Str(GLOBAL.into())),
+        ("$ContextPath", strs(&[SYSTEM, GLOBAL])),
+        ("$Packages", strs(&[])),
+        ("$Path", strs(&[".", "lang"])),
+    ]
+}
+
+fn strings(ctx: &Context2, name: &str) -> Vec<String> {
+    match get_ownvalue(ctx, sym(name)) {
+        Some(Expr::List(items)) => items[1..]
+            .iter()
+            .filter_map(|e| if let Expr::Str(s) = e { Some(s.clone()) } else { None })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn set_strings(ctx: &mut Context2, name: &str, ss: &[String]) {
+    let items = std::iter::once(sym("List")).chain(ss.iter().map(|s| Expr::Str(s.clone())));
+    ctx.set_ownvalue(name, liste(items.collect()));
+}
+
+pub fn current(ctx: &Context2) -> String {
+    match get_ownvalue(ctx, sym("$Context")) {
+        Some(Expr::Str(s)) => s,
+        _ => GLOBAL.to_string(),
+    }
+}
+
+/// a full name, which for system and global symbols is just the short name
+fn full_name(context: &str, short: &str) -> String {
+    if context == SYSTEM || context == GLOBAL {
+        short.to_string()
+    } else {
+        format!("{context}{short}")
+    }
+}
+
+/// `"p`q`x"` is `("p`q`", "x")`. plain names are global or system, we can't tell which here
+fn split(name: &str) -> Option<(&str, &str)> {
+    let i = name.rfind('`')?;
+    Some((&name[..=i], &name[i + 1..]))
+}
+
+/// where a short name is found: `$Context` then `$ContextPath`. global symbols aren't looked
+/// for, a name that's nowhere else is global already
+fn lookup(ctx: &Context2, search: &[String], short: &str) -> Option<String> {
+    search
+        .iter()
+        .find(|c| *c != GLOBAL && ctx.contexts.names.get(*c).is_some_and(|ns| ns.contains(short)))
+        .map(|c| full_name(c, short))
+}
+
+/// `$Context` followed by `$ContextPath`
+fn search_order(ctx: &Context2) -> Vec<String> {
+    std::iter::once(current(ctx)).chain(strings(ctx, "$ContextPath")).collect()
+}
+
+fn make(ctx: &mut Context2, context: &str, short: &str) -> String {
+    if context != GLOBAL {
+        ctx.contexts.names.entry(context.to_string()).or_default().insert(short.to_string());
+    }
+    full_name(context, short)
+}
+
+fn resolve_name(ctx: &mut Context2, search: &[String], name: &str) -> String {
+    let name = match name.strip_prefix('`') {
+        Some(rest) => format!("{}{rest}", search[0]),
+        None => name.to_string(),
+    };
+    match split(&name) {
+        Some((context, short)) => make(ctx, context, short),
+        None => lookup(ctx, search, &name).unwrap_or_else(|| make(ctx, &search[0], &name)),
+    }
+}
+
+/// `ex` with every symbol changed to the one it names, making the ones that don't exist yet
+pub fn resolve(ctx: &mut Context2, ex: &Expr) -> Expr {
+    let search = search_order(ctx);
+    // no packages around, so every name means itself. the prelude still has to be gone
+    // through, its names are system names
+    if search[0] == GLOBAL && search.iter().all(|c| c == SYSTEM || c == GLOBAL) && !has_context_mark(ex) {
+        return ex.clone();
+    }
+    resolve_in(ctx, &search, ex)
+}
+
+fn has_context_mark(ex: &Expr) -> bool {
+    match ex {
+        Expr::Sym(s) => s.contains('`'),
+        Expr::List(items) => items.iter().any(has_context_mark),
+        _ => false,
+    }
+}
+
+fn resolve_in(ctx: &mut Context2, search: &[String], ex: &Expr) -> Expr {
+    match ex {
+        Expr::Sym(s) => {
+            let name = resolve_name(ctx, search, s);
+            if name == s.as_str() {
+                ex.clone()
+            } else {
+                sym(&name)
+            }
+        }
+        Expr::List(items) => {
+            let resolved: Vec<Expr> = items.iter().map(|e| resolve_in(ctx, search, e)).collect();
+            if resolved == items[..] {
+                return ex.clone();
+            }
+            let out = liste(resolved);
+            if let (Expr::List(ls), Some(span)) = (&out, items.span()) {
+                ls.set_span(span);
+            }
+            out
+        }
+        _ => ex.clone(),
+    }
+}
+
+/// `ex` with the short name of every symbol that reads back as the same symbol, for printing
+pub fn shorten(ctx: &Context2, ex: &Expr) -> Expr {
+    match ex {
+        Expr::Sym(s) => match split(s) {
+            Some((_, short)) if lookup(ctx, &search_order(ctx), short).as_deref() == Some(s.as_str()) => sym(short),
+            _ => ex.clone(),
+        },
+        Expr::List(items) if has_context_mark(ex) => liste(items.iter().map(|e| shorten(ctx, e)).collect()),
+        _ => ex.clone(),
+    }
+}
+
+/// the builtins, and what the prelude defined. `$` variables like `$Path` are settings, so
+/// they're left out
+pub fn system_symbols(ctx: &Context2) -> Vec<String> {
+    let defined = |name: &str| {
+        help::builtin(name).is_some()
+            || ctx.vars.get(&sym(name)).is_some_and(|te| te.own.is_some() || te.down.len() > 1 || te.sub.len() > 1)
+            || crate::attributes_of(ctx, &sym(name)).is_some()
+    };
+    let mut names: Vec<String> =
+        ctx.contexts.names[SYSTEM].iter().filter(|n| !n.starts_with('$') && defined(n)).cloned().collect();
+    names.sort();
+    names
+}
+
+/// `(Context x)`
+pub fn context_of(ctx: &Context2, s: &str) -> String {
+    match split(s) {
+        Some((context, _)) => context.to_string(),
+        None if ctx.contexts.names[SYSTEM].contains(s) => SYSTEM.to_string(),
+        None => GLOBAL.to_string(),
+    }
+}
+
+fn is_context(name: &str) -> bool {
+    name.ends_with('`') && !name.starts_with('`') && name.split('`').all(|part| !part.contains(char::is_whitespace))
+}
+
+/// `(BeginPackage "p`" needs...)`: makes `p`` the context, with only it and `System`` to look
+/// names up in, after loading the packages it needs
+pub fn begin_package(ctx: &mut Context2, context: &str, needs: &[String]) -> Expr {
+    if !is_context(context) {
+        message!("BeginPackage: {context} is not a context name like \"Name`\"");
+        return sym("$Failed");
+    }
+    let previous = current(ctx);
+    let path = get_ownvalue(ctx, sym("$ContextPath")).unwrap_or_else(|| liste(vec![sym("List")]));
+    ctx.contexts.scopes.push(Scope::Package { context: context.to_string(), previous, path });
+    ctx.contexts.names.entry(context.to_string()).or_default();
+    ctx.set_ownvalue("$Context", Expr::Str(context.to_string()));
+    set_strings(ctx, "$ContextPath", &[context.to_string(), SYSTEM.to_string()]);
+    let mut packages = strings(ctx, "$Packages");
+    if !packages.iter().any(|p| p == context) {
+        packages.insert(0, context.to_string());
+        set_strings(ctx, "$Packages", &packages);
+    }
+    for n in needs {
+        if needs_package(ctx, n, None) == sym("$Failed") {
+            return sym("$Failed");
+        }
+    }
+    sym("Null")
+}
+
+/// `(EndPackage)`: back to where `BeginPackage` was, with the package first on the path
+pub fn end_package(ctx: &mut Context2) -> Expr {
+    let Some(Scope::Package { .. }) = ctx.contexts.scopes.last() else {
+        message!("EndPackage: no BeginPackage to end, or a Begin is still open");
+        return sym("$Failed");
+    };
+    let Some(Scope::Package { context, previous, path }) = ctx.contexts.scopes.pop() else { unreachable!() };
+    ctx.set_ownvalue("$Context", Expr::Str(previous));
+    ctx.set_ownvalue("$ContextPath", path);
+    let mut path = strings(ctx, "$ContextPath");
+    path.retain(|c| *c != context);
+    path.insert(0, context.clone());
+    set_strings(ctx, "$ContextPath", &path);
+    // names that now mean the package's symbol instead of one that has definitions
+    let mut shadowed: Vec<&String> = ctx.contexts.names[&context]
+        .iter()
+        .filter(|short| ctx.vars.contains_key(&sym(short)))
+        .collect();
+    shadowed.sort();
+    for short in shadowed {
+        message!("{context}{short} shadows {short}");
+    }
+    sym("Null")
+}
+
+/// `(Begin "`Private`")`, relative to `$Context`, or `(Begin "p`")`. gives the new context
+pub fn begin(ctx: &mut Context2, name: &str) -> Expr {
+    let previous = current(ctx);
+    let context = match name.strip_prefix('`') {
+        Some(rest) => format!("{previous}{rest}"),
+        None => name.to_string(),
+    };
+    if !is_context(&context) {
+        message!("Begin: {name} is not a context name like \"`Private`\"");
+        return sym("$Failed");
+    }
+    ctx.contexts.scopes.push(Scope::Begin { previous });
+    ctx.contexts.names.entry(context.clone()).or_default();
+    ctx.set_ownvalue("$Context", Expr::Str(context.clone()));
+    Expr::Str(context)
+}
+
+/// `(End)`: back to the context before the last `Begin`. gives the one it left
+pub fn end(ctx: &mut Context2) -> Expr {
+    let Some(Scope::Begin { .. }) = ctx.contexts.scopes.last() else {
+        message!("End: no Begin to end");
+        return sym("$Failed");
+    };
+    let Some(Scope::Begin { previous }) = ctx.contexts.scopes.pop() else { unreachable!() };
+    let left = current(ctx);
+    ctx.set_ownvalue("$Context", Expr::Str(previous));
+    Expr::Str(left)
+}
+
+/// where `Needs` finds `p`q``: p/q.sexp or p/q.wl in one of the `$Path` directories
+fn package_file(ctx: &Context2, context: &str) -> Option<PathBuf> {
+    let stem = context.trim_end_matches('`').replace('`', "/");
+    strings(ctx, "$Path")
+        .iter()
+        .flat_map(|dir| ["sexp", "wl"].map(|ext| PathBuf::from(dir).join(format!("{stem}.{ext}"))))
+        .find(|p| p.is_file())
+}
+
+/// `(Needs "p`")` or `(Needs "p`" "file")`: loads the package unless it's in `$Packages` already
+pub fn needs_package(ctx: &mut Context2, context: &str, file: Option<&str>) -> Expr {
+    if !is_context(context) {
+        message!("Needs: {context} is not a context name like \"Name`\"");
+        return sym("$Failed");
+    }
+    if strings(ctx, "$Packages").iter().any(|p| p == context) {
+        return sym("Null");
+    }
+    let Some(path) = file.map(PathBuf::from).or_else(|| package_file(ctx, context)) else {
+        message!("Needs: no file for {context} in $Path {:?}", strings(ctx, "$Path"));
+        return sym("$Failed");
+    };
+    if let Err(err) = run_file(ctx, &path) {
+        message!("Needs: cannot open {}: {err}", path.display());
+        return sym("$Failed");
+    }
+    if !strings(ctx, "$Packages").iter().any(|p| p == context) {
+        message!("Needs: {} didn't make the package {context}", path.display());
+        return sym("$Failed");
+    }
+    sym("Null")
+}
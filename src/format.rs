@@ -0,0 +1,929 @@
+//! printing expressions other than as raw s-expressions.
+//!
+//! `FullForm` is what `Display for Expr` already prints. `InputForm` is the infix syntax
+//! `infix_parser` reads, with as few parens as the precedences allow. `OutputForm` is a 2d
+//! layout with stacked fractions and raised exponents.
+//!
+//! `TeXForm` and `MathMLForm` are for pasting into documents rather than reading back.
+//!
+//! all of them share one walk over the expression (`render`), they only differ in how the
+//! pieces get glued together (`Render`)
+
+use crate::{get_ownvalue, head, sym, Context2, Expr, WL_NAMES};
+use num_traits::Signed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Form {
+    Full,
+    Input,
+    Output,
+}
+
+impl Form {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "FullForm" => Some(Form::Full),
+            "InputForm" => Some(Form::Input),
+            "OutputForm" => Some(Form::Output),
+            _ => None,
+        }
+    }
+
+    /// the form the REPL prints results in, from `$OutputForm`
+    pub fn of_context(ctx: &Context2) -> Self {
+        match get_ownvalue(ctx, sym("$OutputForm")) {
+            Some(Expr::Sym(s)) => Form::from_name(&s).unwrap_or(Form::Full),
+            Some(Expr::Str(s)) => Form::from_name(&s).unwrap_or(Form::Full),
+            _ => Form::Full,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixity {
+    /// `a + b + c`, nests to the left like the parser does
+    Left,
+    /// `a -> b -> c` is `a -> (b -> c)`
+    Right,
+    Prefix,
+    Postfix,
+}
+
+/// one row of the operator table. `prec` is higher for tighter binding, and has to agree
+/// with the order of the levels in `infix_parser`
+#[derive(Debug)]
+pub struct Operator {
+    pub head: &'static str,
+    pub prec: u16,
+    pub fixity: Fixity,
+    pub input: &'static str,
+    pub tex: &'static str,
+    pub mathml: &'static str,
+}
+
+pub const PREC_NEGATE: u16 = 480;
+pub const PREC_POWER: u16 = 590;
+pub const PREC_ATOM: u16 = 1000;
+pub const PREC_PLUS: u16 = 310;
+pub const PREC_TIMES: u16 = 400;
+
+#[rustfmt::skip]
+pub const OPERATORS: &[Operator] = &[
+    Operator { head: "CompoundExpression", prec: 10, fixity: Fixity::Left, input: "; ", tex: ";\\; ", mathml: ";" },
+    Operator { head: "set", prec: 40, fixity: Fixity::Right, input: " = ", tex: "=", mathml: "=" },
+    Operator { head: "setd", prec: 40, fixity: Fixity::Right, input: " := ", tex: ":=", mathml: ":=" },
+    Operator { head: "Function", prec: 90, fixity: Fixity::Postfix, input: " &", tex: "\\&", mathml: "&amp;" },
+    Operator { head: "replace_all", prec: 110, fixity: Fixity::Left, input: " /. ", tex: "/.", mathml: "/." },
+    Operator { head: "replace_repeated", prec: 110, fixity: Fixity::Left, input: " //. ", tex: "//.", mathml: "//." },
+    Operator { head: "rule", prec: 120, fixity: Fixity::Right, input: " -> ", tex: "\\to ", mathml: "&#x2192;" },
+    Operator { head: "rule_delayed", prec: 120, fixity: Fixity::Right, input: " :> ", tex: "\\Rightarrow ", mathml: "&#x29F4;" },
+    Operator { head: "Or", prec: 215, fixity: Fixity::Left, input: " || ", tex: "\\lor ", mathml: "&#x2228;" },
+    Operator { head: "And", prec: 220, fixity: Fixity::Left, input: " && ", tex: "\\land ", mathml: "&#x2227;" },
+    Operator { head: "Not", prec: 230, fixity: Fixity::Prefix, input: "!", tex: "\\lnot ", mathml: "&#xAC;" },
+    Operator { head: "sameq", prec: 290, fixity: Fixity::Left, input: " === ", tex: "\\equiv ", mathml: "&#x2261;" },
+    Operator { head: "Equal", prec: 290, fixity: Fixity::Left, input: " == ", tex: "=", mathml: "=" },
+    Operator { head: "Plus", prec: PREC_PLUS, fixity: Fixity::Left, input: " + ", tex: "+", mathml: "+" },
+    Operator { head: "Times", prec: PREC_TIMES, fixity: Fixity::Left, input: "*", tex: " ", mathml: "&#x2062;" },
+    Operator { head: "Power", prec: PREC_POWER, fixity: Fixity::Right, input: "^", tex: "^", mathml: "^" },
+];
+
+/// the operator `ex` is printed with, if any. arities that don't fit (like `(Plus x)`)
+/// fall back to `Plus[x]`
+pub fn operator(ex: &Expr) -> Option<&'static Operator> {
+    let Expr::List(ls) = ex else { return None };
+    let Expr::Sym(h) = &ls[0] else { return None };
+    let op = OPERATORS.iter().find(|op| *h == op.head)?;
+    let arity_ok = match op.fixity {
+        Fixity::Left | Fixity::Right => ls.len() > 2 && (op.head != "Power" || ls.len() == 3),
+        Fixity::Prefix | Fixity::Postfix => ls.len() == 2,
+    };
+    arity_ok.then_some(op)
+}
+
+/// name to show for a symbol. the ones `infix_parser` would misread (like `replace_all`) get their wl name
+pub fn wl_name(s: &str) -> &str {
+    match WL_NAMES.iter().find(|(_, ours)| *ours == s) {
+        Some((wl, _)) => wl,
+        None => s,
+    }
+}
+
+/// how a piece of output gets built. `String` for `InputForm`, `Block` for `OutputForm`,
+/// `TeX` and `MathML` for the document forms
+pub trait Render: Sized {
+    /// typesetting for a reader rather than syntax to parse back, so `(Exp x)` can be `e^x`
+    const MATH: bool = false;
+
+    fn text(s: &str) -> Self;
+    fn hcat(parts: Vec<Self>) -> Self;
+    fn paren(self) -> Self;
+    /// `num` over `den`, each with the precedence it was printed at
+    fn fraction(num: (Self, u16), den: (Self, u16)) -> Self;
+    /// `base` raised to `exp`
+    fn power(base: (Self, u16), exp: (Self, u16)) -> Self;
+    /// between the factors of a product
+    fn times() -> Self;
+    fn string(s: &str) -> Self;
+    fn real(r: f64) -> Self;
+
+    fn number(s: &str) -> Self {
+        Self::text(s)
+    }
+
+    fn symbol(name: &str) -> Self {
+        Self::text(name)
+    }
+
+    /// the name of `f` in `f[x]`
+    fn function(name: &str) -> Self {
+        Self::symbol(name)
+    }
+
+    fn op(op: &Operator) -> Self {
+        Self::text(op.input)
+    }
+
+    fn call(f: Self, args: Vec<Self>) -> Self {
+        Self::hcat(vec![f, Self::text("["), join(args, || Self::text(", ")), Self::text("]")])
+    }
+
+    fn list(items: Vec<Self>) -> Self {
+        Self::hcat(vec![Self::text("{"), join(items, || Self::text(", ")), Self::text("}")])
+    }
+
+    /// a rectangular list of lists. `None` prints it as nested lists
+    fn matrix(_rows: Vec<Vec<Self>>) -> Option<Self> {
+        None
+    }
+
+    fn partial() -> Self {
+        Self::text("\u{2202}")
+    }
+}
+
+fn wrap<R: Render>((r, p): (R, u16), min: u16) -> R {
+    if p < min {
+        r.paren()
+    } else {
+        r
+    }
+}
+
+fn join<R: Render>(parts: Vec<R>, sep: impl Fn() -> R) -> R {
+    let mut out = vec![];
+    for (i, p) in parts.into_iter().enumerate() {
+        if i > 0 {
+            out.push(sep());
+        }
+        out.push(p);
+    }
+    R::hcat(out)
+}
+
+fn args<R: Render>(xs: &[Expr]) -> Vec<R> {
+    xs.iter().map(|x| render::<R>(x).0).collect()
+}
+
+fn int(n: i64) -> Expr {
+    Expr::Int(n.into())
+}
+
+/// `Some(-x)` when `ex` is a negative number
+fn negated_number(ex: &Expr) -> Option<Expr> {
+    match ex {
+        Expr::Int(n) if n.is_negative() => Some(Expr::Int(-n)),
+        Expr::Real(r) if r.into_inner() < 0.0 => Some(Expr::Real(-*r)),
+        _ => None,
+    }
+}
+
+fn times(mut factors: Vec<Expr>) -> Expr {
+    match factors.len() {
+        0 => int(1),
+        1 => factors.remove(0),
+        _ => crate::liste(std::iter::once(sym("Times")).chain(factors).collect()),
+    }
+}
+
+/// the magnitude of a term with a negative leading coefficient: `-x` for `(Times -1 x)` and `2 x` for `(Times -2 x)`.
+/// `None` for everything that doesn't print with a leading minus
+fn negated_term(ex: &Expr) -> Option<Expr> {
+    if let Some(n) = negated_number(ex) {
+        return Some(n);
+    }
+    if head(ex) != sym("Times") || ex.len() < 3 {
+        return None;
+    }
+    let c = negated_number(&ex[1])?;
+    let mut factors = ex[2..].to_vec();
+    if c != int(1) {
+        factors.insert(0, c);
+    }
+    Some(times(factors))
+}
+
+/// splits the factors of a product into a numerator and a denominator.
+/// `(Power b -n)` goes in the denominator as `b^n`
+fn split_fraction(factors: &[Expr]) -> (Vec<Expr>, Vec<Expr>) {
+    let (mut num, mut den) = (vec![], vec![]);
+    for f in factors {
+        match f {
+            Expr::List(_) if head(f) == sym("Power") && f.len() == 3 => match negated_number(&f[2]) {
+                Some(e) if e == int(1) => den.push(f[1].clone()),
+                Some(e) => den.push(crate::liste(vec![sym("Power"), f[1].clone(), e])),
+                None => num.push(f.clone()),
+            },
+            _ if head(f) == sym("Rational") && f.len() == 3 => {
+                if f[1] != int(1) {
+                    num.push(f[1].clone());
+                }
+                den.push(f[2].clone());
+            }
+            _ => num.push(f.clone()),
+        }
+    }
+    (num, den)
+}
+
+/// lays out `ex`, giving back the result and the precedence of its outermost operator
+pub fn render<R: Render>(ex: &Expr) -> (R, u16) {
+    match ex {
+        Expr::Int(n) => (R::number(&n.to_string()), if n.is_negative() { PREC_NEGATE } else { PREC_ATOM }),
+        Expr::Real(r) => (R::real(r.into_inner()), if r.into_inner() < 0.0 { PREC_NEGATE } else { PREC_ATOM }),
+        Expr::Str(s) => (R::string(s), PREC_ATOM),
+        Expr::Sym(s) => (R::symbol(wl_name(s)), PREC_ATOM),
+        Expr::List(ls) if ls.is_empty() => (R::text("()"), PREC_ATOM),
+        Expr::List(ls) => {
+            let h = &ls[0];
+            let xs = &ls[1..];
+            if let Some(special) = render_special::<R>(ex) {
+                return special;
+            }
+            if let Some(op) = operator(ex) {
+                return (render_operator(op, xs), op.prec);
+            }
+            // f[x, y]. `(# &)[1]` and friends need the head in parens
+            let f = match h {
+                Expr::Sym(s) => R::function(wl_name(s)),
+                _ => wrap(render::<R>(h), PREC_ATOM),
+            };
+            (R::call(f, args(xs)), PREC_ATOM)
+        }
+    }
+}
+
+fn render_operator<R: Render>(op: &Operator, xs: &[Expr]) -> R {
+    match op.fixity {
+        Fixity::Prefix => R::hcat(vec![R::op(op), wrap(render(&xs[0]), op.prec + 1)]),
+        Fixity::Postfix => R::hcat(vec![wrap(render(&xs[0]), op.prec + 1), R::op(op)]),
+        Fixity::Left | Fixity::Right => {
+            let last = xs.len() - 1;
+            let parts = xs
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    let loose_ok = match op.fixity {
+                        Fixity::Left => i == 0,
+                        _ => i == last,
+                    };
+                    wrap(render(x), if loose_ok { op.prec } else { op.prec + 1 })
+                })
+                .collect();
+            join(parts, || R::op(op))
+        }
+    }
+}
+
+/// the forms that don't print as plain `a op b op c`
+fn render_special<R: Render>(ex: &Expr) -> Option<(R, u16)> {
+    let h = head(ex);
+    let xs = &ex[1..];
+    let name = match &h {
+        Expr::Sym(s) => s.as_str(),
+        _ => return None,
+    };
+    match (name, xs) {
+        ("List", _) => {
+            if let Some(rows) = matrix_rows(xs) {
+                let rows = rows.iter().map(|r| args(r)).collect();
+                if let Some(m) = R::matrix(rows) {
+                    return Some((m, PREC_ATOM));
+                }
+            }
+            Some((R::list(args(xs)), PREC_ATOM))
+        }
+        ("Slot", [Expr::Int(n)]) if *n == 1.into() => Some((R::text("#"), PREC_ATOM)),
+        ("Slot", [Expr::Int(n)]) if n.is_positive() => Some((R::text(&format!("#{n}")), PREC_ATOM)),
+        ("blank" | "blank_seq" | "blank_null_seq", [] | [Expr::Sym(_)]) => Some((R::text(&blank(ex)?), PREC_ATOM)),
+        ("pattern", [Expr::Sym(x), b]) => {
+            let b = blank(b)?;
+            Some((R::text(&format!("{x}{b}")), PREC_ATOM))
+        }
+        ("Part", [e, is @ ..]) if !is.is_empty() => {
+            let e = wrap(render(e), PREC_ATOM);
+            let is = join(args(is), || R::text(", "));
+            Some((R::hcat(vec![e, R::text("[["), is, R::text("]]")]), PREC_ATOM))
+        }
+        ("CompoundExpression", [.., last]) if xs.len() > 1 && *last == sym("Null") => {
+            let body = crate::liste(std::iter::once(h.clone()).chain(xs[..xs.len() - 1].iter().cloned()).collect());
+            let r = if xs.len() == 2 { wrap(render(&xs[0]), 11) } else { render::<R>(&body).0 };
+            Some((R::hcat(vec![r, R::text(";")]), 10))
+        }
+        ("Plus", [first, rest @ ..]) if !rest.is_empty() => {
+            let mut parts = vec![wrap(render(first), PREC_PLUS)];
+            for x in rest {
+                match negated_term(x) {
+                    Some(pos) => {
+                        parts.push(R::text(" - "));
+                        parts.push(wrap(render(&pos), PREC_PLUS + 1));
+                    }
+                    None => {
+                        parts.push(R::text(" + "));
+                        parts.push(wrap(render(x), PREC_PLUS + 1));
+                    }
+                }
+            }
+            Some((R::hcat(parts), PREC_PLUS))
+        }
+        ("Times", [c, rest @ ..]) if !rest.is_empty() && *c == int(-1) => {
+            let r = wrap(render(&times(rest.to_vec())), PREC_NEGATE);
+            Some((R::hcat(vec![R::text("-"), r]), PREC_NEGATE))
+        }
+        ("Times", _) if xs.len() > 1 => {
+            let (num, den) = split_fraction(xs);
+            if den.is_empty() {
+                let mut out = vec![];
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        out.push(R::times());
+                    }
+                    // `-2*x` is fine, `-2` only reads as a number at the front
+                    out.push(wrap(render(x), if i == 0 { PREC_TIMES } else { PREC_TIMES + 1 }));
+                }
+                return Some((R::hcat(out), PREC_TIMES));
+            }
+            Some((R::fraction(render(&times(num)), render(&times(den))), PREC_TIMES))
+        }
+        ("Power", [b, e]) => {
+            if let Some(pos) = negated_number(e) {
+                let den = if pos == int(1) { b.clone() } else { crate::liste(vec![sym("Power"), b.clone(), pos]) };
+                return Some((R::fraction((R::number("1"), PREC_ATOM), render(&den)), PREC_TIMES));
+            }
+            Some((R::power(render(b), render(e)), PREC_POWER))
+        }
+        ("Rational", [n, d]) => Some((R::fraction(render(n), render(d)), PREC_TIMES)),
+        ("Exp", [x]) if R::MATH => Some((R::power((R::symbol("E"), PREC_ATOM), render(x)), PREC_POWER)),
+        // df/dx, or d/dx (f) when f is more than a symbol
+        ("D", [f, x]) if R::MATH => {
+            let den = R::hcat(vec![R::partial(), render::<R>(x).0]);
+            if matches!(f, Expr::List(_)) {
+                let d = R::fraction((R::partial(), PREC_ATOM), (den, PREC_ATOM));
+                Some((R::hcat(vec![d, wrap(render(f), PREC_ATOM)]), PREC_TIMES))
+            } else {
+                let num = R::hcat(vec![R::partial(), render::<R>(f).0]);
+                Some((R::fraction((num, PREC_ATOM), (den, PREC_ATOM)), PREC_TIMES))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// the rows of `{{a, b}, {c, d}}`, when every row is a list of the same nonzero length
+fn matrix_rows(xs: &[Expr]) -> Option<Vec<&[Expr]>> {
+    let rows: Vec<&[Expr]> = xs
+        .iter()
+        .map(|r| (head(r) == sym("List")).then(|| &r[1..]))
+        .collect::<Option<_>>()?;
+    let n = rows.first()?.len();
+    (n > 0 && rows.iter().all(|r| r.len() == n)).then_some(rows)
+}
+
+/// `_`, `__Int` etc. for blanks with nothing fancy inside
+fn blank(b: &Expr) -> Option<String> {
+    let us = match head(b) {
+        h if h == sym("blank") => "_",
+        h if h == sym("blank_seq") => "__",
+        h if h == sym("blank_null_seq") => "___",
+        _ => return None,
+    };
+    match &b[1..] {
+        [] => Some(us.to_string()),
+        [Expr::Sym(h)] => Some(format!("{us}{}", wl_name(h))),
+        _ => None,
+    }
+}
+
+impl Render for String {
+    fn text(s: &str) -> Self {
+        s.to_string()
+    }
+
+    fn hcat(parts: Vec<Self>) -> Self {
+        parts.concat()
+    }
+
+    fn paren(self) -> Self {
+        format!("({self})")
+    }
+
+    fn fraction(num: (Self, u16), den: (Self, u16)) -> Self {
+        format!("{}/{}", wrap(num, PREC_TIMES), wrap(den, PREC_TIMES + 1))
+    }
+
+    fn power(base: (Self, u16), exp: (Self, u16)) -> Self {
+        format!("{}^{}", wrap(base, PREC_POWER + 1), wrap(exp, PREC_POWER))
+    }
+
+    fn times() -> Self {
+        "*".to_string()
+    }
+
+    fn string(s: &str) -> Self {
+        format!("\"{}\"", crate::escape(s))
+    }
+
+    fn real(r: f64) -> Self {
+        // `3.` so it doesn't read back as the integer 3, and `1.5*^-10` like wl
+        let s = crate::real_literal(r);
+        match s.split_once('e') {
+            Some((m, e)) if m.contains('.') => format!("{m}*^{e}"),
+            Some((m, e)) => format!("{m}.*^{e}"),
+            None => s.strip_suffix('0').filter(|m| m.ends_with('.')).map_or(s.clone(), str::to_string),
+        }
+    }
+}
+
+/// a rectangle of text for `OutputForm`. every row has the same width, `base` is the row
+/// that lines up with the text around it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub rows: Vec<String>,
+    pub base: usize,
+}
+
+impl Block {
+    pub fn width(&self) -> usize {
+        self.rows.first().map_or(0, |r| r.chars().count())
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// `above` empty rows on top and `below` underneath, keeping the width
+    fn pad(mut self, above: usize, below: usize) -> Self {
+        let blank = " ".repeat(self.width());
+        for _ in 0..above {
+            self.rows.insert(0, blank.clone());
+        }
+        for _ in 0..below {
+            self.rows.push(blank.clone());
+        }
+        self.base += above;
+        self
+    }
+
+    fn centered(self, width: usize) -> Self {
+        let left = (width - self.width()) / 2;
+        let right = width - self.width() - left;
+        let rows = self
+            .rows
+            .into_iter()
+            .map(|r| format!("{}{r}{}", " ".repeat(left), " ".repeat(right)))
+            .collect();
+        Block { rows, base: self.base }
+    }
+}
+
+impl std::fmt::Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let rows: Vec<&str> = self.rows.iter().map(|r| r.trim_end()).collect();
+        write!(f, "{}", rows.join("\n"))
+    }
+}
+
+impl Render for Block {
+    fn text(s: &str) -> Self {
+        Block { rows: vec![s.to_string()], base: 0 }
+    }
+
+    fn hcat(parts: Vec<Self>) -> Self {
+        let above = parts.iter().map(|b| b.base).max().unwrap_or(0);
+        let below = parts.iter().map(|b| b.height() - b.base - 1).max().unwrap_or(0);
+        let mut rows = vec![String::new(); above + below + 1];
+        for b in parts {
+            let (a, z) = (above - b.base, below - (b.height() - b.base - 1));
+            for (row, r) in rows.iter_mut().zip(b.pad(a, z).rows) {
+                row.push_str(&r);
+            }
+        }
+        Block { rows, base: above }
+    }
+
+    fn paren(self) -> Self {
+        if self.height() == 1 {
+            return Block::hcat(vec![Block::text("("), self, Block::text(")")]);
+        }
+        // tall parens, one char per row
+        let n = self.height();
+        let side = |top: &str, mid: &str, bot: &str| {
+            let rows = (0..n)
+                .map(|i| match i {
+                    0 => top.to_string(),
+                    i if i == n - 1 => bot.to_string(),
+                    _ => mid.to_string(),
+                })
+                .collect();
+            Block { rows, base: self.base }
+        };
+        let (l, r) = (side("/ ", "| ", "\\ "), side(" \\", " |", " /"));
+        Block::hcat(vec![l, self, r])
+    }
+
+    fn fraction(num: (Self, u16), den: (Self, u16)) -> Self {
+        // the bar already groups both sides
+        let (num, den) = (num.0, den.0);
+        let w = num.width().max(den.width());
+        let base = num.height();
+        let mut rows = num.centered(w).rows;
+        rows.push("-".repeat(w));
+        rows.extend(den.centered(w).rows);
+        Block { rows, base }
+    }
+
+    fn power(base: (Self, u16), exp: (Self, u16)) -> Self {
+        let base = wrap(base, PREC_POWER + 1);
+        let exp = exp.0;
+        // the exponent's bottom row sits just above a one line base, or level with the top of a taller one
+        let overlap = usize::from(base.height() > 1);
+        let shift = exp.height() - overlap;
+        let below = base.height() - overlap;
+        let base_at = shift + base.base;
+        let rows = base.pad(shift, 0).rows.into_iter().zip(exp.pad(0, below).rows).map(|(b, e)| b + &e).collect();
+        Block { rows, base: base_at }
+    }
+
+    fn times() -> Self {
+        Block::text(" ")
+    }
+
+    fn string(s: &str) -> Self {
+        Block::text(s)
+    }
+
+    fn real(r: f64) -> Self {
+        Block::text(&String::real(r))
+    }
+}
+
+/// looks up an operator by head, for the printers that use the table outside of `render_operator`
+fn op_for(head: &str) -> &'static Operator {
+    OPERATORS.iter().find(|op| op.head == head).expect("operator table entry")
+}
+
+/// LaTeX math, without the surrounding `$`s
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TeX(pub String);
+
+fn tex_escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\\' => out.push_str("\\backslash "),
+            '~' => out.push_str("\\sim "),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+impl Render for TeX {
+    const MATH: bool = true;
+
+    fn text(s: &str) -> Self {
+        TeX(tex_escape(s))
+    }
+
+    fn hcat(parts: Vec<Self>) -> Self {
+        TeX(parts.into_iter().map(|p| p.0).collect())
+    }
+
+    fn paren(self) -> Self {
+        TeX(format!("\\left({}\\right)", self.0))
+    }
+
+    fn fraction(num: (Self, u16), den: (Self, u16)) -> Self {
+        TeX(format!("\\frac{{{}}}{{{}}}", num.0 .0, den.0 .0))
+    }
+
+    fn power(base: (Self, u16), exp: (Self, u16)) -> Self {
+        TeX(format!("{}^{{{}}}", wrap(base, PREC_POWER + 1).0, exp.0 .0))
+    }
+
+    fn times() -> Self {
+        TeX(op_for("Times").tex.to_string())
+    }
+
+    fn string(s: &str) -> Self {
+        TeX(format!("\\text{{{}}}", tex_escape(s)))
+    }
+
+    fn real(r: f64) -> Self {
+        TeX(String::real(r))
+    }
+
+    fn symbol(name: &str) -> Self {
+        match name {
+            "Pi" => TeX("\\pi".into()),
+            "E" => TeX("e".into()),
+            "I" => TeX("i".into()),
+            "Infinity" => TeX("\\infty".into()),
+            _ if name.chars().count() == 1 => TeX::text(name),
+            _ => TeX(format!("\\text{{{}}}", tex_escape(name))),
+        }
+    }
+
+    fn function(name: &str) -> Self {
+        match name {
+            "Sin" | "Cos" | "Tan" | "Log" | "Exp" | "Sinh" | "Cosh" | "Tanh" => {
+                TeX(format!("\\{}", name.to_lowercase()))
+            }
+            _ => TeX::symbol(name),
+        }
+    }
+
+    fn op(op: &Operator) -> Self {
+        TeX(op.tex.to_string())
+    }
+
+    fn call(f: Self, args: Vec<Self>) -> Self {
+        let args = join(args, || TeX(",".into()));
+        TeX(format!("{}\\left({}\\right)", f.0, args.0))
+    }
+
+    fn list(items: Vec<Self>) -> Self {
+        let items = join(items, || TeX(",".into()));
+        TeX(format!("\\left\\{{{}\\right\\}}", items.0))
+    }
+
+    fn matrix(rows: Vec<Vec<Self>>) -> Option<Self> {
+        let cols = "c".repeat(rows[0].len());
+        let body: Vec<String> = rows
+            .into_iter()
+            .map(|r| r.into_iter().map(|c| c.0).collect::<Vec<_>>().join(" & "))
+            .collect();
+        Some(TeX(format!(
+            "\\left(\\begin{{array}}{{{cols}}} {} \\end{{array}}\\right)",
+            body.join(" \\\\ ")
+        )))
+    }
+
+    fn partial() -> Self {
+        TeX("\\partial ".into())
+    }
+}
+
+/// presentation MathML, one `<math>` element per expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathML(pub String);
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+impl MathML {
+    fn tag(tag: &str, inner: &str) -> Self {
+        MathML(format!("<{tag}>{inner}</{tag}>"))
+    }
+
+    fn mo(s: &str) -> Self {
+        MathML::tag("mo", s)
+    }
+}
+
+impl Render for MathML {
+    const MATH: bool = true;
+
+    /// punctuation becomes `<mo>`, anything else only shows up here for names and numbers
+    fn text(s: &str) -> Self {
+        let t = s.trim();
+        match t.chars().next() {
+            Some(c) if c.is_ascii_digit() => MathML::tag("mn", &xml_escape(t)),
+            Some(c) if c.is_alphabetic() || c == '$' => MathML::tag("mi", &xml_escape(t)),
+            _ => MathML::mo(&xml_escape(t)),
+        }
+    }
+
+    fn hcat(mut parts: Vec<Self>) -> Self {
+        if parts.len() == 1 {
+            return parts.remove(0);
+        }
+        MathML::tag("mrow", &parts.into_iter().map(|p| p.0).collect::<String>())
+    }
+
+    fn paren(self) -> Self {
+        MathML::hcat(vec![MathML::mo("("), self, MathML::mo(")")])
+    }
+
+    fn fraction(num: (Self, u16), den: (Self, u16)) -> Self {
+        MathML::tag("mfrac", &(num.0 .0 + &den.0 .0))
+    }
+
+    fn power(base: (Self, u16), exp: (Self, u16)) -> Self {
+        MathML::tag("msup", &(wrap(base, PREC_POWER + 1).0 + &exp.0 .0))
+    }
+
+    fn times() -> Self {
+        MathML::mo(op_for("Times").mathml)
+    }
+
+    fn string(s: &str) -> Self {
+        MathML::tag("ms", &xml_escape(s))
+    }
+
+    fn real(r: f64) -> Self {
+        MathML::tag("mn", &String::real(r))
+    }
+
+    fn number(s: &str) -> Self {
+        MathML::tag("mn", s)
+    }
+
+    fn symbol(name: &str) -> Self {
+        match name {
+            "Pi" => MathML::tag("mi", "&#x3C0;"),
+            "E" => MathML::tag("mi", "&#x2147;"),
+            "I" => MathML::tag("mi", "&#x2148;"),
+            "Infinity" => MathML::tag("mi", "&#x221E;"),
+            _ => MathML::tag("mi", &xml_escape(name)),
+        }
+    }
+
+    fn function(name: &str) -> Self {
+        match name {
+            "Sin" | "Cos" | "Tan" | "Log" | "Exp" | "Sinh" | "Cosh" | "Tanh" => {
+                MathML::tag("mi", &name.to_lowercase())
+            }
+            _ => MathML::symbol(name),
+        }
+    }
+
+    fn op(op: &Operator) -> Self {
+        MathML::mo(op.mathml)
+    }
+
+    fn call(f: Self, args: Vec<Self>) -> Self {
+        let args = join(args, || MathML::mo(","));
+        // U+2061 is the invisible "function application" operator
+        MathML::hcat(vec![f, MathML::mo("&#x2061;"), args.paren()])
+    }
+
+    fn list(items: Vec<Self>) -> Self {
+        let items = join(items, || MathML::mo(","));
+        MathML::hcat(vec![MathML::mo("{"), items, MathML::mo("}")])
+    }
+
+    fn matrix(rows: Vec<Vec<Self>>) -> Option<Self> {
+        let rows: String = rows
+            .into_iter()
+            .map(|r| MathML::tag("mtr", &r.into_iter().map(|c| MathML::tag("mtd", &c.0).0).collect::<String>()).0)
+            .collect();
+        Some(MathML::tag("mtable", &rows).paren())
+    }
+
+    fn partial() -> Self {
+        MathML::mo("&#x2202;")
+    }
+}
+
+pub fn tex_form(ex: &Expr) -> String {
+    render::<TeX>(ex).0 .0.trim_end().to_string()
+}
+
+pub fn mathml_form(ex: &Expr) -> String {
+    format!("<math>{}</math>", render::<MathML>(ex).0 .0)
+}
+
+pub fn input_form(ex: &Expr) -> String {
+    render::<String>(ex).0
+}
+
+pub fn output_form(ex: &Expr) -> Block {
+    render::<Block>(ex).0
+}
+
+/// `ex` as text in `form`. a top level `(InputForm e)` etc. overrides `form`
+pub fn format_expr(ex: &Expr, form: Form) -> String {
+    if let Expr::List(ls) = ex {
+        if ls.len() == 2 {
+            if let Expr::Sym(h) = &ls[0] {
+                if let Some(f) = Form::from_name(h) {
+                    return format_expr(&ls[1], f);
+                }
+            }
+        }
+    }
+    match form {
+        Form::Full => ex.to_string(),
+        Form::Input => input_form(ex),
+        Form::Output => output_form(ex).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{infix_parser, parse_expr, Syntax};
+
+    fn sexp(s: &str) -> Expr {
+        parse_expr(s, Syntax::Sexp).unwrap()
+    }
+
+    #[test]
+    fn input_form_round_trips() {
+        for (s, expected) in [
+            ("(Plus a (Times b c))", "a + b*c"),
+            ("(Times (Plus a b) c)", "(a + b)*c"),
+            ("(Plus (Plus a b) c)", "a + b + c"),
+            ("(Plus a (Plus b c))", "a + (b + c)"),
+            ("(Plus a (Times -1 b))", "a - b"),
+            ("(Plus x -2)", "x - 2"),
+            ("(Power (Power a b) c)", "(a^b)^c"),
+            ("(Power a (Power b c))", "a^b^c"),
+            ("(Power -2 2)", "(-2)^2"),
+            ("(Times a (Power b -1))", "a/b"),
+            ("(Times -1 (Power x 2))", "-x^2"),
+            ("(rule (f (pattern x (blank Int))) (List 1 2.))", "f[x_Integer] -> {1, 2.}"),
+            ("(replace_all (g xs) (rule_delayed (pattern y (blank_seq)) y))", "g[xs] /. y__ :> y"),
+            ("(Function (Plus (Slot 1) (Slot 2)))", "# + #2 &"),
+            ("((Function (Slot 1)) 3)", "(# &)[3]"),
+            ("(Or (And a (Not b)) (Equal c d))", "a && !b || c == d"),
+            ("(CompoundExpression (set x 1) (setd (f x) true) Null)", "x = 1; f[x] := True;"),
+            ("(Part xs 1)", "xs[[1]]"),
+        ] {
+            let ex = sexp(s);
+            let printed = input_form(&ex);
+            assert_eq!(printed, expected);
+            assert_eq!(infix_parser::Expr(&printed).unwrap(), ex, "{printed}");
+        }
+        // not every tree reads back the same, but it prints the way you'd write it
+        assert_eq!(input_form(&sexp("(Plus a (Times -2 b) (Times c (Power d -2)))")), "a - 2*b + c/d^2");
+        assert_eq!(input_form(&sexp("(Power x -1)")), "1/x");
+    }
+
+    #[test]
+    fn tex_and_mathml() {
+        let tex = |s: &str| tex_form(&sexp(s));
+        assert_eq!(tex("(Plus (Times 2 x) (Power y 3))"), "2 x + y^{3}");
+        assert_eq!(tex("(Times (Plus a b) (Power c -1))"), "\\frac{a + b}{c}");
+        assert_eq!(tex("(Power (Plus a b) (Rational 1 2))"), "\\left(a + b\\right)^{\\frac{1}{2}}");
+        assert_eq!(tex("(Plus (Sin x) (Times -1 (Exp (Times 2 x))))"), "\\sin\\left(x\\right) - e^{2 x}");
+        assert_eq!(tex("(D (f x) x)"), "\\frac{\\partial }{\\partial x}f\\left(x\\right)");
+        assert_eq!(tex("(D y x)"), "\\frac{\\partial y}{\\partial x}");
+        assert_eq!(
+            tex("(List (List 1 0) (List 0 Pi))"),
+            "\\left(\\begin{array}{cc} 1 & 0 \\\\ 0 & \\pi \\end{array}\\right)"
+        );
+        assert_eq!(tex("(List a (List b))"), "\\left\\{a,\\left\\{b\\right\\}\\right\\}");
+        assert_eq!(tex("(rule my_var \"a b\")"), "\\text{my\\_var}\\to \\text{a b}");
+
+        let mathml = |s: &str| mathml_form(&sexp(s));
+        assert_eq!(
+            mathml("(Times (Plus a 1) (Power x -2))"),
+            "<math><mfrac><mrow><mi>a</mi><mo>+</mo><mn>1</mn></mrow><msup><mi>x</mi><mn>2</mn></msup></mfrac></math>"
+        );
+        assert_eq!(
+            mathml("(Cos (Times 2 x))"),
+            "<math><mrow><mi>cos</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mrow><mn>2</mn><mo>&#x2062;</mo><mi>x</mi></mrow><mo>)</mo></mrow></mrow></math>"
+        );
+        assert_eq!(
+            mathml("(List (List 1) (List 2))"),
+            "<math><mrow><mo>(</mo><mtable><mtr><mtd><mn>1</mn></mtd></mtr><mtr><mtd><mn>2</mn></mtd></mtr></mtable><mo>)</mo></mrow></math>"
+        );
+
+        let mut ctx = Context2::new();
+        for s in ["(TeXForm)", "(MathMLForm)", "(TeXForm a b)"] {
+            assert_eq!(crate::ctx_evalparse(&mut ctx, s), sym("$Failed"));
+        }
+        assert_eq!(crate::ctx_evalparse(&mut ctx, "(TeXForm Pi)"), Expr::Str("\\pi".into()));
+    }
+
+    #[test]
+    fn output_form_2d() {
+        let out = |s: &str| output_form(&sexp(s)).to_string();
+        assert_eq!(out("(Plus (Power x 2) 1)"), " 2\nx  + 1");
+        assert_eq!(out("(Times a (Power (Plus b c) -1))"), "  a\n-----\nb + c");
+        assert_eq!(
+            out("(Plus 1 (Power (Times x (Power y -1)) 2))"),
+            "    / x \\2\n1 + | - |\n    \\ y /"
+        );
+        let b = output_form(&sexp("(Times 2 (Power x -1))"));
+        assert_eq!(b.base, 1);
+        assert_eq!(format_expr(&sexp("(InputForm (Plus a 1))"), Form::Full), "a + 1");
+        assert_eq!(format_expr(&sexp("(Plus a 1)"), Form::Full), "(Plus a 1)");
+    }
+}
@@ -0,0 +1,326 @@
+//! what the REPL knows about names: tab completion, the argument hints shown while typing a
+//! call, and `?sym` / `??sym`.
+//!
+//! builtins live in `internal_functions_apply` and have no definitions to look at, so their
+//! usage is written down in `BUILTINS`. everything else comes from `Context2::vars`.
+//! `BUILTINS` and `ATTRIBUTES` together are every name in System`.
+
+use crate::{attributes_of, format::input_form, glob_match, has_attribute, head, liste, parse_expr, sym, Context2, Expr, Syntax};
+use std::ops::Range;
+
+/// name, the ways to call it (as s-expressions), and a line about what it does
+pub type Builtin = (&'static str, &'static [&'static str], &'static str);
+
+pub const BUILTINS: &[Builtin] = &[
+    ("set", &["(set lhs rhs)"], "evaluates rhs and makes it the value of lhs"),
+    ("setd", &["(setd lhs rhs)"], "makes rhs the value of lhs, evaluated each time lhs is used"),
+    ("clear", &["(clear f)"], "removes the values of f"),
+    ("Hold", &["(Hold x)"], "keeps x from being evaluated"),
+    ("HoldForm", &["(HoldForm x)"], "keeps x from being evaluated, like Hold"),
+    ("HoldComplete", &["(HoldComplete x)"], "keeps x from being evaluated, even Evaluate and Sequence inside it"),
+    ("Unevaluated", &["(f (Unevaluated x))"], "gives f x as it is, and stays if f doesn't use it"),
+    ("Evaluate", &["(Hold (Evaluate x))"], "evaluates x even where it would be held"),
+    ("ReleaseHold", &["(ReleaseHold (Hold x))"], "takes away Hold, HoldForm and HoldComplete, so x gets evaluated"),
+    ("N", &["(N x)"], "x with its numbers made reals, except under NHoldAll, NHoldFirst or NHoldRest"),
+    ("Attributes", &["(Attributes f)"], "the attributes of f, like HoldAll"),
+    ("attrs", &["(attrs f)", "(set (attrs f) (List HoldAll))"], "the attributes of f, setting it replaces them all"),
+    ("SetAttributes", &["(SetAttributes f HoldAll)", "(SetAttributes f (List HoldAll Listable))"], "gives f attributes, keeping the ones it has"),
+    ("ClearAttributes", &["(ClearAttributes f HoldAll)"], "takes attributes away from f"),
+    ("Protect", &["(Protect f g)"], "stops f and g being given values, until Unprotect"),
+    ("Unprotect", &["(Unprotect f g)"], "lets Protected f and g be given values again"),
+    ("clear_cache", &["(clear_cache f)", "(clear_cache)"], "forgets what f (or everything) has memoized"),
+    ("own_values", &["(own_values x)"], "the value x was given with set"),
+    ("down_values", &["(down_values f)"], "the rules for calls to f"),
+    ("sub_values", &["(sub_values f)"], "the rules for calls to calls of f"),
+    ("pattern", &["(pattern x p)"], "matches p and binds what it matched to x"),
+    ("blank", &["(blank)", "(blank h)"], "any one expression, or one with head h"),
+    ("blank_seq", &["(blank_seq)", "(blank_seq h)"], "one or more expressions"),
+    ("blank_null_seq", &["(blank_null_seq)", "(blank_null_seq h)"], "zero or more expressions"),
+    ("Alternatives", &["(Alternatives p1 p2)"], "matches any of the patterns"),
+    ("hold_pattern", &["(hold_pattern p)"], "a pattern that isn't evaluated"),
+    ("rule", &["(rule lhs rhs)"], "rewrites lhs to rhs, rhs evaluated right away"),
+    ("rule_delayed", &["(rule_delayed lhs rhs)"], "rewrites lhs to rhs, rhs evaluated after replacing"),
+    ("matchq", &["(matchq expr pattern)"], "true if expr matches pattern"),
+    ("replace", &["(replace expr rules)"], "applies rules to the whole of expr"),
+    ("replace_all", &["(replace_all expr rules)"], "applies rules to each part of expr"),
+    ("replace_repeated", &["(replace_repeated expr rules)", "(replace_repeated expr rules (rule MaxIterations n))"], "applies rules until expr stops changing"),
+    ("rr", &["(rr expr rules)", "(rr expr rules (rule MaxIterations n))"], "short for replace_repeated"),
+    ("sameq", &["(sameq a b)"], "true if a and b are identical"),
+    ("Equal", &["(Equal a b)"], "true or false for numbers and identical expressions"),
+    ("head", &["(head expr)"], "the head of expr"),
+    ("Function", &["(Function body)", "(Function x body)", "(Function (List x y) body)"], "a pure function, with slots or named parameters"),
+    ("Slot", &["(Slot n)"], "the nth argument of a pure function"),
+    ("CompoundExpression", &["(CompoundExpression a b)"], "evaluates each in turn and gives the last"),
+    ("Plus", &["(Plus a b)"], "sum"),
+    ("Times", &["(Times a b)"], "product"),
+    ("Part", &["(Part expr i)", "(Part expr (List i j))"], "the ith element of expr"),
+    ("Length", &["(Length expr)"], "the number of elements of expr"),
+    ("Map", &["(Map f expr)"], "applies f to each element of expr"),
+    ("NestList", &["(NestList f x n)"], "x, (f x), (f (f x)) ... n times"),
+    ("Table", &["(Table expr n)", "(Table expr (List i imax))", "(Table expr (List i imin imax))"], "a list of expr for each i"),
+    ("Join", &["(Join list1 list2)"], "joins lists with the same head"),
+    ("Get", &["(Get \"file\")", "(Get \"file\" \"infix\")"], "reads and evaluates a file"),
+    ("Needs", &["(Needs \"Name`\")", "(Needs \"Name`\" \"file\")"], "loads a package from $Path, unless it's loaded already"),
+    ("BeginPackage", &["(BeginPackage \"Name`\")", "(BeginPackage \"Name`\" (List \"Other`\"))"], "starts a package, its new symbols are in Name`"),
+    ("EndPackage", &["(EndPackage)"], "ends a package, leaving Name` on $ContextPath"),
+    ("Begin", &["(Begin \"`Private`\")"], "makes new symbols in another context, until End"),
+    ("End", &["(End)"], "goes back to the context before Begin"),
+    ("Context", &["(Context x)", "(Context)"], "the context of the symbol x, or $Context"),
+    ("Export", &["(Export \"file.svg\" matrix)"], "draws a matrix of rgb triples"),
+    ("Timing", &["(Timing expr)"], "evaluates expr and gives the seconds taken and the result"),
+    ("Trace", &["(Trace expr)", "(Trace expr pattern)"], "the forms evaluating expr steps through"),
+    ("TracePrint", &["(TracePrint expr)", "(TracePrint expr pattern)"], "prints the forms as they're evaluated"),
+    ("Stack", &["(Stack)", "(Stack pattern)"], "the heads being evaluated right now"),
+    ("TimeConstrained", &["(TimeConstrained expr secs)", "(TimeConstrained expr secs failexpr)"], "gives up on expr after secs"),
+    ("MemoryConstrained", &["(MemoryConstrained expr bytes)", "(MemoryConstrained expr bytes failexpr)"], "gives up on expr once it allocates bytes"),
+    ("MemoryInUse", &["(MemoryInUse)"], "bytes allocated right now"),
+    ("Save", &["(Save \"file\" f)", "(Save \"file\" f g)"], "appends the definitions of the symbols, and what they use, to file"),
+    ("DumpSave", &["(DumpSave \"file\")", "(DumpSave \"file\" f)"], "writes every definition (or f's) to a binary file that Get reads"),
+    ("Print", &["(Print expr1 expr2)"], "prints the exprs on one line, strings without quotes"),
+    ("In", &["(In n)", "(In -k)"], "the input on line n, or k lines back"),
+    ("Out", &["(Out n)", "(Out -k)", "(Out)"], "the result on line n, or k lines back. % and %% for short"),
+    ("parse", &["(parse \"sexp\")"], "parses a string as an s-expression"),
+    ("ToExpression", &["(ToExpression \"text\")"], "parses and evaluates a string"),
+    ("ToString", &["(ToString expr)", "(ToString expr form)"], "expr as a string, in FullForm or form"),
+    ("Format", &["(Format expr)", "(Format \"source\" width)"], "source or an expression laid out to fit in width columns (80), as a string"),
+    ("TeXForm", &["(TeXForm expr)"], "expr as LaTeX"),
+    ("MathMLForm", &["(MathMLForm expr)"], "expr as MathML"),
+    ("InputForm", &["(InputForm expr)"], "prints expr in infix syntax"),
+    ("StringJoin", &["(StringJoin s1 s2)", "(StringJoin (List s1 s2))"], "concatenates strings"),
+    ("StringLength", &["(StringLength s)"], "the number of characters in s"),
+    ("Characters", &["(Characters s)"], "the characters of s as a list of strings"),
+    ("ToUpperCase", &["(ToUpperCase s)"], "s in upper case"),
+    ("ToLowerCase", &["(ToLowerCase s)"], "s in lower case"),
+    ("StringTake", &["(StringTake s n)", "(StringTake s (List m n))"], "the first n characters, the last -n, or m through n"),
+    ("StringSplit", &["(StringSplit s)", "(StringSplit s sep)"], "splits s at whitespace or sep"),
+    ("StringReplace", &["(StringReplace s rules)"], "replaces the parts of s matching string patterns"),
+    ("StringCases", &["(StringCases s pattern)"], "the parts of s that match"),
+    ("StringPosition", &["(StringPosition s pattern)"], "where pattern matches in s, overlapping ones included"),
+    ("StringMatchQ", &["(StringMatchQ s pattern)"], "true if all of s matches"),
+    ("StringExpression", &["(StringExpression p1 p2)"], "string patterns one after another"),
+    ("RegularExpression", &["(RegularExpression \"regex\")"], "a string pattern written as a regex"),
+    // not builtins of their own, but names the evaluator, `N`, the output forms and string
+    // patterns look for, so they're system names too
+    ("List", &["(List a b)"], "a list, written {a, b} in infix"),
+    ("Sequence", &["(f (Sequence a b))"], "splices a and b into the call it's in"),
+    ("Power", &["(Power x n)"], "x to the n, written x^n in infix"),
+    ("Rational", &["(Rational 3 4)"], "an exact fraction, written 3/4"),
+    ("And", &["(And a b)"], "true if a and b are, written a && b in infix"),
+    ("Or", &["(Or a b)"], "true if a or b is, written a || b in infix"),
+    ("Not", &["(Not a)"], "true if a is false, written !a in infix"),
+    ("D", &["(D f x)"], "the derivative of f with respect to x, as the math forms show it"),
+    ("Exp", &["(Exp x)"], "e to the x"),
+    ("Log", &["(Log x)"], "the natural logarithm of x"),
+    ("Sin", &["(Sin x)"], "the sine of x"),
+    ("Cos", &["(Cos x)"], "the cosine of x"),
+    ("Tan", &["(Tan x)"], "the tangent of x"),
+    ("Sinh", &["(Sinh x)"], "the hyperbolic sine of x"),
+    ("Cosh", &["(Cosh x)"], "the hyperbolic cosine of x"),
+    ("Tanh", &["(Tanh x)"], "the hyperbolic tangent of x"),
+    ("Pi", &["Pi"], "the constant pi, a number under N"),
+    ("E", &["E"], "the base of the natural logarithm, a number under N"),
+    ("I", &["I"], "the imaginary unit"),
+    ("Infinity", &["Infinity"], "no limit, like a $RecursionLimit of Infinity"),
+    ("Null", &["Null"], "nothing, what a call with nothing to give back gives"),
+    ("true", &["true"], "what tests give when they hold"),
+    ("false", &["false"], "what tests give when they don't"),
+    ("Failed", &["Failed"], "what a failed builtin can give back"),
+    ("Int", &["(blank Int)"], "the head of integers"),
+    ("Real", &["(blank Real)"], "the head of reals"),
+    ("Str", &["(blank Str)"], "the head of strings"),
+    ("Sym", &["(blank Sym)"], "the head of symbols"),
+    ("MaxIterations", &["(rule MaxIterations n)"], "the option that caps how many times something repeats"),
+    ("FullForm", &["(set $OutputForm FullForm)"], "results printed as s-expressions"),
+    ("OutputForm", &["(set $OutputForm OutputForm)"], "results printed in infix"),
+    ("DigitCharacter", &["DigitCharacter"], "a string pattern for one digit"),
+    ("LetterCharacter", &["LetterCharacter"], "a string pattern for one letter"),
+    ("WordCharacter", &["WordCharacter"], "a string pattern for one letter or digit"),
+    ("WhitespaceCharacter", &["WhitespaceCharacter"], "a string pattern for one space, tab or newline"),
+    ("Whitespace", &["Whitespace"], "a string pattern for a run of whitespace"),
+    ("StartOfString", &["StartOfString"], "a string pattern matching where the string starts"),
+    ("EndOfString", &["EndOfString"], "a string pattern matching where the string ends"),
+    ("$Aborted", &["$Aborted"], "what an evaluation that was aborted gives"),
+    ("$Failed", &["$Failed"], "what Needs, BeginPackage and Begin give when they fail"),
+    ("$Context", &["$Context"], "the context new symbols are made in"),
+    ("$ContextPath", &["$ContextPath"], "the contexts a short name is looked for in"),
+    ("$Packages", &["$Packages"], "the packages loaded so far"),
+    ("$Path", &["$Path"], "the directories Needs and Get look in"),
+    ("$Line", &["$Line"], "the number of the input being evaluated"),
+    ("$HistoryLength", &["$HistoryLength"], "how many results % and Out keep"),
+    ("$OutputForm", &["$OutputForm"], "the form results are printed in"),
+    ("$Syntax", &["$Syntax"], "\"sexp\" or \"infix\", how input is read"),
+    ("$RecursionLimit", &["$RecursionLimit"], "how deep evaluation can nest"),
+    ("$IterationLimit", &["$IterationLimit"], "how many times a result can be rewritten"),
+];
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|(n, _, _)| *n == name)
+}
+
+/// a usage line in the syntax the REPL is reading
+fn show_usage(usage: &str, syntax: Syntax) -> String {
+    match (syntax, parse_expr(usage, Syntax::Sexp)) {
+        (Syntax::Infix, Ok(ex)) => input_form(&ex),
+        _ => usage.to_string(),
+    }
+}
+
+/// symbols that have some kind of definition
+pub fn defined_names(ctx: &Context2) -> impl Iterator<Item = String> + '_ {
+    ctx.vars.keys().filter_map(|k| match k {
+        Expr::Sym(s) => Some(s.to_string()),
+        _ => None,
+    })
+}
+
+/// every name worth completing: builtins and anything with a definition, sorted
+pub fn symbol_names(ctx: &Context2) -> Vec<String> {
+    let mut names: Vec<String> = BUILTINS.iter().map(|(n, _, _)| n.to_string()).collect();
+    names.extend(defined_names(ctx));
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn is_name_char(c: char, syntax: Syntax) -> bool {
+    match syntax {
+        Syntax::Sexp => c.is_alphanumeric() || matches!(c, '$' | '-' | '_' | '`'),
+        // `x_Int` completes the `Int`
+        Syntax::Infix => c.is_alphanumeric() || matches!(c, '$' | '`'),
+    }
+}
+
+/// the start of the name the cursor is in or right after
+pub fn word_start(line: &str, pos: usize, syntax: Syntax) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_name_char(*c, syntax))
+        .last()
+        .map_or(pos, |(i, _)| i)
+}
+
+/// the byte range of the name the cursor is in or just after
+pub fn word_at(line: &str, pos: usize, syntax: Syntax) -> Range<usize> {
+    let end = line[pos..]
+        .char_indices()
+        .find(|(_, c)| !is_name_char(*c, syntax))
+        .map_or(line.len(), |(i, _)| pos + i);
+    word_start(line, pos, syntax)..end
+}
+
+/// names starting with `prefix`
+pub fn complete<'a>(names: &'a [String], prefix: &str) -> Vec<&'a String> {
+    if prefix.is_empty() {
+        return vec![];
+    }
+    names.iter().filter(|n| n.starts_with(prefix)).collect()
+}
+
+/// the head of the innermost call the cursor is inside, `(f a |` or `f[a, |`
+pub fn enclosing_head(line: &str, syntax: Syntax) -> Option<&str> {
+    let (open, close) = match syntax {
+        Syntax::Sexp => ('(', ')'),
+        Syntax::Infix => ('[', ']'),
+    };
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut opened_at = None;
+    for (i, c) in line.char_indices().rev() {
+        // brackets inside strings don't count. escaped quotes are rare enough to ignore here
+        if c == '"' {
+            in_string = !in_string;
+        }
+        if in_string {
+            continue;
+        }
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            if depth == 0 {
+                opened_at = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let at = opened_at?;
+    let name = match syntax {
+        Syntax::Sexp => {
+            let rest = &line[at + 1..];
+            let end = rest.find(|c| !is_name_char(c, syntax)).unwrap_or(rest.len());
+            &rest[..end]
+        }
+        Syntax::Infix => &line[word_start(line, at, syntax)..at],
+    };
+    (!name.is_empty()).then_some(name)
+}
+
+/// the argument hint for what's being typed, if it's a call to a builtin
+pub fn hint(line: &str, syntax: Syntax) -> Option<String> {
+    let name = enclosing_head(line, syntax)?;
+    let (_, usages, _) = builtin(name)?;
+    let shown: Vec<String> = usages.iter().map(|u| show_usage(u, syntax)).collect();
+    Some(format!("  {}", shown.join(" | ")))
+}
+
+fn show(ex: &Expr, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Sexp => ex.to_string(),
+        Syntax::Infix => input_form(ex),
+    }
+}
+
+/// how many rules `?name` lists before leaving the rest to `??name`
+const SHORT_RULES: usize = 10;
+
+/// what `?name` prints, or `??name` with `full`. `?Str*` lists the names matching
+pub fn describe(ctx: &mut Context2, name: &str, full: bool) -> String {
+    let syntax = Syntax::of_context(ctx);
+    if name.contains('*') {
+        let names: Vec<String> = symbol_names(ctx).into_iter().filter(|n| glob_match(n, name)).collect();
+        return if names.is_empty() { format!("nothing matches {name}") } else { names.join("  ") };
+    }
+    let s = sym(name);
+    let mut out = vec![name.to_string()];
+    if let Some((_, usages, summary)) = builtin(name) {
+        out.extend(usages.iter().map(|u| format!("  {}", show_usage(u, syntax))));
+        out.push(format!("  {summary}"));
+    }
+    if let Some(attrs) = attributes_of(ctx, &s) {
+        out.push(format!("attributes: {}", show(&attrs, syntax)));
+    }
+    // ReadProtected symbols show their attributes and nothing else
+    if let Some(te) = ctx.vars.get(&s).filter(|_| !has_attribute(ctx, &s, "ReadProtected")) {
+        if let Some(own) = &te.own {
+            out.push(format!("value: {}", show(own, syntax)));
+        }
+        let rules = [("down values", &te.down), ("sub values", &te.sub)];
+        for (what, dvs) in rules.into_iter().filter(|(_, dvs)| dvs.len() > 1) {
+            out.push(format!("{what}:"));
+            // stored as (rule_delayed (hold_pattern lhs) rhs), shown the way it was defined
+            let lhss = dvs[1..]
+                .iter()
+                .map(|dv| if head(&dv[1]) == sym("hold_pattern") { dv[1][1].clone() } else { dv[1].clone() });
+            if full {
+                for (lhs, dv) in lhss.zip(&dvs[1..]) {
+                    let def = liste(vec![sym("setd"), lhs, dv[2].clone()]);
+                    out.push(format!("  {}", show(&def, syntax)));
+                }
+            } else {
+                // just what each rule matches, ?? has the rest
+                out.extend(lhss.take(SHORT_RULES).map(|lhs| format!("  {}", show(&lhs, syntax))));
+                let n = dvs.len() - 1;
+                let more = if n > SHORT_RULES { format!("{} more, ", n - SHORT_RULES) } else { String::new() };
+                out.push(format!("  ({more}??{name} shows the definitions)"));
+            }
+        }
+        if !te.memo.is_empty() {
+            out.push(format!("memoized: {} results", te.memo.len()));
+        }
+    }
+    if out.len() == 1 {
+        out.push(format!("  {name} has no definitions"));
+    }
+    out.join("\n")
+}
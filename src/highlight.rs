@@ -0,0 +1,154 @@
+//! coloring the REPL input line as it's typed.
+//!
+//! the tokens come from the `tokens` rule in each grammar, built out of the same `number`,
+//! `string`, `symbol` ... rules the parser uses, so a literal is colored as a literal exactly
+//! when it would parse as one. brackets that don't pair up, and anything after the point where a
+//! balanced line stops parsing, come out red.
+
+use crate::{expr_parser, help, infix_parser, parse_expressions, Syntax};
+use std::collections::HashSet;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Space,
+    Comment,
+    Str,
+    Number,
+    Symbol,
+    /// `x_Int`, `__`, `#2`. the s-expression pattern heads are plain symbols to the tokenizer
+    Pattern,
+    Open,
+    Close,
+    Operator,
+    Invalid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Builtin,
+    Defined,
+    Undefined,
+    Number,
+    Str,
+    Comment,
+    Pattern,
+    Invalid,
+    /// the bracket the cursor is on and its partner
+    Matched,
+}
+
+impl Style {
+    fn ansi(self) -> Option<&'static str> {
+        Some(match self {
+            Style::Plain | Style::Defined => return None,
+            Style::Builtin => "\x1b[1m",
+            Style::Undefined => "\x1b[34m",
+            Style::Number => "\x1b[36m",
+            Style::Str => "\x1b[90m",
+            Style::Comment => "\x1b[2m",
+            Style::Pattern => "\x1b[32m",
+            Style::Invalid => "\x1b[31m",
+            Style::Matched => "\x1b[1;34m",
+        })
+    }
+}
+
+const PATTERN_HEADS: &[&str] = &["pattern", "blank", "blank_seq", "blank_null_seq"];
+
+pub fn tokens(line: &str, syntax: Syntax) -> Vec<(Range<usize>, Token)> {
+    match syntax {
+        Syntax::Sexp => expr_parser::tokens(line),
+        Syntax::Infix => infix_parser::tokens(line),
+    }
+    .unwrap_or_default()
+}
+
+fn pairs_with(open: &str, close: &str) -> bool {
+    matches!((open, close), ("(", ")") | ("[", "]") | ("{", "}"))
+}
+
+pub type Spans = Vec<(Range<usize>, Style)>;
+
+/// a style for each token, and the byte offsets of the brackets that pair up
+pub fn styles(line: &str, syntax: Syntax, defined: &HashSet<String>) -> (Spans, Vec<(usize, usize)>) {
+    let mut out: Spans = vec![];
+    let mut open = vec![];
+    let mut pairs = vec![];
+    for (range, token) in tokens(line, syntax) {
+        let text = &line[range.clone()];
+        let style = match token {
+            Token::Space | Token::Operator => Style::Plain,
+            Token::Comment => Style::Comment,
+            Token::Str => Style::Str,
+            Token::Number => Style::Number,
+            Token::Pattern => Style::Pattern,
+            Token::Invalid => Style::Invalid,
+            Token::Symbol if syntax == Syntax::Sexp && PATTERN_HEADS.contains(&text) => Style::Pattern,
+            Token::Symbol if help::builtin(text).is_some() => Style::Builtin,
+            Token::Symbol if defined.contains(text) => Style::Defined,
+            Token::Symbol => Style::Undefined,
+            Token::Open => {
+                open.push(out.len());
+                Style::Plain
+            }
+            Token::Close => match open.last() {
+                Some(&o) if pairs_with(&line[out[o].0.clone()], text) => {
+                    open.pop();
+                    pairs.push((out[o].0.start, range.start));
+                    Style::Plain
+                }
+                _ => Style::Invalid,
+            },
+        };
+        out.push((range, style));
+    }
+
+    // a balanced line gets the real parser's opinion too, like `f[x,,]`
+    let balanced = open.is_empty() && !out.iter().any(|(_, s)| *s == Style::Invalid);
+    if balanced && !line.trim().is_empty() {
+        if let Err(err) = parse_expressions(line, syntax) {
+            // from the token it stopped at to the end, or just the last token if it ran out of input
+            let at = err.location.offset;
+            let real = |r: &Range<usize>| !line[r.clone()].trim().is_empty();
+            let from = out
+                .iter()
+                .position(|(r, _)| r.end > at && real(r))
+                .or_else(|| out.iter().rposition(|(r, _)| real(r)))
+                .unwrap_or(out.len());
+            for (r, s) in out[from..].iter_mut() {
+                if real(r) {
+                    *s = Style::Invalid;
+                }
+            }
+        }
+    }
+    (out, pairs)
+}
+
+/// `line` with ansi colors, for `Highlighter::highlight`
+pub fn highlight(line: &str, pos: usize, syntax: Syntax, defined: &HashSet<String>) -> String {
+    let (spans, pairs) = styles(line, syntax, defined);
+    // the bracket under the cursor, or the one just typed
+    let matched = pairs
+        .iter()
+        .find(|(a, b)| [*a, *b].iter().any(|&i| i == pos || i + 1 == pos))
+        .copied();
+    let mut out = String::with_capacity(line.len() * 2);
+    for (range, style) in spans {
+        let style = match matched {
+            Some((a, b)) if range.start == a || range.start == b => Style::Matched,
+            _ => style,
+        };
+        match style.ansi() {
+            Some(esc) => {
+                out.push_str(esc);
+                out.push_str(&line[range]);
+                out.push_str("\x1b[0m");
+            }
+            None => out.push_str(&line[range]),
+        }
+    }
+    out
+}
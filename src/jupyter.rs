@@ -0,0 +1,466 @@
+//! a jupyter kernel, so cas3 can run in notebooks. the `cas3-jupyter` binary starts one.
+//!
+//! it speaks the messaging protocol (5.3) over the sockets in `zmtp`: kernel_info, execute,
+//! complete, inspect, is_complete, history, comm_info and shutdown requests. `Print` output and
+//! messages come back as streams, svg files written by `Export` as `image/svg+xml` display data,
+//! and results as plain text along with their TeXForm as `text/latex`.
+
+use crate::zmtp::{self, Publisher, Router};
+use crate::{capture, contexts, format, format_expr, help, parse_expressions, parse_source, sym};
+use crate::{Expr, Form, Kernel, Syntax};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::cell::Cell;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const PROTOCOL_VERSION: &str = "5.3";
+
+/// separates the routing identities from the message
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// what jupyter puts in the connection file it hands the kernel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub ip: String,
+    pub shell_port: u16,
+    pub iopub_port: u16,
+    pub stdin_port: u16,
+    pub control_port: u16,
+    pub hb_port: u16,
+    /// signs every message. empty means unsigned
+    pub key: String,
+}
+
+impl ConnectionInfo {
+    /// every socket on localhost on a port picked when binding
+    pub fn local(key: &str) -> Self {
+        ConnectionInfo {
+            ip: "127.0.0.1".into(),
+            shell_port: 0,
+            iopub_port: 0,
+            stdin_port: 0,
+            control_port: 0,
+            hb_port: 0,
+            key: key.into(),
+        }
+    }
+
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let v: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if v["transport"].as_str().is_some_and(|t| t != "tcp") {
+            return Err(format!("unsupported transport {}, only tcp is", v["transport"]));
+        }
+        let scheme = v["signature_scheme"].as_str().unwrap_or("hmac-sha256");
+        if scheme != "hmac-sha256" {
+            return Err(format!("unsupported signature scheme {scheme}"));
+        }
+        let port = |name: &str| {
+            v[name]
+                .as_u64()
+                .and_then(|p| u16::try_from(p).ok())
+                .ok_or(format!("{name} is missing"))
+        };
+        Ok(ConnectionInfo {
+            ip: v["ip"].as_str().unwrap_or("127.0.0.1").into(),
+            shell_port: port("shell_port")?,
+            iopub_port: port("iopub_port")?,
+            stdin_port: port("stdin_port")?,
+            control_port: port("control_port")?,
+            hb_port: port("hb_port")?,
+            key: v["key"].as_str().unwrap_or("").into(),
+        })
+    }
+
+    pub fn addr(&self, port: u16) -> String {
+        format!("{}:{port}", self.ip)
+    }
+}
+
+/// the kernelspec `--install` writes, for a kernel run as `exe`
+pub fn kernel_spec(exe: &Path) -> Value {
+    json!({
+        "argv": [exe.display().to_string(), "{connection_file}"],
+        "display_name": "cas3",
+        "language": "cas3",
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// where a reply goes back to, or the topic of an iopub message
+    pub identities: Vec<Vec<u8>>,
+    pub header: Value,
+    pub parent_header: Value,
+    pub metadata: Value,
+    pub content: Value,
+}
+
+impl Message {
+    pub fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or("")
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &[u8]) -> Option<Vec<u8>> {
+    let s = std::str::from_utf8(s).ok()?;
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// the current time in ISO 8601, for message headers
+fn iso_now() -> String {
+    let t = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, secs) = ((t.as_secs() / 86400) as i64, t.as_secs() % 86400);
+    // days since 1970 to a date, from howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let (era, doe) = (z.div_euclid(146097), z.rem_euclid(146097));
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    format!("{year:04}-{month:02}-{day:02}T{h:02}:{m:02}:{s:02}.{:06}Z", t.subsec_micros())
+}
+
+/// one end of a conversation: signs what it sends and checks what it gets. the kernel has one,
+/// and so does a client
+pub struct Session {
+    key: Vec<u8>,
+    id: String,
+    sent: Cell<u64>,
+}
+
+impl Session {
+    pub fn new(key: &str) -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        Session {
+            key: key.as_bytes().to_vec(),
+            id: format!("{:x}-{nanos:x}", std::process::id()),
+            sent: Cell::new(0),
+        }
+    }
+
+    /// a new message, in reply to `parent` if there is one
+    pub fn message(&self, parent: Option<&Message>, msg_type: &str, content: Value) -> Message {
+        self.sent.set(self.sent.get() + 1);
+        Message {
+            identities: parent.map(|p| p.identities.clone()).unwrap_or_default(),
+            header: json!({
+                "msg_id": format!("{}-{}", self.id, self.sent.get()),
+                "session": self.id,
+                "username": "cas3",
+                "date": iso_now(),
+                "msg_type": msg_type,
+                "version": PROTOCOL_VERSION,
+            }),
+            parent_header: parent.map_or(json!({}), |p| p.header.clone()),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    fn mac(&self, parts: &[Vec<u8>]) -> Option<Hmac<Sha256>> {
+        if self.key.is_empty() {
+            return None;
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        parts.iter().for_each(|p| mac.update(p));
+        Some(mac)
+    }
+
+    pub fn encode(&self, msg: &Message) -> zmtp::Message {
+        let parts: Vec<Vec<u8>> = [&msg.header, &msg.parent_header, &msg.metadata, &msg.content]
+            .iter()
+            .map(|v| v.to_string().into_bytes())
+            .collect();
+        let signature = self.mac(&parts).map_or(String::new(), |mac| hex(&mac.finalize().into_bytes()));
+        let mut frames = msg.identities.clone();
+        frames.push(DELIMITER.to_vec());
+        frames.push(signature.into_bytes());
+        frames.extend(parts);
+        frames
+    }
+
+    pub fn decode(&self, frames: &[Vec<u8>]) -> Result<Message, String> {
+        let split = frames.iter().position(|f| f == DELIMITER).ok_or("no <IDS|MSG> delimiter")?;
+        let parts = frames.get(split + 2..split + 6).ok_or("too few frames")?;
+        if let Some(mac) = self.mac(parts) {
+            let signature = unhex(&frames[split + 1]).ok_or("a signature that isn't hex")?;
+            mac.verify_slice(&signature).map_err(|_| "a bad signature")?;
+        }
+        let json = |p: &Vec<u8>| serde_json::from_slice::<Value>(p).map_err(|e| e.to_string());
+        Ok(Message {
+            identities: frames[..split].to_vec(),
+            header: json(&parts[0])?,
+            parent_header: json(&parts[1])?,
+            metadata: json(&parts[2])?,
+            content: json(&parts[3])?,
+        })
+    }
+}
+
+/// a code point offset from the protocol as a byte offset into `s`
+fn byte_offset(s: &str, chars: usize) -> usize {
+    s.char_indices().nth(chars).map_or(s.len(), |(i, _)| i)
+}
+
+/// what requests do, without the sockets. cells run in `kernel`, under its `KernelSettings`
+pub struct Handler {
+    pub kernel: Kernel,
+    execution_count: u64,
+}
+
+impl Handler {
+    pub fn new(kernel: Kernel) -> Self {
+        Handler { kernel, execution_count: 0 }
+    }
+
+    /// the reply content for a shell or control request, or None for ones we don't handle.
+    /// anything for iopub along the way goes to `publish` as `(msg_type, content)`
+    pub fn handle(&mut self, msg_type: &str, content: &Value, publish: &mut dyn FnMut(&str, Value)) -> Option<Value> {
+        let code = content["code"].as_str().unwrap_or("");
+        let cursor = content["cursor_pos"].as_u64().map_or(code.len(), |c| byte_offset(code, c as usize));
+        Some(match msg_type {
+            "kernel_info_request" => self.kernel_info(),
+            "execute_request" => self.execute(content, publish),
+            "complete_request" => self.complete(code, cursor),
+            "inspect_request" => self.inspect(code, cursor, content["detail_level"].as_u64().unwrap_or(0) > 0),
+            "is_complete_request" => self.is_complete(code),
+            "history_request" => json!({"status": "ok", "history": []}),
+            "comm_info_request" => json!({"status": "ok", "comms": {}}),
+            // ctrl-c in the notebook sends SIGINT, which aborts whatever's running
+            "interrupt_request" => json!({"status": "ok"}),
+            "shutdown_request" => json!({"status": "ok", "restart": content["restart"].as_bool().unwrap_or(false)}),
+            _ => return None,
+        })
+    }
+
+    fn kernel_info(&self) -> Value {
+        let (extension, mode) = match Syntax::of_context(&self.kernel.ctx) {
+            Syntax::Sexp => (".sexp", "commonlisp"),
+            Syntax::Infix => (".wl", "mathematica"),
+        };
+        json!({
+            "status": "ok",
+            "protocol_version": PROTOCOL_VERSION,
+            "implementation": "cas3",
+            "implementation_version": env!("CARGO_PKG_VERSION"),
+            "language_info": {
+                "name": "cas3",
+                "version": env!("CARGO_PKG_VERSION"),
+                "mimetype": "text/x-cas3",
+                "file_extension": extension,
+                "codemirror_mode": mode,
+            },
+            "banner": "cas3",
+            "help_links": [],
+        })
+    }
+
+    /// a result as a mime bundle, plain text and TeX
+    fn bundle(&self, ex: &Expr) -> Value {
+        let mut data = json!({"text/plain": format_expr(ex, Form::of_context(&self.kernel.ctx))});
+        if !matches!(ex, Expr::Str(_)) {
+            data["text/latex"] = json!(format!("${}$", format::tex_form(ex)));
+        }
+        data
+    }
+
+    fn execute(&mut self, content: &Value, publish: &mut dyn FnMut(&str, Value)) -> Value {
+        let code = content["code"].as_str().unwrap_or("");
+        let silent = content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+        }
+        let n = self.execution_count;
+        if !silent {
+            publish("execute_input", json!({"code": code, "execution_count": n}));
+        }
+
+        // a cell that doesn't parse doesn't run at all
+        let (exprs, errors) = parse_source(&format!("In[{n}]"), code, Syntax::of_context(&self.kernel.ctx));
+        if !errors.is_empty() {
+            let traceback: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            let error = json!({"ename": "SyntaxError", "evalue": traceback[0], "traceback": traceback});
+            publish("error", error.clone());
+            let mut reply = json!({"status": "error", "execution_count": n});
+            reply.as_object_mut().unwrap().extend(error.as_object().unwrap().clone());
+            return reply;
+        }
+
+        let kernel = &mut self.kernel;
+        let (results, output) = capture(|| {
+            exprs
+                .iter()
+                .map(|ex| {
+                    let ex = contexts::resolve(&mut kernel.ctx, ex);
+                    let line = kernel.ctx.line();
+                    let res = kernel.evaluate_resolved(&ex);
+                    kernel.ctx.record_history(&line, &ex, &res);
+                    contexts::shorten(&kernel.ctx, &res)
+                })
+                .collect::<Vec<Expr>>()
+        });
+        for (name, text) in [("stdout", output.stdout), ("stderr", output.stderr)] {
+            if !text.is_empty() {
+                publish("stream", json!({"name": name, "text": text}));
+            }
+        }
+        for file in output.exported {
+            if let Ok(svg) = std::fs::read_to_string(&file) {
+                publish("display_data", json!({"data": {"image/svg+xml": svg}, "metadata": {}}));
+            }
+        }
+        if !silent {
+            // every result shows, like the REPL, the last as the cell's result
+            for (i, res) in results.iter().enumerate().filter(|(_, r)| **r != sym("Null")) {
+                let data = self.bundle(res);
+                if i + 1 == results.len() {
+                    publish("execute_result", json!({"execution_count": n, "data": data, "metadata": {}}));
+                } else {
+                    publish("display_data", json!({"data": data, "metadata": {}}));
+                }
+            }
+        }
+        json!({"status": "ok", "execution_count": n, "user_expressions": {}, "payload": []})
+    }
+
+    /// `cursor` is a byte offset
+    pub fn complete(&self, code: &str, cursor: usize) -> Value {
+        let start = help::word_start(code, cursor, Syntax::of_context(&self.kernel.ctx));
+        let names = help::symbol_names(&self.kernel.ctx);
+        let matches = help::complete(&names, &code[start..cursor]);
+        json!({
+            "status": "ok",
+            "matches": matches,
+            "cursor_start": code[..start].chars().count(),
+            "cursor_end": code[..cursor].chars().count(),
+            "metadata": {},
+        })
+    }
+
+    /// the help for the name under the cursor, or for the call it's in. `full` shows definitions
+    pub fn inspect(&mut self, code: &str, cursor: usize, full: bool) -> Value {
+        let syntax = Syntax::of_context(&self.kernel.ctx);
+        let word = help::word_at(code, cursor, syntax);
+        let name = match &code[word] {
+            "" => help::enclosing_head(&code[..cursor], syntax).unwrap_or(""),
+            w => w,
+        };
+        let found = !name.is_empty() && (help::builtin(name).is_some() || self.kernel.ctx.vars.contains_key(&sym(name)));
+        if !found {
+            return json!({"status": "ok", "found": false, "data": {}, "metadata": {}});
+        }
+        let text = help::describe(&mut self.kernel.ctx, name, full);
+        json!({"status": "ok", "found": true, "data": {"text/plain": text}, "metadata": {}})
+    }
+
+    /// whether the notebook console should run `code` or wait for more lines
+    pub fn is_complete(&self, code: &str) -> Value {
+        match parse_expressions(code, Syntax::of_context(&self.kernel.ctx)) {
+            Ok(_) => json!({"status": "complete"}),
+            // ran out of input, like an unclosed bracket
+            Err(err) if err.location.offset >= code.trim_end().len() => json!({"status": "incomplete", "indent": ""}),
+            Err(_) => json!({"status": "invalid"}),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Shell,
+    Control,
+}
+
+/// the kernel's sockets
+pub struct Server {
+    /// with the ports actually bound
+    pub info: ConnectionInfo,
+    session: Session,
+    shell: Router,
+    control: Router,
+    iopub: Publisher,
+    incoming: Receiver<(Channel, zmtp::Message)>,
+    // nothing asks for input, but clients expect to connect to it
+    _stdin: Router,
+}
+
+impl Server {
+    /// binds everything in `info`. a port of 0 picks a free one
+    pub fn bind(info: ConnectionInfo) -> io::Result<Self> {
+        let (tx, incoming) = channel();
+        let shell = Router::bind(&info.addr(info.shell_port), Channel::Shell, tx.clone())?;
+        let control = Router::bind(&info.addr(info.control_port), Channel::Control, tx)?;
+        let iopub = Publisher::bind(&info.addr(info.iopub_port))?;
+        let (stdin_tx, _) = channel();
+        let stdin = Router::bind(&info.addr(info.stdin_port), (), stdin_tx)?;
+
+        // the heartbeat just echoes
+        let (hb_tx, pings) = channel();
+        let hb = Router::bind(&info.addr(info.hb_port), (), hb_tx)?;
+        let hb_port = hb.port;
+        thread::spawn(move || pings.iter().for_each(|(_, ping)| hb.send(&ping)));
+
+        let info = ConnectionInfo {
+            shell_port: shell.port,
+            iopub_port: iopub.port,
+            stdin_port: stdin.port,
+            control_port: control.port,
+            hb_port,
+            ..info
+        };
+        let session = Session::new(&info.key);
+        Ok(Server { info, session, shell, control, iopub, incoming, _stdin: stdin })
+    }
+
+    fn publish(&self, parent: &Message, msg_type: &str, content: Value) {
+        let mut msg = self.session.message(Some(parent), msg_type, content);
+        msg.identities = vec![msg_type.as_bytes().to_vec()];
+        self.iopub.send(&self.session.encode(&msg));
+    }
+
+    /// answers requests until a shutdown_request
+    pub fn run(&self, handler: &mut Handler) {
+        for (channel, frames) in self.incoming.iter() {
+            let request = match self.session.decode(&frames) {
+                Ok(request) => request,
+                Err(err) => {
+                    eprintln!("cas3-jupyter: dropping a message: {err}");
+                    continue;
+                }
+            };
+            let msg_type = request.msg_type().to_string();
+            self.publish(&request, "status", json!({"execution_state": "busy"}));
+            let content = handler.handle(&msg_type, &request.content, &mut |t, c| self.publish(&request, t, c));
+            match content {
+                Some(content) => {
+                    let reply_type = msg_type.replace("_request", "_reply");
+                    let reply = self.session.message(Some(&request), &reply_type, content);
+                    let router = match channel {
+                        Channel::Shell => &self.shell,
+                        Channel::Control => &self.control,
+                    };
+                    router.send(&self.session.encode(&reply));
+                }
+                None => eprintln!("cas3-jupyter: ignoring a {msg_type}"),
+            }
+            self.publish(&request, "status", json!({"execution_state": "idle"}));
+            if msg_type == "shutdown_request" {
+                return;
+            }
+        }
+    }
+}
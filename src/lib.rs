@@ -0,0 +1,5714 @@
+extern crate cairo;
+extern crate peg;
+use std::{
+    borrow::Cow::{self, Borrowed, Owned},
+    ops::{Add, Mul},
+};
+
+use ordered_float::{self, NotNan};
+// use rug::Integer;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{cast::ToPrimitive, Pow, Signed, Zero};
+
+use cairo::{Context, SvgSurface};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::{Hint, Hinter},
+    validate::MatchingBracketValidator,
+    Helper, Result, Validator,
+};
+
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+static MESSAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// prints a message about something that went wrong (to stderr, so it stays out of piped
+/// results) and counts it, so a script run can fail if anything complained
+macro_rules! message {
+    ($($arg:tt)*) => {{
+        $crate::MESSAGES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        $crate::print_err(&format!($($arg)*));
+    }};
+}
+
+/// how many messages have been printed so far
+pub fn message_count() -> usize {
+    MESSAGES.load(Ordering::Relaxed)
+}
+
+/// what `Print`, messages and `Export` produced while `capture` was running
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    /// svg files written
+    pub exported: Vec<PathBuf>,
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Output>> = const { RefCell::new(None) };
+}
+
+/// runs `f` with its printing collected instead of going to the terminal, for front ends that
+/// show it themselves like the jupyter kernel
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Output) {
+    let outer = CAPTURED.with(|c| c.replace(Some(Output::default())));
+    let res = f();
+    let out = CAPTURED.with(|c| c.replace(outer)).unwrap_or_default();
+    (res, out)
+}
+
+fn captured(f: impl FnOnce(&mut Output)) -> bool {
+    CAPTURED.with(|c| c.borrow_mut().as_mut().map(f).is_some())
+}
+
+pub(crate) fn print_out(line: &str) {
+    if !captured(|o| o.stdout.push_str(&format!("{line}\n"))) {
+        println!("{line}");
+    }
+}
+
+pub(crate) fn print_err(line: &str) {
+    if !captured(|o| o.stderr.push_str(&format!("{line}\n"))) {
+        eprintln!("{line}");
+    }
+}
+
+pub mod cli;
+pub mod contexts;
+pub mod format;
+pub mod help;
+pub mod highlight;
+pub mod jupyter;
+pub mod lsp;
+pub mod pretty;
+pub mod save;
+pub mod string_patterns;
+pub mod zmtp;
+pub use format::{format_expr, Form};
+use highlight::Token;
+use string_patterns::StringPattern;
+
+peg::parser! {
+    grammar expr_parser() for str {
+        rule comment()
+            = "(*" (!"*)" [_])* "*)"
+
+        rule whitespace() = quiet!{([' ' | '\t' | '\n' | '\r'] / comment())*} // Allow whitespace or comments
+
+        // 12, -1.5, .5, 1.5e-10, 1*^10, 16^^FF, 3/4. `parse_number` does the rest
+        rule number() -> Expr
+            = n:$("-"? (['0'..='9']+ "^^" ['0'..='9' | 'a'..='z' | 'A'..='Z']+
+                        / ['0'..='9']+ "/" ['0'..='9']+
+                        / (['0'..='9']+ ("." ['0'..='9']*)? / "." ['0'..='9']+) (['e' | 'E'] ['+' | '-']? ['0'..='9']+ / "*^" "-"? ['0'..='9']+)?))
+              !['a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '^']
+              {? parse_number(n) }
+
+        rule symbol() -> Expr
+            = s:$("`"? symbol_part() ("`" symbol_part())*) { Expr::Sym(s.into()) }
+
+        // p`q`x is x in the context p`q`, `x is x in $Context
+        rule symbol_part() = ['a'..='z' | 'A'..='Z' | '?' | '$'] ['a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' ]*
+
+        rule string() -> Expr
+            = quiet!{"\""} s:$(quiet!{("\\" [_] / !['"' | '\\'] [_])*}) (quiet!{"\""} / expected!("a closing quote")) {? unescape(s).map(Expr::Str) }
+
+        rule out_ref() -> Expr
+            = r:$("%" ['0'..='9']+ / "%"+) {? out_ref(r) }
+
+        rule atom() -> Expr
+            = quiet!{number() / symbol() / out_ref()} / string() / expected!("an atom")
+
+        // a number keeps how it was written, so (f 16^^FF) prints back as written
+        rule item() -> (Expr, Option<&'input str>)
+            = whitespace() t:$(number()) whitespace() {? parse_number(t).map(|n| (n, Some(t))) }
+            / e:Expr() { (e, None) }
+
+        rule list() -> Expr
+            = s:position!() "(" l:item() ** whitespace() ")" e:position!() { spanned(spelled_list(l), s, e) }
+
+        pub rule Expr() -> Expr
+            = whitespace() e:(atom() / list()) whitespace() { e }
+
+        pub rule expressions() -> Vec<Expr>
+            = whitespace() e:Expr() ** whitespace() { e }
+
+        // a line starting with something other than whitespace or a closing bracket
+        rule recovery_point() = "\n" &[^ '\n' | '\r' | ' ' | '\t' | ')' | ']' | '}']
+
+        rule form() -> TopLevelForm
+            = e:Expr() { Ok(e) }
+            / s:position!() [_] (!recovery_point() [_])* e:position!() { Err(s..e) }
+
+        /// never fails. the top level forms that didn't parse come back as the source range they cover
+        pub rule recovering() -> Vec<TopLevelForm>
+            = whitespace() fs:form()* { fs }
+
+        rule token() -> Token
+            = comment() { Token::Comment }
+            / "(*" [_]* { Token::Invalid }
+            / [' ' | '\t' | '\n' | '\r']+ { Token::Space }
+            / string() { Token::Str }
+            / "\"" (!"\"" [_])* "\""? { Token::Invalid }
+            / number() { Token::Number }
+            / symbol() { Token::Symbol }
+            / out_ref() { Token::Symbol }
+            / "(" { Token::Open }
+            / ")" { Token::Close }
+            / [_] { Token::Invalid }
+
+        /// never fails, for the highlighter. anything the rules above don't accept is `Invalid`
+        pub rule tokens() -> Vec<(std::ops::Range<usize>, Token)>
+            = ts:(s:position!() t:token() e:position!() { (s..e, t) })* { ts }
+    }
+}
+
+fn parse(s: &str) -> Expr {
+    expr_parser::Expr(s).unwrap()
+}
+
+/// the text of a number literal: `12`, `-1.5`, `1.5e-10`, `1*^10`, `16^^FF` or `3/4`.
+/// `*^` on an integer stays exact, like `2*^-3` is 1/500. the number itself is just the value,
+/// `16^^FF` is 255. the list a literal was read in keeps how it was written, see `List::spelling`
+pub fn parse_number(s: &str) -> std::result::Result<Expr, &'static str> {
+    let (neg, body) = match s.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, s),
+    };
+    let sign = |n: BigInt| if neg { -n } else { n };
+    if let Some((base, digits)) = body.split_once("^^") {
+        let base = base.parse().ok().filter(|b| (2..=36).contains(b)).ok_or("a base from 2 to 36")?;
+        let n = BigInt::parse_bytes(digits.as_bytes(), base).ok_or("digits less than the base")?;
+        return Ok(Expr::Int(sign(n)));
+    }
+    if let Some((n, d)) = body.split_once('/') {
+        let d: BigInt = d.parse().or(Err("integer"))?;
+        if d.is_zero() {
+            return Err("a nonzero denominator");
+        }
+        return Ok(rational(sign(n.parse().or(Err("integer"))?), d));
+    }
+    let (mantissa, exp) = match body.split_once("*^") {
+        Some((m, e)) => (m, e.parse::<i32>().or(Err("a smaller exponent"))?),
+        None => (body, 0),
+    };
+    if !mantissa.contains(['.', 'e', 'E']) {
+        let n = sign(mantissa.parse().or(Err("integer"))?);
+        let scale = BigInt::from(10).pow(exp.unsigned_abs());
+        return Ok(if exp < 0 { rational(n, scale) } else { Expr::Int(n * scale) });
+    }
+    let r: f64 = if exp == 0 { mantissa.parse() } else { format!("{mantissa}e{exp}").parse() }.or(Err("real"))?;
+    let r = NotNan::new(if neg { -r } else { r }).ok().filter(|r| r.is_finite()).ok_or("a finite real")?;
+    Ok(Expr::Real(r))
+}
+
+/// n/d in lowest terms as `(Rational n d)`, or just the integer when d divides n. d can't be 0
+pub fn rational(n: BigInt, d: BigInt) -> Expr {
+    let g = n.gcd(&d);
+    let (mut n, mut d) = (n / &g, d / &g);
+    if d.is_negative() {
+        n = -n;
+        d = -d;
+    }
+    if d == BigInt::from(1) {
+        Expr::Int(n)
+    } else {
+        liste(vec![sym("Rational"), Expr::Int(n), Expr::Int(d)])
+    }
+}
+
+/// `ex` with its exact numbers, Pi and E made reals, for `N`. arguments a head holds with
+/// NHoldAll, NHoldFirst or NHoldRest stay as they are
+fn numeric(ctx: &Context2, ex: &Expr) -> Expr {
+    let real = |r: f64| NotNan::new(r).map(Expr::Real).ok();
+    let n = match ex {
+        Expr::Sym(s) if s.as_str() == "Pi" => real(std::f64::consts::PI),
+        Expr::Sym(s) if s.as_str() == "E" => real(std::f64::consts::E),
+        Expr::List(ls) if ls.is_empty() => None,
+        Expr::List(_) if exact_number(ex).is_some() => {
+            let (n, d) = exact_number(ex).unwrap();
+            n.to_f64().zip(d.to_f64()).and_then(|(n, d)| real(n / d))
+        }
+        Expr::List(ls) => {
+            let attrs = ctx.vars.get(&ls[0]).map_or(&[][..], |te| &te.attrs[..]);
+            let held = |i: usize| {
+                attrs.contains(&sym("NHoldAll"))
+                    || (i == 1 && attrs.contains(&sym("NHoldFirst")))
+                    || (i > 1 && attrs.contains(&sym("NHoldRest")))
+            };
+            let items = ls.iter().enumerate().map(|(i, e)| if i == 0 || held(i) { e.clone() } else { numeric(ctx, e) });
+            Some(liste(items.collect()))
+        }
+        Expr::Int(i) => i.to_f64().and_then(real),
+        _ => None,
+    };
+    n.unwrap_or_else(|| ex.clone())
+}
+
+/// an integer or a `(Rational n d)` as numerator and denominator
+fn exact_number(e: &Expr) -> Option<(BigInt, BigInt)> {
+    match e {
+        Expr::Int(n) => Some((n.clone(), BigInt::from(1))),
+        Expr::List(ls) if ls.len() == 3 && ls[0] == sym("Rational") => match (&ls[1], &ls[2]) {
+            (Expr::Int(n), Expr::Int(d)) if !d.is_zero() => Some((n.clone(), d.clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// a real the way both parsers read it back: `3.0`, `0.1`, `1e-10`
+pub fn real_literal(r: f64) -> String {
+    format!("{r:?}")
+}
+
+/// the body of a string literal, `\"`, `\\`, `\n`, `\t`, `\r` and `\u{hex}` escapes and all
+pub fn unescape(s: &str) -> std::result::Result<String, &'static str> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('u') => {
+                let rest = chars.as_str();
+                let hex = rest
+                    .strip_prefix('{')
+                    .and_then(|r| r.split_once('}'))
+                    .map(|(hex, _)| hex)
+                    .ok_or("\\u{hex}")?;
+                let c = u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or("a unicode scalar value")?;
+                out.push(c);
+                chars = rest[hex.len() + 2..].chars();
+            }
+            _ => return Err("one of \\\" \\\\ \\n \\t \\r \\u{hex}"),
+        }
+    }
+    Ok(out)
+}
+
+/// inverse of `unescape`, so printed strings read back the same
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// wl names accepted by the infix parser for things cas3 spells differently.
+/// (`_` is pattern syntax in infix input, so `replace_all` can't be written there)
+pub const WL_NAMES: &[(&str, &str)] = &[
+    ("True", "true"),
+    ("False", "false"),
+    ("Integer", "Int"),
+    ("Symbol", "Sym"),
+    ("String", "Str"),
+    ("Rule", "rule"),
+    ("RuleDelayed", "rule_delayed"),
+    ("Set", "set"),
+    ("SetDelayed", "setd"),
+    ("Pattern", "pattern"),
+    ("Blank", "blank"),
+    ("BlankSequence", "blank_seq"),
+    ("BlankNullSequence", "blank_null_seq"),
+    ("HoldPattern", "hold_pattern"),
+    ("Replace", "replace"),
+    ("ReplaceAll", "replace_all"),
+    ("ReplaceRepeated", "replace_repeated"),
+    ("MatchQ", "matchq"),
+    ("SameQ", "sameq"),
+    ("Head", "head"),
+    ("Clear", "clear"),
+    ("OwnValues", "own_values"),
+    ("DownValues", "down_values"),
+    ("SubValues", "sub_values"),
+];
+
+fn from_wl_name(s: &str) -> Expr {
+    match WL_NAMES.iter().find(|(wl, _)| *wl == s) {
+        Some((_, ours)) => sym(ours),
+        None => sym(s),
+    }
+}
+
+fn call(h: &str, args: Vec<Expr>) -> Expr {
+    liste(std::iter::once(sym(h)).chain(args).collect())
+}
+
+/// -x, folding the sign into number literals like wl does
+fn negate(x: Expr) -> Expr {
+    match x {
+        Expr::Int(n) => Expr::Int(-n),
+        Expr::Real(r) => Expr::Real(-r),
+        x => call("Times", vec![Expr::Int((-1).into()), x]),
+    }
+}
+
+/// `%` is (Out), `%%%` is (Out -3) and `%5` is (Out 5)
+fn out_ref(r: &str) -> std::result::Result<Expr, &'static str> {
+    let n = &r[1..];
+    if n.starts_with('%') || n.is_empty() {
+        let back = r.len() as i64;
+        return Ok(call("Out", if back == 1 { vec![] } else { vec![Expr::Int((-back).into())] }));
+    }
+    Ok(call("Out", vec![Expr::Int(n.parse().or(Err("a line number"))?)]))
+}
+
+/// x / y, where dividing one integer literal by another gives the exact rational
+fn divide(x: Expr, y: Expr) -> Expr {
+    match (&x, &y) {
+        (Expr::Int(n), Expr::Int(d)) if !d.is_zero() => rational(n.clone(), d.clone()),
+        _ => call("Times", vec![x, call("Power", vec![y, Expr::Int((-1).into())])]),
+    }
+}
+
+/// `a; b; c` is one (CompoundExpression a b c), not nested ones
+fn compound(x: Expr, y: Expr) -> Expr {
+    match x {
+        Expr::List(mut ls) if ls[0] == sym("CompoundExpression") => {
+            ls.push(y);
+            Expr::List(ls)
+        }
+        x => call("CompoundExpression", vec![x, y]),
+    }
+}
+
+enum Suffix {
+    Call(Vec<Expr>),
+    Part(Vec<Expr>),
+}
+
+peg::parser! {
+    /// wl style surface syntax: `f[x, y]`, `{a, b}`, `x_Int`, `a -> b`, `expr /. rules`, `# + 1 &` ...
+    /// produces the same trees `expr_parser` does for the equivalent s-expression
+    grammar infix_parser() for str {
+        rule comment()
+            = "(*" (!"*)" [_])* "*)"
+
+        rule _() = quiet!{([' ' | '\t' | '\n' | '\r'] / comment())*}
+
+        // no sign or `3/4` here, those come out of `-` and `/` on literals
+        rule number() -> Expr
+            = n:$(['0'..='9']+ "^^" ['0'..='9' | 'a'..='z' | 'A'..='Z']+
+                  / (['0'..='9']+ ("." !"." ['0'..='9']*)? / "." ['0'..='9']+) (['e' | 'E'] ['+' | '-']? ['0'..='9']+ / "*^" "-"? ['0'..='9']+)?)
+              {? parse_number(n) }
+
+        rule string() -> Expr
+            = quiet!{"\""} s:$(quiet!{("\\" [_] / !['"' | '\\'] [_])*}) (quiet!{"\""} / expected!("a closing quote")) {? unescape(s).map(Expr::Str) }
+
+        rule ident() -> &'input str
+            = $("`"? ident_part() ("`" ident_part())*)
+
+        rule ident_part() = ['a'..='z' | 'A'..='Z' | '$'] ['a'..='z' | 'A'..='Z' | '0'..='9' | '$']*
+
+        // _ __ ___, optionally followed by a head
+        rule blank() -> Expr
+            = u:$("___" / "__" / "_") h:ident()? {
+                let b = match u.len() { 1 => "blank", 2 => "blank_seq", _ => "blank_null_seq" };
+                call(b, h.map(from_wl_name).into_iter().collect())
+            }
+
+        rule symbol_or_pattern() -> Expr
+            = n:ident() b:blank()? {
+                match b {
+                    Some(b) => call("pattern", vec![sym(n), b]),
+                    None => from_wl_name(n),
+                }
+            }
+            / blank()
+
+        rule out_ref() -> Expr
+            = r:$("%" ['0'..='9']+ / "%"+) {? out_ref(r) }
+
+        rule slot() -> Expr
+            = "#" n:$(['0'..='9']*) {?
+                let n: u32 = if n.is_empty() { 1 } else { n.parse().or(Err("slot number"))? };
+                Ok(call("Slot", vec![Expr::Int(n.into())]))
+            }
+
+        rule args() -> Vec<Expr>
+            = _ a:expr() ** (_ "," _) _ { a }
+
+        rule primary() -> Expr
+            = quiet!{number() / slot() / symbol_or_pattern() / out_ref()} / string()
+            / "{" a:args() "}" { call("List", a) }
+            / "(" _ e:expr() _ ")" { e }
+            / expected!("an expression")
+
+        rule suffix() -> Suffix
+            = "[[" a:args() "]]" { Suffix::Part(a) }
+            / "[" a:args() "]" { Suffix::Call(a) }
+
+        rule postfix() -> Expr
+            = start:position!() p:primary() ss:(s:suffix() e:position!() { (s, e) })* {
+                ss.into_iter().fold(p, |f, (s, end)| match s {
+                    Suffix::Call(a) => spanned(liste(std::iter::once(f).chain(a).collect()), start, end),
+                    Suffix::Part(a) => spanned(liste(std::iter::once(sym("Part")).chain(std::iter::once(f)).chain(a).collect()), start, end),
+                })
+            }
+
+        rule expr() -> Expr = precedence!{
+            x:(@) _ ";" _ y:@ { compound(x, y) }
+            x:(@) _ ";" { compound(x, sym("Null")) }
+            --
+            x:@ _ ":=" _ y:(@) { call("setd", vec![x, y]) }
+            x:@ _ "=" !"=" _ y:(@) { call("set", vec![x, y]) }
+            --
+            x:@ _ "&" !"&" { call("Function", vec![x]) }
+            --
+            x:(@) _ "//." _ y:@ { call("replace_repeated", vec![x, y]) }
+            x:(@) _ "/." _ y:@ { call("replace_all", vec![x, y]) }
+            --
+            x:@ _ "->" _ y:(@) { call("rule", vec![x, y]) }
+            x:@ _ ":>" _ y:(@) { call("rule_delayed", vec![x, y]) }
+            --
+            x:(@) _ "||" _ y:@ { call("Or", vec![x, y]) }
+            --
+            x:(@) _ "&&" _ y:@ { call("And", vec![x, y]) }
+            --
+            "!" _ x:@ { call("Not", vec![x]) }
+            --
+            x:(@) _ "===" _ y:@ { call("sameq", vec![x, y]) }
+            x:(@) _ "==" _ y:@ { call("Equal", vec![x, y]) }
+            --
+            x:(@) _ "+" _ y:@ { call("Plus", vec![x, y]) }
+            x:(@) _ "-" !">" _ y:@ { call("Plus", vec![x, negate(y)]) }
+            --
+            x:(@) _ "*" _ y:@ { call("Times", vec![x, y]) }
+            x:(@) _ "/" !['/' | '.'] _ y:@ { divide(x, y) }
+            --
+            "-" _ x:@ { negate(x) }
+            --
+            x:@ _ "^" _ y:(@) { call("Power", vec![x, y]) }
+            --
+            f:@ _ "@" _ x:(@) { liste(vec![f, x]) }
+            --
+            p:postfix() { p }
+        }
+
+        pub rule Expr() -> Expr
+            = _ s:position!() e:expr() end:position!() _ { spanned(e, s, end) }
+
+        pub rule expressions() -> Vec<Expr>
+            = _ e:expr() ** _ _ { e }
+
+        rule recovery_point() = "\n" &[^ '\n' | '\r' | ' ' | '\t' | ')' | ']' | '}']
+
+        // a newline ends a top level expression unless something is still open, like in wl
+        rule line_end() = quiet!{[' ' | '\t' | '\r']* (comment() [' ' | '\t' | '\r']*)* (&"\n" / ![_])}
+
+        rule form() -> TopLevelForm
+            = s:position!() e:expr() end:position!() line_end() _ { Ok(spanned(e, s, end)) }
+            / s:position!() [_] (!recovery_point() [_])* e:position!() _ { Err(s..e) }
+
+        pub rule recovering() -> Vec<TopLevelForm>
+            = _ fs:form()* { fs }
+
+        rule token() -> Token
+            = comment() { Token::Comment }
+            / "(*" [_]* { Token::Invalid }
+            / [' ' | '\t' | '\n' | '\r']+ { Token::Space }
+            / string() { Token::Str }
+            / "\"" (!"\"" [_])* "\""? { Token::Invalid }
+            / number() { Token::Number }
+            / slot() { Token::Pattern }
+            / out_ref() { Token::Symbol }
+            / p:$(symbol_or_pattern()) { if p.contains('_') { Token::Pattern } else { Token::Symbol } }
+            / ['(' | '[' | '{'] { Token::Open }
+            / [')' | ']' | '}'] { Token::Close }
+            / ['+' | '-' | '*' | '/' | '^' | '=' | ':' | ';' | '&' | '|' | '!' | '@' | '.' | ',' | '>'] { Token::Operator }
+            / [_] { Token::Invalid }
+
+        pub rule tokens() -> Vec<(std::ops::Range<usize>, Token)>
+            = ts:(s:position!() t:token() e:position!() { (s..e, t) })* { ts }
+    }
+}
+
+/// which surface syntax some source text is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// `(f (pattern x (blank)))`, what `lang/*.sexp` uses
+    Sexp,
+    /// `f[x_]`
+    Infix,
+}
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sexp" => Some(Syntax::Sexp),
+            "infix" => Some(Syntax::Infix),
+            _ => None,
+        }
+    }
+
+    /// what goes in `$Syntax`
+    pub fn name(self) -> &'static str {
+        match self {
+            Syntax::Sexp => "sexp",
+            Syntax::Infix => "infix",
+        }
+    }
+
+    /// `.wl` and `.m` files are infix, everything else s-expressions
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("wl" | "m") => Syntax::Infix,
+            _ => Syntax::Sexp,
+        }
+    }
+
+    /// the syntax the REPL reads input in, from `$Syntax` ("sexp" or "infix")
+    pub fn of_context(ctx: &Context2) -> Self {
+        match get_ownvalue(ctx, sym("$Syntax")) {
+            Some(Expr::Str(s)) => Syntax::from_name(&s).unwrap_or(Syntax::Sexp),
+            _ => Syntax::Sexp,
+        }
+    }
+}
+
+pub type ParseError = peg::error::ParseError<peg::str::LineCol>;
+
+/// a top level form from `recovering`, or the range of source it gave up on
+pub type TopLevelForm = std::result::Result<Expr, std::ops::Range<usize>>;
+
+/// some text expressions were parsed from, so spans can be turned back into file:line:col
+#[derive(Debug)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+/// where a parsed list came from. offsets are bytes into `Source::text`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub source: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+// a source and the number of lists whose span points into it
+type KeptSource = Option<(Rc<Source>, usize)>;
+
+thread_local! {
+    // every parsed source. a REPL line or notebook cell is dropped once nothing read from it is
+    // left, its slot stays so ids don't move
+    static SOURCES: RefCell<Vec<KeptSource>> = const { RefCell::new(vec![]) };
+    // the source the parser is reading right now, if it's one we keep spans for
+    static CURRENT_SOURCE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn retain_source(id: usize) {
+    let _ = SOURCES.try_with(|s| {
+        if let Ok(mut s) = s.try_borrow_mut() {
+            if let Some(Some((_, n))) = s.get_mut(id) {
+                *n += 1;
+            }
+        }
+    });
+}
+
+fn release_source(id: usize) {
+    // the parser drops lists it backtracks over, the source it's reading has to stay until it's done
+    let reading = CURRENT_SOURCE.try_with(|c| c.get() == Some(id)).unwrap_or(false);
+    let _ = SOURCES.try_with(|s| {
+        if let Ok(mut s) = s.try_borrow_mut() {
+            if let Some(slot @ Some(_)) = s.get_mut(id) {
+                let n = &mut slot.as_mut().unwrap().1;
+                *n = n.saturating_sub(1);
+                if *n == 0 && !reading {
+                    *slot = None;
+                }
+            }
+        }
+    });
+}
+
+fn spanned(ex: Expr, start: usize, end: usize) -> Expr {
+    if let (Expr::List(ls), Some(source)) = (&ex, CURRENT_SOURCE.with(|c| c.get())) {
+        ls.set_span(Span { source, start, end });
+    }
+    ex
+}
+
+/// 1 based line and column (in chars) of a byte offset
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, col)
+}
+
+impl Span {
+    pub fn source(&self) -> Option<Rc<Source>> {
+        SOURCES.with(|s| s.borrow().get(self.source)?.as_ref().map(|(src, _)| src.clone()))
+    }
+
+    /// `file:line:col` of the start
+    pub fn describe(&self) -> Option<String> {
+        let src = self.source()?;
+        let (line, col) = line_col(&src.text, self.start);
+        Some(format!("{}:{line}:{col}", src.name))
+    }
+}
+
+/// `file:line:col` of where `ex` was read from, if it came straight out of the parser
+pub fn source_location(ex: &Expr) -> Option<String> {
+    match ex {
+        Expr::List(ls) => ls.span()?.describe(),
+        _ => None,
+    }
+}
+
+/// ` at file:line:col` for a message about `ex`, or about the innermost form on the stack that has a span
+fn location_note(stack: &Expr, ex: &Expr) -> String {
+    source_location(ex)
+        .or_else(|| stack.iter().rev().find_map(source_location))
+        .map(|l| format!(" at {l}"))
+        .unwrap_or_default()
+}
+
+/// a parse error with enough context to print a caret under it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub source_line: String,
+}
+
+impl Diagnostic {
+    fn at(file: &str, text: &str, offset: usize, message: String) -> Self {
+        let (line, column) = line_col(text, offset);
+        let source_line = text.lines().nth(line - 1).unwrap_or("").to_string();
+        Diagnostic { file: file.to_string(), line, column, message, source_line }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(f, "{gutter}--> {}:{}:{}", self.file, self.line, self.column)?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(f, "{gutter} | {}^", " ".repeat(self.column - 1))
+    }
+}
+
+/// parses everything in `text` that it can. a bad top level form is reported and skipped, the parser
+/// picks back up at the next line that starts a new form. lists keep a `Span` back into `text`
+pub fn parse_source(name: &str, text: &str, syntax: Syntax) -> (Vec<Expr>, Vec<Diagnostic>) {
+    let id = SOURCES.with(|s| {
+        let mut s = s.borrow_mut();
+        s.push(Some((Rc::new(Source { name: name.to_string(), text: text.to_string() }), 0)));
+        s.len() - 1
+    });
+    CURRENT_SOURCE.with(|c| c.set(Some(id)));
+    let (exprs, errors) = recover(text, syntax);
+    CURRENT_SOURCE.with(|c| c.set(None));
+    // nothing read from it kept a span, like a line with just a symbol on it
+    retain_source(id);
+    release_source(id);
+    let errors = errors.into_iter().map(|(offset, message)| Diagnostic::at(name, text, offset, message)).collect();
+    (exprs, errors)
+}
+
+/// just the errors `parse_source` would report, as byte offsets into `text`, without keeping the source around
+pub fn parse_errors(text: &str, syntax: Syntax) -> Vec<(usize, String)> {
+    recover(text, syntax).1
+}
+
+fn recover(text: &str, syntax: Syntax) -> (Vec<Expr>, Vec<(usize, String)>) {
+    let forms = match syntax {
+        Syntax::Sexp => expr_parser::recovering(text),
+        Syntax::Infix => infix_parser::recovering(text),
+    };
+    let mut exprs = vec![];
+    let mut errors = vec![];
+    match forms {
+        Ok(forms) => {
+            for form in forms {
+                match form {
+                    Ok(ex) => exprs.push(ex),
+                    // parse the bad bit again on its own to find out what was wrong with it
+                    Err(range) => errors.push(match parse_expr(&text[range.clone()], syntax) {
+                        Err(err) => (range.start + err.location.offset, format!("expected {}", err.expected)),
+                        Ok(_) => (range.start, "unexpected input".to_string()),
+                    }),
+                }
+            }
+        }
+        Err(err) => errors.push((err.location.offset, format!("expected {}", err.expected))),
+    }
+    (exprs, errors)
+}
+
+pub fn parse_expressions(src: &str, syntax: Syntax) -> std::result::Result<Vec<Expr>, ParseError> {
+    match syntax {
+        Syntax::Sexp => expr_parser::expressions(src),
+        Syntax::Infix => infix_parser::expressions(src),
+    }
+}
+
+pub fn parse_expr(src: &str, syntax: Syntax) -> std::result::Result<Expr, ParseError> {
+    match syntax {
+        Syntax::Sexp => expr_parser::Expr(src),
+        Syntax::Infix => infix_parser::Expr(src),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Expr {
+    Int(num_bigint::BigInt),
+    // Int(Integer),
+    Real(ordered_float::NotNan<f64>),
+    Sym(Symbol),
+    Str(String),
+    List(List),
+}
+
+thread_local! {
+    static SYMBOLS: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// an interned symbol name. every `Symbol` with the same name shares one allocation,
+/// so comparing and hashing symbols never looks at the characters
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn intern(s: &str) -> Self {
+        SYMBOLS.with(|tbl| {
+            let mut tbl = tbl.borrow_mut();
+            if let Some(rc) = tbl.get(s) {
+                return Symbol(rc.clone());
+            }
+            let rc: Rc<str> = Rc::from(s);
+            tbl.insert(rc.clone());
+            Symbol(rc)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8 as usize).hash(state)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Symbol::intern(s)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Self {
+        Symbol::intern(&s)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+/// the elements of an `Expr::List`, shared behind an `Rc`.
+/// cloning is a refcount bump, and the structural hash is computed once and cached
+/// until someone mutates the list through `DerefMut`
+#[derive(Clone)]
+pub struct List(Rc<ListNode>);
+
+struct ListNode {
+    hash: Cell<Option<u64>>,
+    // where the parser found this list, doesn't take part in equality
+    span: Cell<Option<Span>>,
+    // the `Context2::version` this list was last found to be a fixed point at, 0 if never
+    evaluated: Cell<u64>,
+    // (index, text) for number items the reader saw written some other way than they print,
+    // like 16^^FF. only for printing, doesn't take part in equality
+    spellings: Option<Box<[(usize, String)]>>,
+    items: Vec<Expr>,
+}
+
+impl List {
+    pub fn new(items: Vec<Expr>) -> Self {
+        List(Rc::new(ListNode {
+            hash: Cell::new(None),
+            span: Cell::new(None),
+            evaluated: Cell::new(0),
+            spellings: None,
+            items,
+        }))
+    }
+
+    /// how the item at `i` was written, when that's not how it prints
+    pub fn spelling(&self, i: usize) -> Option<&str> {
+        let spellings = self.0.spellings.as_deref()?;
+        spellings.iter().find(|(j, _)| *j == i).map(|(_, t)| t.as_str())
+    }
+
+    pub fn is_evaluated_at(&self, version: u64) -> bool {
+        self.0.evaluated.get() == version
+    }
+
+    pub fn mark_evaluated(&self, version: u64) {
+        self.0.evaluated.set(version);
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.0.span.get()
+    }
+
+    pub fn set_span(&self, span: Span) {
+        retain_source(span.source);
+        if let Some(old) = self.0.span.replace(Some(span)) {
+            release_source(old.source);
+        }
+    }
+
+    pub fn ptr_eq(a: &List, b: &List) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    pub fn hash_value(&self) -> u64 {
+        if let Some(h) = self.0.hash.get() {
+            return h;
+        }
+        let mut hasher = DefaultHasher::new();
+        self.0.items.hash(&mut hasher);
+        let h = hasher.finish();
+        self.0.hash.set(Some(h));
+        h
+    }
+
+    pub fn into_vec(self) -> Vec<Expr> {
+        match Rc::try_unwrap(self.0) {
+            Ok(mut node) => std::mem::take(&mut node.items),
+            Err(rc) => rc.items.clone(),
+        }
+    }
+}
+
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        List::ptr_eq(self, other)
+            || (self.0.items.len() == other.0.items.len()
+                && self.hash_value() == other.hash_value()
+                && self.0.items == other.0.items)
+    }
+}
+
+impl Eq for List {}
+
+impl Hash for List {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash_value())
+    }
+}
+
+// a span keeps its source alive, so copies count and drops let go
+impl Clone for ListNode {
+    fn clone(&self) -> Self {
+        if let Some(span) = self.span.get() {
+            retain_source(span.source);
+        }
+        ListNode {
+            hash: self.hash.clone(),
+            span: self.span.clone(),
+            evaluated: self.evaluated.clone(),
+            spellings: self.spellings.clone(),
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl Drop for ListNode {
+    fn drop(&mut self) {
+        if let Some(span) = self.span.get() {
+            release_source(span.source);
+        }
+    }
+}
+
+impl Deref for List {
+    type Target = Vec<Expr>;
+
+    fn deref(&self) -> &Vec<Expr> {
+        &self.0.items
+    }
+}
+
+impl DerefMut for List {
+    fn deref_mut(&mut self) -> &mut Vec<Expr> {
+        let node = Rc::make_mut(&mut self.0);
+        node.hash.set(None);
+        node.evaluated.set(0);
+        node.spellings = None;
+        &mut node.items
+    }
+}
+
+impl From<Vec<Expr>> for List {
+    fn from(items: Vec<Expr>) -> Self {
+        List::new(items)
+    }
+}
+
+impl FromIterator<Expr> for List {
+    fn from_iter<I: IntoIterator<Item = Expr>>(iter: I) -> Self {
+        List::new(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for List {
+    type Item = Expr;
+    type IntoIter = std::vec::IntoIter<Expr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0.items, f)
+    }
+}
+
+impl Expr {
+    /// O(1) identity check. `true` means the two are the same shared node (or the same interned symbol),
+    /// `false` means we'd have to look inside to know
+    pub fn ptr_eq(&self, other: &Expr) -> bool {
+        match (self, other) {
+            (Expr::List(a), Expr::List(b)) => List::ptr_eq(a, b),
+            (Expr::Sym(a), Expr::Sym(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Int(i) => write!(f, "{}", i),
+            Expr::Real(r) => f.write_str(&real_literal(r.into_inner())),
+            Expr::Sym(s) => write!(f, "{}", s),
+            Expr::Str(s) => write!(f, "\"{}\"", escape(s)),
+            Expr::List(lst) => {
+                let str_list: Vec<String> = lst
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| lst.spelling(i).map_or_else(|| x.to_string(), str::to_string))
+                    .collect();
+                write!(f, "({})", str_list.join(" "))
+            }
+        }
+    }
+}
+
+impl Deref for Expr {
+    type Target = Vec<Expr>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Expr::List(ls) => ls,
+            e => panic!("Can only deref Expr::List. ex:{}", e),
+        }
+    }
+}
+
+impl DerefMut for Expr {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Expr::List(ls) => ls,
+            _ => panic!("Can only deref Expr::List"),
+        }
+    }
+}
+
+pub fn sym(s: &str) -> Expr {
+    Expr::Sym(Symbol::intern(s))
+}
+
+fn list(strs: Vec<&str>) -> Expr {
+    liste(strs.iter().map(|s| sym(s)).collect::<Vec<_>>())
+}
+
+fn liste(es: Vec<Expr>) -> Expr {
+    Expr::List(List::new(es))
+}
+
+// a list from the reader, remembering the number items that were written differently than they print
+fn spelled_list(items: Vec<(Expr, Option<&str>)>) -> Expr {
+    let mut spellings = vec![];
+    let mut es = Vec::with_capacity(items.len());
+    for (i, (e, text)) in items.into_iter().enumerate() {
+        if let Some(text) = text {
+            if text != e.to_string() {
+                spellings.push((i, text.to_string()));
+            }
+        }
+        es.push(e);
+    }
+    let mut ls = List::new(es);
+    if !spellings.is_empty() {
+        Rc::get_mut(&mut ls.0).unwrap().spellings = Some(spellings.into_boxed_slice());
+    }
+    Expr::List(ls)
+}
+
+fn head(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Int(_) => sym("Int"),
+        Expr::Real(_) => sym("Real"),
+        Expr::Sym(_) => sym("Sym"),
+        Expr::Str(_) => sym("Str"),
+        Expr::List(lst) => {
+            if let Some(first) = lst.first() {
+                first.clone()
+            } else {
+                message!("[ERROR]: empty list isnt allowed");
+                sym("GET_FUCKED")
+            }
+        }
+    }
+}
+
+fn length(expr: &Expr) -> Expr {
+    match expr {
+        Expr::List(es) => Expr::Int((es.len() - 1).into()),
+        _ => Expr::Int(0.into()),
+    }
+}
+
+// (a b c) -> (b c)
+// fn rest(expr: &Expr) -> Expr {}
+
+pub fn is_atom(expr: &Expr) -> bool {
+    match expr {
+        Expr::List(_) => false,
+        _ => true,
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Context2 {
+    vars: HashMap<Expr, TableEntry>,
+    /// bumped on every change to a definition. a list stamped with this version is already
+    /// fully evaluated and `evaluate` hands it back untouched
+    version: u64,
+    /// set while a `Trace` or `TracePrint` is running
+    tracer: Option<Tracer>,
+    pub abort: AbortFlag,
+    /// innermost last
+    constraints: Vec<Constraint>,
+    ticks: u32,
+    contexts: contexts::Contexts,
+}
+
+/// records the forms `evaluate` steps through.
+/// every `evaluate` call opens a frame, and a frame where something happened ends up as a
+/// `(List form1 (List ...subevaluations) form2 ...)` in its parent, like wl's Trace
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Tracer {
+    /// only keep forms matching this
+    pattern: Option<Expr>,
+    /// print each form as we go instead of collecting (TracePrint)
+    print: bool,
+    frames: Vec<Vec<Expr>>,
+}
+
+impl Tracer {
+    pub fn new(pattern: Option<Expr>, print: bool) -> Self {
+        Self {
+            pattern,
+            print,
+            // the root frame collects the trace of the whole evaluation
+            frames: vec![vec![]],
+        }
+    }
+
+    fn record(&mut self, ex: &Expr) {
+        if let Some(pat) = &self.pattern {
+            if !my_match(ex.clone(), pat.clone(), &vec![], &mut HashMap::new(), &mut HashMap::new()) {
+                return;
+            }
+        }
+        if self.print {
+            print_out(&format!("{}{}", "  ".repeat(self.frames.len().saturating_sub(2)), ex));
+            return;
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.push(liste(vec![sym("HoldForm"), ex.clone()]));
+        }
+    }
+
+    fn close_frame(&mut self) {
+        let frame = self.frames.pop().unwrap_or_default();
+        // an unfiltered frame always starts with the form we were asked for, so
+        // it is only interesting if something happened after that
+        let min_len = if self.pattern.is_some() { 1 } else { 2 };
+        if frame.len() >= min_len {
+            if let Some(parent) = self.frames.last_mut() {
+                parent.push(liste(std::iter::once(sym("List")).chain(frame).collect()));
+            }
+        }
+    }
+
+    /// the collected trace, once the traced evaluation has returned
+    fn finish(mut self) -> Expr {
+        let mut root = self.frames.pop().unwrap_or_default();
+        if root.len() == 1 {
+            root.pop().unwrap()
+        } else {
+            liste(std::iter::once(sym("List")).chain(root).collect())
+        }
+    }
+}
+
+// versions come from one counter shared by every context, so a stamp from one
+// context can never be mistaken for a stamp from another
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+/// raised by the SIGINT handler (or anyone holding a clone). while it is up every `evaluate`
+/// returns `$Aborted` right away, so a long computation unwinds back to whoever started it
+#[derive(Debug, Clone, Default)]
+pub struct AbortFlag(Arc<AtomicBool>);
+
+impl AbortFlag {
+    pub fn raise(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// lowers the flag, returning whether it was up
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::SeqCst)
+    }
+
+    /// the raw flag, for `signal_hook::flag::register`
+    pub fn handle(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
+
+impl PartialEq for AbortFlag {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for AbortFlag {}
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // bytes allocated minus bytes freed on this thread. memory freed here that another thread
+    // allocated makes it go down, so it can be negative
+    static THREAD_ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count_thread(delta: isize) {
+    // try_with, the allocator still runs while a thread's locals are being torn down
+    let _ = THREAD_ALLOCATED.try_with(|n| n.set(n.get().wrapping_add(delta)));
+}
+
+/// a `GlobalAlloc` that keeps a running total of live heap bytes, for `MemoryInUse`, and one per
+/// thread, for `MemoryConstrained`, so other threads allocating don't count against a limit. a
+/// binary has to install it with `#[global_allocator]`, otherwise memory limits never trip
+pub struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+            count_thread(layout.size() as isize);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        count_thread(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let p = System.realloc(ptr, layout, new_size);
+        if !p.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+            count_thread(new_size as isize - layout.size() as isize);
+        }
+        p
+    }
+}
+
+/// live heap bytes, as counted by `CountingAlloc`
+pub fn allocated_bytes() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// net heap bytes this thread has allocated, as counted by `CountingAlloc`
+fn thread_allocated_bytes() -> isize {
+    THREAD_ALLOCATED.try_with(Cell::get).unwrap_or(0)
+}
+
+/// one running `TimeConstrained`/`MemoryConstrained`, or a limit from `KernelSettings`
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Constraint {
+    deadline: Option<Instant>,
+    memory_limit: Option<usize>,
+    // this thread's heap when the constraint started, the limit is on growth past this
+    baseline: isize,
+    tripped: bool,
+}
+
+// how many evaluate calls go by between looking at the clock and the allocator
+const CONSTRAINT_CHECK_INTERVAL: u32 = 64;
+
+pub const DEFAULT_RECURSION_LIMIT: usize = 1024;
+pub const DEFAULT_ITERATION_LIMIT: usize = 4096;
+
+/// reads a limit like `$RecursionLimit` from its ownvalue. `Infinity` means no limit,
+/// and anything that isn't a non-negative integer falls back to `default`
+fn limit_value(ctx: &Context2, name: &str, default: usize) -> Option<usize> {
+    match get_ownvalue(ctx, sym(name)) {
+        Some(Expr::Int(n)) => Some(n.to_usize().unwrap_or(default)),
+        Some(Expr::Sym(s)) if s == "Infinity" => None,
+        _ => Some(default),
+    }
+}
+
+impl Default for Context2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Context2 {
+    pub fn new() -> Self {
+        let mut ctx = Self {
+            vars: HashMap::new(),
+            version: NEXT_VERSION.fetch_add(1, Ordering::Relaxed),
+            tracer: None,
+            abort: AbortFlag::default(),
+            constraints: vec![],
+            ticks: 0,
+            contexts: contexts::Contexts::default(),
+        };
+        for (name, n) in [
+            ("$RecursionLimit", DEFAULT_RECURSION_LIMIT),
+            ("$IterationLimit", DEFAULT_ITERATION_LIMIT),
+        ] {
+            let mut te = TableEntry::new();
+            te.own = Some(Expr::Int(n.into()));
+            ctx.vars.insert(sym(name), te);
+        }
+        let mut te = TableEntry::new();
+        te.own = Some(Expr::Str("sexp".into()));
+        ctx.vars.insert(sym("$Syntax"), te);
+        let mut te = TableEntry::new();
+        te.own = Some(sym("FullForm"));
+        ctx.vars.insert(sym("$OutputForm"), te);
+        let mut te = TableEntry::new();
+        te.own = Some(Expr::Int(1.into()));
+        ctx.vars.insert(sym("$Line"), te);
+        let mut te = TableEntry::new();
+        te.own = Some(sym("Infinity"));
+        ctx.vars.insert(sym("$HistoryLength"), te);
+        for (name, value) in contexts::defaults() {
+            let mut te = TableEntry::new();
+            te.own = Some(value);
+            ctx.vars.insert(sym(name), te);
+        }
+        ctx
+    }
+
+    /// `(set name value)` without going through the evaluator, for settings like `$Syntax`
+    pub fn set_ownvalue(&mut self, name: &str, value: Expr) {
+        self.vars.entry(sym(name)).or_insert_with(TableEntry::new).own = Some(value);
+        self.bump_version();
+    }
+
+    /// the number of the input being evaluated, `$Line`
+    pub fn line(&self) -> BigInt {
+        match get_ownvalue(self, sym("$Line")) {
+            Some(Expr::Int(n)) => n,
+            _ => BigInt::from(1),
+        }
+    }
+
+    /// stores `(In n)` and `(Out n)` for the input just evaluated on line `n`, drops whatever
+    /// `$HistoryLength` says is too old, and moves `$Line` on. the expressions go straight into
+    /// the down_values, nothing gets printed and reparsed
+    pub fn record_history(&mut self, n: &BigInt, input: &Expr, output: &Expr) {
+        let keep = match get_ownvalue(self, sym("$HistoryLength")) {
+            Some(Expr::Int(k)) => Some(k),
+            _ => None,
+        };
+        for (h, ex) in [("In", input), ("Out", output)] {
+            let te = self.vars.entry(sym(h)).or_insert_with(TableEntry::new);
+            if keep.as_ref().is_none_or(|k| k.is_positive()) {
+                insert_down_value(te, &liste(vec![sym(h), Expr::Int(n.clone())]), ex);
+            }
+            if let Some(k) = &keep {
+                let oldest = n - k;
+                // each down value is (rule_delayed (hold_pattern (In i)) ...), after the List head
+                te.down.retain(|dv| match dv {
+                    Expr::List(dv) => !matches!(&dv[1][1][..], [_, Expr::Int(i)] if *i <= oldest),
+                    _ => true,
+                });
+            }
+        }
+        // a `(set $Line 0)` during the evaluation counts too, the next line is one after whatever $Line is now
+        let next = self.line() + 1;
+        self.set_ownvalue("$Line", Expr::Int(next));
+    }
+
+    /// call after anything that can change what an expression evaluates to
+    pub fn bump_version(&mut self) {
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// starts a time and/or memory limit on everything evaluated until the matching `pop_constraint`
+    pub fn push_constraint(&mut self, time: Option<Duration>, memory: Option<usize>) {
+        self.constraints.push(Constraint {
+            // a deadline too far off to represent is no deadline
+            deadline: time.and_then(|t| Instant::now().checked_add(t)),
+            memory_limit: memory,
+            baseline: thread_allocated_bytes(),
+            tripped: false,
+        });
+        // look right away on the first call, a zero limit should trip immediately
+        self.ticks = CONSTRAINT_CHECK_INTERVAL - 1;
+    }
+
+    /// ends the innermost limit, returning whether it was exceeded
+    pub fn pop_constraint(&mut self) -> bool {
+        self.constraints.pop().is_some_and(|c| c.tripped)
+    }
+
+    /// true when evaluation should unwind: the user hit ctrl-c or some limit ran out
+    pub fn should_stop(&mut self) -> bool {
+        if self.abort.is_raised() {
+            return true;
+        }
+        if self.constraints.is_empty() {
+            return false;
+        }
+        if self.constraints.iter().any(|c| c.tripped) {
+            return true;
+        }
+        self.ticks += 1;
+        if self.ticks < CONSTRAINT_CHECK_INTERVAL {
+            return false;
+        }
+        self.ticks = 0;
+        let now = Instant::now();
+        let mem = thread_allocated_bytes();
+        let mut stop = false;
+        for c in &mut self.constraints {
+            let out_of_time = c.deadline.is_some_and(|d| now >= d);
+            let out_of_memory = c
+                .memory_limit
+                .is_some_and(|m| usize::try_from(mem.saturating_sub(c.baseline)).is_ok_and(|grew| grew > m));
+            if out_of_time || out_of_memory {
+                c.tripped = true;
+                stop = true;
+            }
+        }
+        stop
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TableEntry {
+    own: Option<Expr>,
+    down: Expr,
+    sub: Expr,
+    /// results remembered for a symbol with the `Memoize` attribute, keyed by the call with evaluated args
+    memo: HashMap<Expr, Expr>,
+    /// like `HoldAll`, in the order they were given
+    attrs: Vec<Expr>,
+}
+
+impl TableEntry {
+    pub fn new() -> Self {
+        Self {
+            own: None,
+            down: liste(vec![sym("List")]),
+            sub: liste(vec![sym("List")]),
+            memo: HashMap::new(),
+            attrs: vec![],
+        }
+    }
+}
+
+/// the attributes `SetAttributes` knows about
+pub const ATTRIBUTES: &[&str] = &[
+    "Constant",
+    "Flat",
+    "HoldAll",
+    "HoldAllComplete",
+    "HoldFirst",
+    "HoldRest",
+    "Listable",
+    "Locked",
+    "Memoize",
+    "NHoldAll",
+    "NHoldFirst",
+    "NHoldRest",
+    "NumericFunction",
+    "OneIdentity",
+    "Orderless",
+    "Protected",
+    "ReadProtected",
+    "SequenceHold",
+    "Stub",
+    "Temporary",
+];
+
+/// true if `ex` contains a pattern object anywhere, ie it can stand for more than one expression
+fn has_patterns(ex: &Expr) -> bool {
+    match ex {
+        Expr::List(ls) => {
+            let pat_heads = [
+                sym("pattern"),
+                sym("blank"),
+                sym("blank_seq"),
+                sym("blank_null_seq"),
+                sym("Alternatives"),
+            ];
+            ls.first().is_some_and(|h| pat_heads.contains(h)) || ls.iter().any(has_patterns)
+        }
+        _ => false,
+    }
+}
+
+/// adds `(rule_delayed (hold_pattern lhs) rhs)` to the down_values of `te`.
+/// a new definition for an lhs we already have replaces the old one, and definitions without
+/// patterns (like a cached `(fib 5)`) go in front of the general rules so they are tried first.
+/// anything memoized from the old definitions is forgotten
+fn insert_down_value(te: &mut TableEntry, lhs: &Expr, rhs: &Expr) {
+    te.memo.clear();
+    let dv = liste(vec![
+        sym("rule_delayed"),
+        liste(vec![sym("hold_pattern"), lhs.clone()]),
+        rhs.clone(),
+    ]);
+    if let Some(i) = te.down[1..].iter().position(|old| old[1][1] == *lhs) {
+        te.down[i + 1] = dv;
+    } else if !has_patterns(lhs) {
+        let first_general = te.down[1..]
+            .iter()
+            .position(|old| has_patterns(&old[1][1]))
+            .map_or(te.down.len(), |i| i + 1);
+        te.down.insert(first_general, dv);
+    } else {
+        te.down.push(dv);
+    }
+}
+
+/// the attributes of `s` as a `(List ...)`, if it has any
+pub fn attributes_of(ctx: &Context2, s: &Expr) -> Option<Expr> {
+    let te = ctx.vars.get(s).filter(|te| !te.attrs.is_empty())?;
+    Some(liste(std::iter::once(sym("List")).chain(te.attrs.iter().cloned()).collect()))
+}
+
+/// true if `s` has `attr`, like `Protected`, among its attributes
+pub fn has_attribute(ctx: &Context2, s: &Expr, attr: &str) -> bool {
+    ctx.vars.get(s).is_some_and(|te| te.attrs.contains(&sym(attr)))
+}
+
+/// replaces the attributes of `s`, with none of the checks `SetAttributes` does
+fn set_attributes(ctx: &mut Context2, s: &Expr, attrs: Vec<Expr>) {
+    ctx.vars.entry(s.clone()).or_insert_with(TableEntry::new).attrs = attrs;
+    ctx.bump_version();
+}
+
+/// the attribute names in `attrs`, a single one or a list of them, if they're all ones we know
+fn attribute_names(f: &str, attrs: &Expr) -> Option<Vec<Expr>> {
+    let names = match attrs {
+        Expr::List(ls) => ls[1..].to_vec(),
+        a => vec![a.clone()],
+    };
+    for a in &names {
+        if !matches!(a, Expr::Sym(name) if ATTRIBUTES.contains(&name.as_str())) {
+            message!("{f}: {a} is not a known attribute");
+            return None;
+        }
+    }
+    Some(names)
+}
+
+/// why `lhs` can't be given a value, if it can't. `(attrs s)` is about s: its attributes stay
+/// put if it's Locked or Protected
+fn assignment_blocked(ctx: &Context2, lhs: &Expr) -> Option<String> {
+    match lhs {
+        Expr::Sym(_) if has_attribute(ctx, lhs, "Protected") => Some(format!("Symbol {lhs} is Protected")),
+        Expr::List(ls) if ls.len() == 2 && ls[0] == sym("attrs") => ["Locked", "Protected"]
+            .iter()
+            .find(|a| has_attribute(ctx, &ls[1], a))
+            .map(|a| format!("Symbol {} is {a}", ls[1])),
+        Expr::List(ls) if has_attribute(ctx, &ls[0], "Protected") => Some(format!("Tag {} in {lhs} is Protected", ls[0])),
+        _ => None,
+    }
+}
+
+pub fn get_ownvalue(ctx: &Context2, sym: Expr) -> Option<Expr> {
+    // println!("ctx: {:?}. sym: {}", ctx, sym);
+    let te = ctx.vars.get(&sym);
+    if let Some(te) = te {
+        let rule = te.own.clone();
+        return rule;
+        // for now, since I'm only allowing a single ownvalue maybe im not going to do the whole handling of HoldPattern[lhs] :> rhs
+        // where i actually take sym and do sym /. OwnValues[sym]
+        // apply_rule()
+    } else {
+        None
+    }
+}
+
+/// i hate this function 
+fn unpack_mat(ex: Expr) -> Option<Vec<Vec<(f64, f64, f64)>>> {
+    let mut result: Vec<Vec<(f64, f64, f64)>> = vec![];
+
+    if let Expr::List(outer_list) = ex {
+        // println!("outerr elem: {:?}", outer_list[0]);
+        for outer_elem in &outer_list[1..] {
+            if let Expr::List(inner_list) = outer_elem {
+                let mut inner_vec: Vec<(f64, f64, f64)> = vec![];
+                // println!("inner elem: {:?}", inner_list[0]);
+                for inner_elem in &inner_list[1..] {
+                    if let Expr::List(tuple_list) = inner_elem {
+                        // println!("tuple_list: {:?}", tuple_list);
+                        if tuple_list.len() == 4 {
+                            if let (Expr::Real(a), Expr::Real(b), Expr::Real(c)) =
+                                (&tuple_list[1], &tuple_list[2], &tuple_list[3])
+                            {
+                                inner_vec.push((a.into_inner(), b.into_inner(), c.into_inner()));
+                            } else {
+                                return None; // one of the elements was not a Real
+                            }
+                        } else {
+                            return None; // tuple_list did not contain exactly 3 elements
+                        }
+                    } else {
+                        return None; // inner_elem was not a List
+                    }
+                }
+                result.push(inner_vec);
+            } else {
+                return None; // outer_elem was not a List
+            }
+        }
+        Some(result)
+    } else {
+        None // ex was not a List
+    }
+}
+
+fn create_svg_from_colors(colors: Vec<Vec<(f64, f64, f64)>>, filename: &str, scale_factor: i32) {
+    let rows = colors.len();
+    let cols = colors[0].len(); // Assuming all rows have the same length
+
+    let width = cols as i32 * scale_factor;
+    let height = rows as i32 * scale_factor;
+
+    // Create SvgSurface
+    let surface = SvgSurface::new(width as f64, height as f64, Some(filename))
+        .expect("Couldn't create SVG surface");
+
+    // Create Context for drawing
+    let cr = Context::new(&surface).expect("Couldn't create Cairo context");
+
+    // Scale the coordinate system
+    cr.scale(scale_factor as f64, scale_factor as f64);
+
+    // Loop through each cell to draw rectangles with specified colors
+    for (i, row) in colors.iter().enumerate() {
+        for (j, &(r, g, b)) in row.iter().enumerate() {
+            cr.rectangle(j as f64, i as f64, 1.0, 1.0);
+            cr.set_source_rgb(r, g, b);
+            cr.fill().expect("Failed to fill rectangle");
+        }
+    }
+
+    // Finish drawing
+    cr.show_page().expect("Failed to save SVG");
+}
+
+// fn export()
+
+/// `set` holds its lhs, but like wl the arguments of a concrete lhs still get evaluated,
+/// so `(set (fib (Plus 2 3)) 5)` defines `(fib 5)`. lhs with patterns are left alone
+fn eval_lhs_args(stack: &mut Expr, ctx: &mut Context2, lhs: &Expr) -> Expr {
+    match lhs {
+        Expr::List(ls) if !has_patterns(lhs) => share_unchanged(
+            lhs,
+            std::iter::once(ls[0].clone())
+                .chain(ls[1..].iter().map(|a| evaluate(stack, ctx, a)))
+                .collect(),
+        ),
+        _ => lhs.clone(),
+    }
+}
+
+/// evaluates `ex` (the rewritten form of the call `key`) all the way and remembers the result
+/// for the `Memoize` symbol `nh`. a result cut short by an abort or a limit isn't remembered
+fn memoize_result(stack: &mut Expr, ctx: &mut Context2, nh: &Expr, key: Expr, ex: &Expr) -> Expr {
+    let res = evaluate(stack, ctx, ex);
+    if ctx.should_stop() {
+        return res;
+    }
+    ctx.vars
+        .entry(nh.clone())
+        .or_insert_with(TableEntry::new)
+        .memo
+        .insert(key, res.clone());
+    res
+}
+
+// are we guaranteed that we have a list here?
+// can evaluated_args be empty
+/// fills in a body for `((Function body) args...)` or `((Function params body) args...)`.
+/// `(Slot n)` inside nested Functions belongs to them, so we don't descend there. named
+/// parameters do go inside, except where a nested Function takes the same name
+fn apply_function(f: &Expr, args: &[Expr]) -> Option<Expr> {
+    fn fill(body: &Expr, sub: &dyn Fn(&Expr) -> Option<Expr>) -> Expr {
+        if let Some(e) = sub(body) {
+            return e;
+        }
+        match body {
+            Expr::List(_) if head(body) == sym("Function") => body.clone(),
+            Expr::List(ls) => share_unchanged(body, ls.iter().map(|e| fill(e, sub)).collect()),
+            _ => body.clone(),
+        }
+    }
+    fn fill_named(body: &Expr, bound: &[(&Expr, &Expr)]) -> Expr {
+        if let Some((_, arg)) = bound.iter().find(|(p, _)| *p == body) {
+            return (*arg).clone();
+        }
+        match body {
+            Expr::List(ls) if head(body) == sym("Function") && ls.len() == 3 => {
+                let inner = function_params(&ls[1]).unwrap_or_default();
+                let outer: Vec<_> = bound.iter().filter(|(p, _)| !inner.contains(p)).copied().collect();
+                share_unchanged(body, vec![ls[0].clone(), ls[1].clone(), fill_named(&ls[2], &outer)])
+            }
+            Expr::List(ls) => share_unchanged(body, ls.iter().map(|e| fill_named(e, bound)).collect()),
+            _ => body.clone(),
+        }
+    }
+    fn function_params(params: &Expr) -> Option<Vec<Expr>> {
+        match params {
+            Expr::Sym(_) => Some(vec![params.clone()]),
+            Expr::List(ls) if head(params) == sym("List") => Some(ls[1..].to_vec()),
+            _ => None,
+        }
+    }
+    match &f[1..] {
+        [body] => Some(fill(body, &|e| {
+            if head(e) != sym("Slot") {
+                return None;
+            }
+            match &e[1] {
+                Expr::Int(n) => n
+                    .to_usize()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|i| args.get(i).cloned()),
+                _ => None,
+            }
+        })),
+        [params, body] => {
+            let params = function_params(params)?;
+            if params.len() > args.len() {
+                return None;
+            }
+            Some(fill_named(body, &params.iter().zip(args).collect::<Vec<_>>()))
+        }
+        _ => None,
+    }
+}
+
+/// the chars `(StringTake s spec)` keeps: `n` first n, `-n` last n, `(List m n)` m through n counting from 1
+fn string_take_range(len: usize, spec: &Expr) -> Option<std::ops::Range<usize>> {
+    let len = len as i64;
+    // 1 based, negative from the end
+    let index = |e: &Expr| -> Option<i64> {
+        let i = match e {
+            Expr::Int(i) => i.to_i64()?,
+            _ => return None,
+        };
+        let i = if i < 0 { len + i + 1 } else { i };
+        (1..=len).contains(&i).then_some(i)
+    };
+    let (from, to) = match spec {
+        Expr::Int(n) => {
+            let n = n.to_i64()?;
+            if n.abs() > len {
+                return None;
+            }
+            if n >= 0 {
+                (0, n)
+            } else {
+                (len + n, len)
+            }
+        }
+        Expr::List(_) if head(spec) == sym("List") => match &spec[1..] {
+            [m] => (index(m)? - 1, index(m)?),
+            [m, n] => (index(m)? - 1, index(n)?.max(index(m)? - 1)),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(from as usize..to as usize)
+}
+
+/// whole string match where `*` in `pat` matches any run of characters
+fn glob_match(s: &str, pat: &str) -> bool {
+    let mut parts = pat.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for p in middle {
+        match rest.find(p) {
+            Some(i) => rest = &rest[i + p.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+// nh can be List too
+pub fn internal_functions_apply(
+    stack: &mut Expr,
+    ctx: &mut Context2,
+    nh: Expr,
+    evaluated_args: Vec<Expr>,
+) -> Expr {
+    let reconstructed_ex = Expr::List(
+        std::iter::once(nh.clone())
+            .chain(evaluated_args.clone().to_owned())
+            .collect(),
+    );
+
+    if head(&nh) == sym("Function") {
+        return match apply_function(&nh, &evaluated_args) {
+            Some(res) => res,
+            None => {
+                message!("Function: can't apply {nh} to {} arguments", evaluated_args.len());
+                reconstructed_ex
+            }
+        };
+    } else if nh == sym("CompoundExpression") {
+        // HoldAll, so each part sees what the ones before it set
+        let mut res = sym("Null");
+        for arg in &evaluated_args {
+            res = evaluate(stack, ctx, arg);
+        }
+        return res;
+    } else if nh == sym("Equal") {
+        // only decides when both sides are numbers or identical, otherwise it stays unevaluated.
+        // integers and rationals compare exactly, a real brings in floating point
+        fn as_f64(e: &Expr) -> Option<f64> {
+            match e {
+                Expr::Real(r) => Some(r.into_inner()),
+                _ => exact_number(e).and_then(|(n, d)| Some(n.to_f64()? / d.to_f64()?)),
+            }
+        }
+        fn numbers_equal(a: &Expr, b: &Expr) -> Option<bool> {
+            match (exact_number(a), exact_number(b)) {
+                (Some((an, ad)), Some((bn, bd))) => Some(an * bd == bn * ad),
+                _ => Some(as_f64(a)? == as_f64(b)?),
+            }
+        }
+        if evaluated_args.windows(2).all(|w| w[0] == w[1]) {
+            return sym("true");
+        }
+        let eqs: Option<Vec<bool>> = evaluated_args.windows(2).map(|w| numbers_equal(&w[0], &w[1])).collect();
+        return match eqs {
+            Some(eqs) => sym(&format!("{}", eqs.iter().all(|&e| e))),
+            None => reconstructed_ex,
+        };
+    } else if nh == sym("Format") {
+        // (Format "source") lays out source text, comments and all, (Format expr) an expression.
+        // an optional second argument is the width
+        let width = match evaluated_args.get(1) {
+            None => pretty::DEFAULT_WIDTH,
+            Some(Expr::Int(w)) if w.is_positive() => w.to_usize().unwrap_or(usize::MAX),
+            Some(_) => {
+                message!("Format: the width must be a positive integer");
+                return sym("$Failed");
+            }
+        };
+        let src = match &evaluated_args[..] {
+            [Expr::Str(s), ..] => s.clone(),
+            [ex, ..] => ex.to_string(),
+            [] => {
+                message!("Format takes an expression or a string of source");
+                return sym("$Failed");
+            }
+        };
+        return match pretty::format_source(&src, width) {
+            Ok(out) => Expr::Str(out.trim_end().to_string()),
+            Err(err) => {
+                message!("Format: {err}");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("TeXForm") || nh == sym("MathMLForm") {
+        let [ex] = evaluated_args.as_slice() else {
+            message!("{nh} takes 1 argument");
+            return sym("$Failed");
+        };
+        return Expr::Str(if nh == sym("TeXForm") { format::tex_form(ex) } else { format::mathml_form(ex) });
+    } else if nh == sym("matchq") {
+        if evaluated_args.len() != 2 {
+            message!("matchq takes 2 arguments");
+            return sym("$Failed");
+        }
+        return sym(&format!(
+            "{}",
+            my_match(
+                evaluated_args[0].clone(),
+                evaluated_args[1].clone(),
+                &vec![],
+                &mut HashMap::new(),
+                &mut HashMap::new()
+            )
+        ));
+    } else if nh == sym("sameq") {
+        // println!("in sameq: evaluated_args: {:?}", evaluated_args);
+        let first_arg = &evaluated_args[0];
+        let all_same = evaluated_args.iter().all(|arg| arg == first_arg);
+        return sym(&format!("{}", all_same));
+    } else if nh == sym("replace") {
+        return replace(&evaluated_args[0], &evaluated_args[1]);
+    } else if nh == sym("replace_all") {
+        return replace_all(&evaluated_args[0], &evaluated_args[1]);
+    } else if nh == sym("rr") || nh == sym("replace_repeated") {
+        // (rr expr rules) or (rr expr rules (rule MaxIterations n))
+        let mut max_iterations = Some(DEFAULT_MAX_ITERATIONS);
+        match &evaluated_args[..] {
+            [_, _] => {}
+            [_, _, opt] if head(opt) == sym("rule") && opt[1] == sym("MaxIterations") => {
+                max_iterations = match &opt[2] {
+                    Expr::Int(n) if n.to_usize().is_some() => n.to_usize(),
+                    Expr::Sym(s) if s == "Infinity" => None,
+                    _ => {
+                        message!("replace_repeated: MaxIterations must be a non-negative integer or Infinity");
+                        return reconstructed_ex;
+                    }
+                }
+            }
+            _ => {
+                message!("replace_repeated takes 2 arguments and an optional (rule MaxIterations n)");
+                return sym("$Failed");
+            }
+        }
+        return replace_repeated(ctx, &evaluated_args[0], &evaluated_args[1], max_iterations);
+    } else if nh == sym("head") {
+        return head(&evaluated_args[0]);
+    } else if nh == sym("parse") {
+        match evaluated_args[0] {
+            Expr::Str(ref s) => {
+                let pex = expr_parser::Expr(s);
+                match pex {
+                    Ok(expr) => return expr,
+                    Err(err) => {
+                        message!("Failed to parse: {}", err);
+                        return sym("$Failed");
+                    }
+                }
+            }
+            _ => {
+                message!("parse takes a string");
+                return sym("$Failed");
+            }
+        }
+    } else if nh == sym("ToExpression") {
+        // parse, in whatever syntax the repl is reading, and evaluate
+        let Some(Expr::Str(s)) = evaluated_args.first() else {
+            message!("ToExpression takes a string");
+            return sym("$Failed");
+        };
+        match parse_expressions(s, Syntax::of_context(ctx)) {
+            Ok(exprs) => {
+                let mut res = sym("Null");
+                for ex in exprs {
+                    let ex = contexts::resolve(ctx, &ex);
+                    res = evaluate(stack, ctx, &ex);
+                }
+                return res;
+            }
+            Err(err) => {
+                message!("ToExpression: failed to parse: {}", err);
+                return sym("$Failed");
+            }
+        }
+    } else if nh == sym("ToString") {
+        // (ToString expr) or (ToString expr InputForm)
+        if !(1..=2).contains(&evaluated_args.len()) {
+            message!("ToString takes 1 or 2 arguments");
+            return sym("$Failed");
+        }
+        let form = match evaluated_args.get(1) {
+            Some(Expr::Sym(f)) => Form::from_name(f).unwrap_or(Form::Full),
+            _ => Form::of_context(ctx),
+        };
+        return match &evaluated_args[0] {
+            Expr::Str(_) => evaluated_args[0].clone(),
+            ex => Expr::Str(format_expr(ex, form)),
+        };
+    } else if nh == sym("StringJoin") {
+        // (StringJoin "a" "b") or (StringJoin (List "a" "b"))
+        let parts = match &evaluated_args[..] {
+            [l @ Expr::List(_)] if head(l) == sym("List") => l[1..].to_vec(),
+            args => args.to_vec(),
+        };
+        let mut res = String::new();
+        for p in &parts {
+            match p {
+                Expr::Str(s) => res.push_str(s),
+                _ => {
+                    message!("StringJoin: {p} is not a string");
+                    return reconstructed_ex;
+                }
+            }
+        }
+        return Expr::Str(res);
+    } else if nh == sym("StringLength") {
+        return match &evaluated_args[..] {
+            [Expr::Str(s)] => Expr::Int(s.chars().count().into()),
+            _ => reconstructed_ex,
+        };
+    } else if nh == sym("Characters") {
+        return match &evaluated_args[..] {
+            [Expr::Str(s)] => liste(
+                std::iter::once(sym("List"))
+                    .chain(s.chars().map(|c| Expr::Str(c.to_string())))
+                    .collect(),
+            ),
+            _ => reconstructed_ex,
+        };
+    } else if nh == sym("ToUpperCase") || nh == sym("ToLowerCase") {
+        return match &evaluated_args[..] {
+            [Expr::Str(s)] if nh == sym("ToUpperCase") => Expr::Str(s.to_uppercase()),
+            [Expr::Str(s)] => Expr::Str(s.to_lowercase()),
+            _ => reconstructed_ex,
+        };
+    } else if nh == sym("StringTake") {
+        if let [Expr::Str(s), spec] = &evaluated_args[..] {
+            let chars: Vec<char> = s.chars().collect();
+            if let Some(range) = string_take_range(chars.len(), spec) {
+                return Expr::Str(chars[range].iter().collect());
+            }
+            message!("StringTake: cannot take {spec} of a string of length {}", chars.len());
+        }
+        return reconstructed_ex;
+    } else if nh == sym("StringSplit") {
+        // on whitespace, or on a separator string
+        let pieces: Vec<&str> = match &evaluated_args[..] {
+            [Expr::Str(s)] => s.split_whitespace().collect(),
+            [Expr::Str(s), Expr::Str(sep)] if !sep.is_empty() => {
+                s.split(sep.as_str()).filter(|p| !p.is_empty()).collect()
+            }
+            _ => return reconstructed_ex,
+        };
+        return liste(
+            std::iter::once(sym("List"))
+                .chain(pieces.into_iter().map(|p| Expr::Str(p.to_string())))
+                .collect(),
+        );
+    } else if nh == sym("StringReplace") {
+        // (StringReplace "abc" (rule "b" "x")) or a list of rules, lhs can be any string pattern
+        let [Expr::Str(s), rules] = &evaluated_args[..] else {
+            return reconstructed_ex;
+        };
+        let rules = match string_patterns::string_rules(rules) {
+            Ok(rules) if rules.iter().all(|(_, rhs)| rhs.is_some()) => rules,
+            Ok(_) => {
+                message!("StringReplace: {} is not a rule or list of rules", evaluated_args[1]);
+                return reconstructed_ex;
+            }
+            Err(err) => {
+                message!("StringReplace: {err}");
+                return reconstructed_ex;
+            }
+        };
+        let mut res = String::new();
+        let mut last = 0;
+        for (start, i, m) in string_patterns::scan(s, &rules) {
+            let rhs = rules[i].1.as_ref().unwrap();
+            let new = evaluate(stack, ctx, &string_patterns::instantiate(rhs, &m));
+            let Expr::Str(new) = new else {
+                message!("StringReplace: {new} is not a string");
+                return reconstructed_ex;
+            };
+            res.push_str(&s[last..start]);
+            res.push_str(&new);
+            last = m.end;
+        }
+        res.push_str(&s[last..]);
+        return Expr::Str(res);
+    } else if nh == sym("StringCases") {
+        // the matched substrings, or the rhs of the rule that matched
+        let [Expr::Str(s), rules] = &evaluated_args[..] else {
+            return reconstructed_ex;
+        };
+        let rules = match string_patterns::string_rules(rules) {
+            Ok(rules) => rules,
+            Err(err) => {
+                message!("StringCases: {err}");
+                return reconstructed_ex;
+            }
+        };
+        let mut res = liste(vec![sym("List")]);
+        for (start, i, m) in string_patterns::scan(s, &rules) {
+            res.push(match &rules[i].1 {
+                Some(rhs) => evaluate(stack, ctx, &string_patterns::instantiate(rhs, &m)),
+                None => Expr::Str(s[start..m.end].to_string()),
+            });
+        }
+        return res;
+    } else if nh == sym("StringPosition") {
+        // (List start end) for every match, overlapping ones too
+        let [Expr::Str(s), pat] = &evaluated_args[..] else {
+            return reconstructed_ex;
+        };
+        return match StringPattern::compile(pat) {
+            Ok(p) => liste(
+                std::iter::once(sym("List"))
+                    .chain(string_patterns::positions(s, &p))
+                    .collect(),
+            ),
+            Err(err) => {
+                message!("StringPosition: {err}");
+                reconstructed_ex
+            }
+        };
+    } else if nh == sym("StringMatchQ") {
+        // a plain string pattern can use "*" for any run of characters
+        return match &evaluated_args[..] {
+            [Expr::Str(s), Expr::Str(p)] => sym(&format!("{}", glob_match(s, p))),
+            [Expr::Str(s), pat] => match StringPattern::compile(pat) {
+                Ok(p) => sym(&format!("{}", p.matches_whole(s))),
+                Err(err) => {
+                    message!("StringMatchQ: {err}");
+                    reconstructed_ex
+                }
+            },
+            _ => reconstructed_ex,
+        };
+    } else if nh == sym("set") {
+        // array part setting notes:
+        // (set l (Table false 9))
+        // simple single assignment case
+        // (set (Part l 1) 3)
+
+        // this is the more advanced case setting multiple at the same time
+        // (set (Part l (list 1 2 3)) (Table true 3))
+
+        // println!("evaluated_args: {:?}", evaluated_args);
+        let lhs = &eval_lhs_args(stack, ctx, &evaluated_args[0]);
+        let rhs = &evaluated_args[1];
+        if let Some(why) = assignment_blocked(ctx, lhs) {
+            message!("set: {why}");
+            return rhs.clone();
+        }
+        // (set (attrs f) (List ...)) replaces the attributes of f
+        if let Expr::List(ls) = lhs {
+            if ls.len() == 2 && ls[0] == sym("attrs") {
+                let Some(attrs) = attribute_names("set", rhs) else { return sym("$Failed") };
+                set_attributes(ctx, &ls[1], attrs);
+                return rhs.clone();
+            }
+        }
+
+        match lhs {
+            // ownvalue
+            Expr::Sym(ref s) => {
+                // the attributes and down values of s stay, only what it memoized is stale
+                let te = ctx.vars.entry(sym(s)).or_insert_with(TableEntry::new);
+                te.own = Some(evaluated_args[1].clone());
+                te.memo.clear();
+                ctx.bump_version();
+                return evaluated_args[1].clone();
+            }
+            // this is the down/subvalue case
+            Expr::List(ls) => {
+                let lhs_h = &ls[0];
+                match lhs_h {
+                    // lhs_h is the tag in this downvalue assignment
+                    Expr::Sym(_) => {
+                        // todo: implementing list inplace modification
+                        // if lhs_h == &sym("Part") {
+                        //     let part_lhs = &ls[1];
+                        //     let part_rhs = &ls[2];
+                        //     assert_eq!(length(rhs), length(part_rhs));
+                        // }
+                        //given
+                        // (set (f (pattern x (blank))) x)
+                        // we end up pushing
+                        // (rule_delayed (holdpattern expr[1]) expr[2])
+                        // (rule_delayed (holdpattern evaluated_args[0]) evaluated_args[1])
+                        // onto the downvalues of h (which is expected to have head list)
+                        let te: &mut TableEntry = ctx
+                            .vars
+                            .entry(lhs_h.clone())
+                            .or_insert_with(TableEntry::new);
+                        insert_down_value(te, lhs, rhs);
+                        ctx.bump_version();
+                        return rhs.clone();
+                    }
+                    // subvalue
+                    Expr::List(_) => {
+                        todo!("subvalues")
+                    }
+                    _ => {
+                        let h = head(lhs_h);
+                        message!("Tag {h} in {lhs} is Protected");
+                        return rhs.clone();
+                    }
+                }
+            }
+            _ => {
+                message!("set takes a symbol or list, got {}", lhs);
+                return sym("$Failed");
+            }
+        }
+    } else if nh == sym("setd") {
+        // println!("evaluated_args: {:?}", evaluated_args);
+        let lhs = &eval_lhs_args(stack, ctx, &evaluated_args[0]);
+        if let Some(why) = assignment_blocked(ctx, lhs) {
+            message!("setd: {why}");
+            return sym("$Failed");
+        }
+        if let Expr::List(ls) = lhs {
+            if ls.len() == 2 && ls[0] == sym("attrs") {
+                let Some(attrs) = attribute_names("setd", &evaluated_args[1]) else { return sym("$Failed") };
+                set_attributes(ctx, &ls[1], attrs);
+                return sym("Null");
+            }
+        }
+        match lhs {
+            Expr::Sym(ref s) => {
+                // the attributes and down values of s stay, only what it memoized is stale
+                let te = ctx.vars.entry(sym(s)).or_insert_with(TableEntry::new);
+                te.own = Some(evaluated_args[1].clone());
+                te.memo.clear();
+                ctx.bump_version();
+                return sym("Null");
+            }
+            // this is the down/subvalue case
+            Expr::List(ls) => {
+                let lhs_h = &ls[0];
+                match lhs_h {
+                    // lhs_h is the tag in this downvalue assignment
+                    Expr::Sym(_) => {
+                        let rhs = &evaluated_args[1];
+                        // onto the downvalues of h (which is expected to have head list)
+                        let te: &mut TableEntry = ctx
+                            .vars
+                            .entry(lhs_h.clone())
+                            .or_insert_with(TableEntry::new);
+                        insert_down_value(te, lhs, rhs);
+                        ctx.bump_version();
+                        return sym("Null");
+                    }
+                    // subvalue
+                    Expr::List(_) => {
+                        todo!("subvalues")
+                    }
+                    _ => panic!("hi"),
+                }
+            }
+            _ => {
+                message!("set takes a symbol or list, got {}", lhs);
+                return sym("$Failed");
+            }
+        }
+    } else if (nh == sym("own_values") || nh == sym("down_values")) && has_attribute(ctx, &evaluated_args[0], "ReadProtected") {
+        message!("{nh}: Symbol {} is ReadProtected", evaluated_args[0]);
+        return sym("$Failed");
+    } else if nh == sym("own_values") {
+        ctx.vars
+            .get(&evaluated_args[0])
+            .unwrap() // there is a bug here
+            .own
+            .clone()
+            .unwrap()
+        // todo!()
+    } else if nh == sym("down_values") {
+        ctx.vars.get(&evaluated_args[0]).unwrap().down.clone()
+    } else if nh == sym("sub_values") {
+        todo!()
+    } else if nh == sym("clear") {
+        match &evaluated_args[0] {
+            s @ Expr::Sym(_) if has_attribute(ctx, s, "Protected") => {
+                message!("clear: Symbol {s} is Protected");
+                return sym("$Failed");
+            }
+            Expr::Sym(_) => {
+                if let Some(te) = ctx.vars.get_mut(&evaluated_args[0]) {
+                    te.own = None;
+                    te.down = liste(vec![sym("List")]);
+                    te.sub = liste(vec![sym("List")]);
+                    te.memo.clear();
+                }
+                ctx.bump_version();
+                return sym("Null");
+            }
+            _ => {
+                message!("set takes a symbol");
+                return sym("$Failed");
+            }
+        }
+    } else if nh == sym("Evaluate") {
+        // only does anything as an argument of something that holds it, here it's already done
+        return match evaluated_args.as_slice() {
+            [x] => x.clone(),
+            _ => liste(std::iter::once(sym("Sequence")).chain(evaluated_args).collect()),
+        };
+    } else if nh == sym("ReleaseHold") {
+        // (ReleaseHold (Hold x)) is x, evaluated. only the outermost holds go
+        fn release(ex: &Expr) -> Expr {
+            let holds = ["Hold", "HoldForm", "HoldComplete", "hold", "hold_pattern"];
+            match ex {
+                Expr::List(ls) if ls.first().is_some_and(|h| holds.iter().any(|n| *h == sym(n))) => match &ls[1..] {
+                    [x] => x.clone(),
+                    args => liste(std::iter::once(sym("Sequence")).chain(args.iter().cloned()).collect()),
+                },
+                Expr::List(ls) => liste(ls.iter().map(release).collect()),
+                _ => ex.clone(),
+            }
+        }
+        return match evaluated_args.as_slice() {
+            [x] => release(x),
+            _ => {
+                message!("ReleaseHold takes 1 argument");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("N") {
+        return match evaluated_args.as_slice() {
+            // (N (r 1/2)) is (r 0.5), which may have rules of its own
+            [x] => {
+                let n = numeric(ctx, x);
+                evaluate(stack, ctx, &n)
+            }
+            _ => {
+                message!("N takes 1 argument");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("Attributes") || nh == sym("attrs") {
+        return match evaluated_args.as_slice() {
+            [s @ Expr::Sym(_)] => attributes_of(ctx, s).unwrap_or_else(|| liste(vec![sym("List")])),
+            _ => {
+                message!("{nh} takes a symbol");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("SetAttributes") || nh == sym("ClearAttributes") {
+        // (SetAttributes f HoldAll), (SetAttributes f (List HoldAll Listable)) or
+        // (SetAttributes (List f g) HoldAll). attributes already there stay
+        let [syms, attrs] = evaluated_args.as_slice() else {
+            message!("{nh} takes a symbol, or a list of them, and attributes");
+            return sym("$Failed");
+        };
+        let syms = match syms {
+            Expr::List(ls) => ls[1..].to_vec(),
+            s => vec![s.clone()],
+        };
+        let Some(attrs) = attribute_names(&nh.to_string(), attrs) else { return sym("$Failed") };
+        for s in &syms {
+            if !matches!(s, Expr::Sym(_)) {
+                message!("{nh}: {s} is not a symbol");
+                return sym("$Failed");
+            }
+            if let Some(why) = assignment_blocked(ctx, &liste(vec![sym("attrs"), s.clone()])) {
+                message!("{nh}: {why}");
+                return sym("$Failed");
+            }
+        }
+        for s in &syms {
+            let te = ctx.vars.entry(s.clone()).or_insert_with(TableEntry::new);
+            for a in &attrs {
+                if nh == sym("ClearAttributes") {
+                    te.attrs.retain(|old| old != a);
+                } else if !te.attrs.contains(a) {
+                    te.attrs.push(a.clone());
+                }
+            }
+        }
+        ctx.bump_version();
+        return sym("Null");
+    } else if nh == sym("Protect") || nh == sym("Unprotect") {
+        // gives the names of the symbols it changed
+        let protect = nh == sym("Protect");
+        let mut changed = vec![sym("List")];
+        for s in &evaluated_args {
+            let Expr::Sym(name) = s else {
+                message!("{nh}: {s} is not a symbol");
+                continue;
+            };
+            if has_attribute(ctx, s, "Locked") {
+                message!("{nh}: Symbol {s} is Locked");
+                continue;
+            }
+            if has_attribute(ctx, s, "Protected") == protect {
+                continue;
+            }
+            let te = ctx.vars.entry(s.clone()).or_insert_with(TableEntry::new);
+            if protect {
+                te.attrs.push(sym("Protected"));
+            } else {
+                te.attrs.retain(|a| *a != sym("Protected"));
+            }
+            ctx.bump_version();
+            changed.push(Expr::Str(name.to_string()));
+        }
+        return liste(changed);
+    } else if nh == sym("Trace") || nh == sym("TracePrint") {
+        // (Trace expr) or (Trace expr pat)
+        if evaluated_args.is_empty() || evaluated_args.len() > 2 {
+            message!("{nh} takes 1 or 2 arguments");
+            return sym("$Failed");
+        }
+        let print = nh == sym("TracePrint");
+        let outer = ctx
+            .tracer
+            .replace(Tracer::new(evaluated_args.get(1).cloned(), print));
+        let res = evaluate(stack, ctx, &evaluated_args[0]);
+        let tracer = std::mem::replace(&mut ctx.tracer, outer).unwrap();
+        if print {
+            return res;
+        }
+        return tracer.finish();
+    } else if nh == sym("Stack") {
+        // the last entry is this (Stack) call itself
+        let frames = &stack[..stack.len().saturating_sub(1)];
+        let mut res = vec![sym("List")];
+        match evaluated_args.first() {
+            None => res.extend(frames.iter().map(head)),
+            Some(pat) => res.extend(
+                frames
+                    .iter()
+                    .filter(|e| {
+                        my_match(
+                            (*e).clone(),
+                            pat.clone(),
+                            &vec![],
+                            &mut HashMap::new(),
+                            &mut HashMap::new(),
+                        )
+                    })
+                    .map(|e| liste(vec![sym("HoldForm"), e.clone()])),
+            ),
+        }
+        return liste(res);
+    } else if nh == sym("TimeConstrained") || nh == sym("MemoryConstrained") {
+        // (TimeConstrained expr secs [failexpr]), (MemoryConstrained expr bytes [failexpr])
+        if !(2..=3).contains(&evaluated_args.len()) {
+            message!("{nh} takes 2 or 3 arguments");
+            return sym("$Failed");
+        }
+        let limit = evaluate(stack, ctx, &evaluated_args[1]);
+        let (time, memory) = match (nh == sym("TimeConstrained"), &limit) {
+            (true, Expr::Int(n)) if n.to_u64().is_some() => {
+                (Some(Duration::from_secs(n.to_u64().unwrap())), None)
+            }
+            // too long to be a Duration is the same as no limit
+            (true, Expr::Real(r)) if r.into_inner() >= 0. => {
+                (Duration::try_from_secs_f64(r.into_inner()).ok(), None)
+            }
+            (false, Expr::Int(n)) if n.to_usize().is_some() => (None, n.to_usize()),
+            _ => {
+                message!("{nh}: the limit must be a non-negative number, got {limit}");
+                return reconstructed_ex;
+            }
+        };
+        ctx.push_constraint(time, memory);
+        let res = evaluate(stack, ctx, &evaluated_args[0]);
+        if ctx.pop_constraint() {
+            return evaluated_args
+                .get(2)
+                .cloned()
+                .unwrap_or_else(|| sym("$Aborted"));
+        }
+        return res;
+    } else if nh == sym("MemoryInUse") {
+        return Expr::Int(allocated_bytes().into());
+    } else if nh == sym("clear_cache") {
+        // (clear_cache f) forgets what f has memoized, (clear_cache) forgets everything
+        match evaluated_args.first() {
+            None => ctx.vars.values_mut().for_each(|te| te.memo.clear()),
+            Some(f @ Expr::Sym(_)) => {
+                if let Some(te) = ctx.vars.get_mut(f) {
+                    te.memo.clear();
+                }
+            }
+            Some(_) => {
+                message!("clear_cache takes a symbol");
+                return sym("$Failed");
+            }
+        }
+        return sym("Null");
+    } else if nh == sym("Plus") {
+        match (&evaluated_args[0], &evaluated_args[1]) {
+            (Expr::Int(a), Expr::Int(b)) => Expr::Int(a.add(b).into()),
+            (a, b) if exact_number(a).is_some() && exact_number(b).is_some() => {
+                let ((an, ad), (bn, bd)) = (exact_number(a).unwrap(), exact_number(b).unwrap());
+                rational(an * &bd + bn * &ad, ad * bd)
+            }
+            // see issue about 3.0 printing as `3`
+            // (Expr::Real(a), Expr::Real(b)) => Expr::Real(a + b),
+            _ => {
+                let reconstructed_ex = Expr::List(
+                    std::iter::once(nh.clone())
+                        .chain(evaluated_args.clone().to_owned())
+                        .collect(),
+                );
+                return reconstructed_ex;
+            }
+        }
+    } else if nh == sym("Times") {
+        match (&evaluated_args[0], &evaluated_args[1]) {
+            (Expr::Int(a), Expr::Int(b)) => Expr::Int(a.mul(b).into()),
+            (a, b) if exact_number(a).is_some() && exact_number(b).is_some() => {
+                let ((an, ad), (bn, bd)) = (exact_number(a).unwrap(), exact_number(b).unwrap());
+                rational(an * bn, ad * bd)
+            }
+            _ => {
+                return reconstructed_ex;
+            }
+        }
+    } else if nh == sym("Part") {
+        match &evaluated_args[0] {
+            Expr::List(ls) => match &evaluated_args[1] {
+                Expr::Int(i) => {
+                    let i = i.to_isize().unwrap();
+                    if i < 0 || i >= ls.len() as isize {
+                        message!("Part: index {} out of range", i);
+                        return reconstructed_ex;
+                    }
+                    return ls[i as usize].clone();
+                }
+                Expr::List(indices) => {
+                    let mut results = vec![sym("List")];
+                    for index in indices[1..].iter() {
+                        match index {
+                            Expr::Int(i) => {
+                                let i = i.to_isize().unwrap();
+                                if i < 0 || i >= ls.len() as isize {
+                                    message!("Part: index {} out of range", i);
+                                    return reconstructed_ex;
+                                }
+                                results.push(ls[i as usize].clone());
+                            }
+                            _ => {
+                                // println!("Part: each index in the list must be an integer");
+                                return reconstructed_ex;
+                            }
+                        }
+                    }
+                    return liste(results);
+                }
+                _ => {
+                    // println!("Part: index must be an integer or a list of integers");
+                    return reconstructed_ex;
+                }
+            },
+            _ => return reconstructed_ex,
+        }
+    } else if nh == sym("Out") || nh == sym("In") {
+        // (Out) and (Out -k) count back from the current line, what % and %% read as
+        let back = match &evaluated_args[..] {
+            [] => BigInt::from(-1),
+            [Expr::Int(k)] if k.is_negative() => k.clone(),
+            _ => return reconstructed_ex,
+        };
+        let n = ctx.line() + back;
+        if !n.is_positive() {
+            return reconstructed_ex;
+        }
+        return evaluate(stack, ctx, &liste(vec![nh.clone(), Expr::Int(n)]));
+    } else if nh == sym("Length") {
+        return length(&evaluated_args[0]);
+    } else if nh == sym("Get") {
+        if let Expr::Str(p) = &evaluated_args[0] {
+            // (Get "file.wl") reads infix syntax, or say which with (Get "file" "infix")
+            let syntax = match evaluated_args.get(1) {
+                Some(Expr::Str(name)) => match Syntax::from_name(name) {
+                    Some(syntax) => syntax,
+                    None => {
+                        message!("Get: unknown syntax {name}, expected \"sexp\" or \"infix\"");
+                        return sym("$Failed");
+                    }
+                },
+                _ => Syntax::for_path(Path::new(&p)),
+            };
+            match run_file_with(ctx, Path::new(&p), syntax) {
+                Ok(res) => return res,
+                Err(err) => {
+                    message!("Get: cannot open {p}: {err}");
+                    return sym("$Failed");
+                }
+            }
+        } else {
+            message!("Get takes an Expr::String");
+            return sym("$Failed");
+        }
+    } else if nh == sym("BeginPackage") {
+        // (BeginPackage "p`") or (BeginPackage "p`" (List "q`" ...)) to load what it needs first
+        let needs: Vec<String> = match evaluated_args.get(1) {
+            Some(Expr::List(ls)) => ls[1..].iter().filter_map(|n| if let Expr::Str(n) = n { Some(n.clone()) } else { None }).collect(),
+            _ => vec![],
+        };
+        return match evaluated_args.first() {
+            Some(Expr::Str(context)) => contexts::begin_package(ctx, context, &needs),
+            _ => {
+                message!("BeginPackage takes a context name like \"Name`\"");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("EndPackage") {
+        return contexts::end_package(ctx);
+    } else if nh == sym("Begin") {
+        return match evaluated_args.first() {
+            Some(Expr::Str(context)) => contexts::begin(ctx, context),
+            _ => {
+                message!("Begin takes a context name like \"`Private`\"");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("End") {
+        return contexts::end(ctx);
+    } else if nh == sym("Needs") {
+        return match evaluated_args.as_slice() {
+            [Expr::Str(context)] => contexts::needs_package(ctx, context, None),
+            [Expr::Str(context), Expr::Str(file)] => contexts::needs_package(ctx, context, Some(file)),
+            _ => {
+                message!("Needs takes a context name like \"Name`\" and maybe a file");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("Context") {
+        return match evaluated_args.first() {
+            Some(Expr::Sym(s)) => Expr::Str(contexts::context_of(ctx, s)),
+            None => Expr::Str(contexts::current(ctx)),
+            Some(ex) => {
+                message!("Context: {ex} is not a symbol");
+                sym("$Failed")
+            }
+        };
+    } else if nh == sym("Save") || nh == sym("DumpSave") {
+        // (Save "file" f g) appends f and g (and what they use) as source, (DumpSave "file") writes
+        // everything as binary, (DumpSave "file" f) just f and what it uses
+        let Some(Expr::Str(path)) = evaluated_args.first() else {
+            message!("{nh} takes a file name and symbols");
+            return sym("$Failed");
+        };
+        let mut syms = vec![];
+        for a in &evaluated_args[1..] {
+            match a {
+                Expr::Sym(_) => syms.push(a.clone()),
+                Expr::Str(name) => syms.push(sym(name)),
+                _ => {
+                    message!("{nh}: {a} is not a symbol");
+                    return sym("$Failed");
+                }
+            }
+        }
+        let written = if nh == sym("Save") {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut f| std::io::Write::write_all(&mut f, save::save_source(ctx, &syms).as_bytes()))
+        } else {
+            let only = (!syms.is_empty()).then_some(&syms[..]);
+            std::fs::write(path, save::dump(ctx, only))
+        };
+        if let Err(err) = written {
+            message!("{nh}: cannot write {path}: {err}");
+            return sym("$Failed");
+        }
+        return sym("Null");
+    } else if nh == sym("Print") {
+        let text: Vec<String> = evaluated_args
+            .iter()
+            .map(|a| match a {
+                Expr::Str(s) => s.clone(),
+                a => format_expr(a, Form::of_context(ctx)),
+            })
+            .collect();
+        print_out(&text.concat());
+        return sym("Null");
+    } else if nh == sym("Map") {
+        // todo level spec
+        // honestly i was hoping i could do this in cas3, not builtin but just to get things going
+
+        let mut res = liste(vec![head(&evaluated_args[1])]);
+        let f = &evaluated_args[0];
+        let mapargs = &evaluated_args[1];
+
+        for (_i, arg) in mapargs[1..].iter().enumerate() {
+            let fi = liste(vec![f.clone(), arg.clone()]);
+            res.push(fi);
+        }
+        return res;
+    } else if nh == sym("NestList") {
+        let f = &evaluated_args[0];
+        let x = &evaluated_args[1];
+        let n = &evaluated_args[2];
+        let mut res = list(vec!["List"]);
+        res.push(x.clone());
+        if let Expr::Int(count) = n {
+            for _i in 0..count.to_i64().unwrap() {
+                let fi = evaluate(
+                    stack,
+                    ctx,
+                    &liste(vec![f.clone(), res.last().unwrap().clone()]),
+                );
+                res.push(fi);
+            }
+            return res;
+        } else {
+            return reconstructed_ex;
+        }
+    } else if nh == sym("Table") {
+        let table_body = &evaluated_args[0];
+
+        // todo: test if this works implemented in cas3 code
+        if evaluated_args.len() == 1 {
+            return table_body.clone();
+        }
+        let spec = evaluate(stack, ctx, &evaluated_args[1].clone());
+
+        if evaluated_args.len() == 2 {
+            // if int, we copy n times
+            if let Expr::Int(n) = &spec {
+                return Expr::List(
+                    std::iter::once(sym("List"))
+                        .chain((0..n.to_i64().unwrap()).map(|_| evaluated_args[0].clone()))
+                        .collect(),
+                );
+            } else if let Expr::List(ls) = &spec {
+                // this is the place where we want to make helpers for
+                // the "standard Wolfram Language iteration specification"
+
+                let mut res = liste(vec![sym("List")]);
+                let var = &ls[1];
+
+                // this specific case is {i, imax}
+                if ls.len() == 3 {
+                    if ls[0] != sym("List") {
+                        dbg!("invalid range specification. need a list");
+                        // return reconstructed_ex;
+                    }
+                    if let Expr::Int(imax) = &ls[2] {
+                        for i in 1..=imax.to_i64().unwrap() {
+                            let mut e_i = liste(vec![sym("replace_all")]);
+                            e_i.push(table_body.clone());
+                            let local_rule =
+                                liste(vec![sym("rule"), var.clone(), Expr::Int(i.into())]); // (rule var iter)
+                            e_i.push(local_rule);
+                            res.push(e_i);
+                        }
+                        return res;
+                    } else if let Expr::List(vals) = &ls[2] {
+                        // this is the sequence case where you just
+                        // Table[expr,{i,{i1,i2,…}}]
+
+                        if head(&ls[2]) != sym("List") {
+                            dbg!(
+                                "invalid range specification. need a list of values, gave {}",
+                                &ls[2]
+                            );
+                            // return reconstructed_ex;
+                        } else {
+                            for val in &vals[1..] {
+                                let mut e_i = liste(vec![sym("replace_all")]);
+                                e_i.push(table_body.clone());
+                                let local_rule =
+                                    liste(vec![sym("rule"), var.clone(), val.clone()]); // (rule var iter)
+                                e_i.push(local_rule);
+                                res.push(e_i);
+                            }
+                            return res;
+                        }
+                    } else {
+                        dbg!("need an int or list for imax, reals not supported in iteration specification yet");
+                        return sym("$Failed");
+                    }
+                } else if ls.len() == 4 {
+                    // this is {i, imin, imax}
+                    if let (Expr::Int(imin), Expr::Int(imax)) = (&ls[2], &ls[3]) {
+                        for i in imin.to_i64().unwrap()..=imax.to_i64().unwrap() {
+                            let mut e_i = liste(vec![sym("replace_all")]);
+                            e_i.push(table_body.clone());
+                            let local_rule =
+                                liste(vec![sym("rule"), var.clone(), Expr::Int(i.into())]); // (rule var iter)
+                            e_i.push(local_rule);
+                            res.push(e_i);
+                        }
+                        return res;
+                    } else {
+                        // this is the sequence case where you just
+                        // Table[expr,{i,{i1,i2,…}}]
+                        dbg!("need an int or list for imax, reals not supported in iteration specification yet");
+                        return sym("$Failed");
+                    }
+                } else if ls.len() == 5 {
+                    // this is {i, imin, imax, di}
+                    // this is {i, imin, imax}
+                    if let [Expr::Int(imin), Expr::Int(imax), Expr::Int(di)] = &ls[2..] {
+                        let rng = imin.to_i64().unwrap()..=imax.to_i64().unwrap();
+                        let iter = rng.step_by(di.to_i64().unwrap() as usize);
+                        for i in iter {
+                            let mut e_i = liste(vec![sym("replace_all")]);
+                            e_i.push(table_body.clone());
+                            let local_rule =
+                                liste(vec![sym("rule"), var.clone(), Expr::Int(i.into())]); // (rule var iter)
+                            e_i.push(local_rule);
+                            res.push(e_i);
+                        }
+                        return res;
+                    } else {
+                        // this is the sequence case where you just
+                        // Table[expr,{i,{i1,i2,…}}]
+                        dbg!("need an int or list for imax, reals not supported in iteration specification yet");
+                        return sym("$Failed");
+                    }
+                }
+            }
+        }
+
+        let range_lists = &evaluated_args[1..]; //.clone().reverse();
+                                                // Table[ f[i,j], {i, imin, imax}, {j, jmin, jmax}]
+                                                // Table[Table[f[i,j], {j, jmin, jmax}], {i, imin, imax}]
+                                                // let mut ex = liste(vec![sym("Table")]);
+                                                // ex.push(table_body.clone());
+
+        let mut nested_table = table_body.clone();
+        for range in range_lists.iter().rev() {
+            let mut new_table = liste(vec![sym("Table"), nested_table.clone()]);
+            new_table = match &mut new_table {
+                Expr::List(ref mut v) => {
+                    v.push(range.clone());
+                    new_table.clone()
+                }
+                _ => panic!("Unexpected expression type"),
+            };
+            nested_table = new_table;
+        }
+        return nested_table;
+    } else if nh == sym("Join") {
+        if !matches!(&evaluated_args[0], Expr::List(ls)) {
+            message!("Join joins lists dummy!");
+            return reconstructed_ex;
+        }
+
+        let ha = head(&evaluated_args[0]);
+
+        let mut res = vec![ha.clone()];
+        for e in evaluated_args {
+            if ha != head(&e) {
+                message!("Join: heads of arguments are not all the same");
+                return reconstructed_ex;
+            }
+            if let Expr::List(ls) = e {
+                res.append(&mut ls[1..].to_vec());
+            } else {
+                //fix
+                return sym("Failed");
+            }
+        }
+        return liste(res);
+    } else if nh == sym("Timing") {
+        let t1 = Instant::now();
+        let res = evaluate(stack, ctx, &evaluated_args[0]);
+        let dt = t1.elapsed(); // Capture the elapsed time
+
+        // Convert duration to seconds
+        let elapsed_seconds = dt.as_secs() as f64 + dt.subsec_nanos() as f64 * 1e-9;
+        // NotNan
+        liste(vec![
+            sym("List"),
+            Expr::Real(NotNan::new(elapsed_seconds).unwrap()),
+            res,
+        ])
+    } else if nh == sym("Export") {
+        let dst = &evaluated_args[0];
+        let ex = &evaluated_args[1];
+        // println!("ex: {:?}", ex);
+        let Some(m) = unpack_mat(ex.clone()) else {
+            message!("Export: second argument must be a matrix of {{r, g, b}} reals");
+            return sym("$Failed");
+        };
+        let filename = match dst {
+            Expr::Str(s) => s,
+            _ => {
+                message!("Export: first argument must be a string");
+                return sym("$Failed");
+            }
+        };
+        create_svg_from_colors(m, filename, 50);
+        captured(|o| o.exported.push(filename.into()));
+        return sym("Null");
+    } else {
+        return Expr::List(
+            std::iter::once(nh.clone())
+                .chain(evaluated_args.clone().to_owned())
+                .collect(),
+        );
+    }
+}
+
+/// evaluates `expr` to a fixed point.
+/// `stack` holds the chain of expressions currently being evaluated, outermost first
+pub fn evaluate(stack: &mut Expr, ctx: &mut Context2, expr: &Expr) -> Expr {
+    match expr {
+        Expr::Int(_) | Expr::Real(_) | Expr::Str(_) => return expr.clone(),
+        // nothing was redefined since we last saw this list reach a fixed point
+        Expr::List(ls) if ls.is_evaluated_at(ctx.version) => return expr.clone(),
+        _ => {}
+    }
+    if ctx.should_stop() {
+        return sym("$Aborted");
+    }
+    // only calls count towards the depth, so the head and symbols of the call
+    // that hit the limit can still be looked at
+    if let Expr::List(_) = expr {
+        if let Some(limit) = limit_value(ctx, "$RecursionLimit", DEFAULT_RECURSION_LIMIT) {
+            if stack.len() >= limit {
+                let at = location_note(stack, expr);
+                message!("$RecursionLimit::reclim: Recursion depth of {limit} exceeded during evaluation of {expr}{at}.");
+                let held = liste(vec![sym("Hold"), expr.clone()]);
+                // so the caller re-evaluating its arguments doesn't wrap this again
+                if let Expr::List(ls) = &held {
+                    ls.mark_evaluated(ctx.version);
+                }
+                return held;
+            }
+        }
+    }
+    let start_version = ctx.version;
+    stack.push(expr.clone());
+    if let Some(tracer) = &mut ctx.tracer {
+        tracer.frames.push(vec![]);
+    }
+
+    let ex = evaluate_steps(stack, ctx, expr);
+
+    if let Some(tracer) = &mut ctx.tracer {
+        tracer.close_frame();
+    }
+    stack.pop();
+    // only trust the fixed point if no definitions changed while we were getting there
+    if let Expr::List(ls) = &ex {
+        if ctx.version == start_version && !ctx.should_stop() {
+            ls.mark_evaluated(start_version);
+        }
+    }
+    ex
+}
+
+fn evaluate_steps(stack: &mut Expr, ctx: &mut Context2, expr: &Expr) -> Expr {
+    let mut ex = expr.clone();
+    let mut last_ex = None;
+    let iteration_limit = limit_value(ctx, "$IterationLimit", DEFAULT_ITERATION_LIMIT);
+    let mut iterations = 0;
+
+    loop {
+        // when nothing changed both sides share one node, so this is a pointer compare
+        if Some(&ex) == last_ex.as_ref() {
+            // If the expression hasn't changed, break the loop.
+            break;
+        }
+        if ctx.should_stop() {
+            return sym("$Aborted");
+        }
+        iterations += 1;
+        if iteration_limit.is_some_and(|limit| iterations > limit) {
+            let limit = iteration_limit.unwrap();
+            message!("$IterationLimit::itlim: Iteration limit of {limit} exceeded.");
+            return liste(vec![sym("Hold"), ex]);
+        }
+        // println!("evaluating: {}", ex);
+        if let Some(top) = stack.last_mut() {
+            *top = ex.clone();
+        }
+        if let Some(tracer) = &mut ctx.tracer {
+            tracer.record(&ex);
+        }
+
+        last_ex = Some(ex.clone());
+
+        match &ex {
+            Expr::Int(_) | Expr::Real(_) | Expr::Str(_) => {
+                break;
+            }
+            Expr::Sym(ref s) => {
+                if let Some(rule) = get_ownvalue(ctx, sym(s)) {
+                    ex = rule;
+                } else {
+                    break;
+                }
+            }
+            Expr::List(ref ls) => {
+                let h;
+                if let Some(sh) = ls.first() {
+                    h = sh;
+                } else {
+                    message!("Expr::List needs a head");
+                    return sym("$Failed");
+                }
+                // step 5
+                let mut nh = evaluate(stack, ctx, h);
+
+                // step 6
+                // the use of a separate stack here is questionable
+                // also to note that when we use "contains" on it, we also do a comparison against the head ("List"), which is wrong
+                // but for practical purposes shouldn't cause a problem
+
+                // the most important next step here is making sure that (attrs set) works correctly
+                // note that something here causes SO
+
+                // a potential problem here is that nh is not necesarily an atom
+                // nh can be Expr::List
+
+                // 3a (we might be able to just replace_all((attrs h) (down_values attrs)))
+                // nvm 3a is less easy to see that a match wastn found
+
+                // println!("hi");
+
+                // 1. assume h::Sym
+                // 2. get dvs of attr
+                // 3. find a matching rule in dvs to (attrs h)
+                // if no matching rule found, return "(List)"
+
+                // #16 - attributes live on the symbol's table entry, no matching needed
+                let nh_attrs = match ctx.vars.get(&nh) {
+                    Some(te) if matches!(nh, Expr::Sym(_)) => te.attrs.clone(),
+                    _ => vec![],
+                };
+
+                // println!("nh_attrs: {:?}", nh_attrs);
+                // assert!(head(&nh_attrs) == sym("List"));
+                // HoldAllComplete: the arguments are left exactly as they are, not even
+                // Evaluate, Unevaluated or Sequence get looked at
+                let complete = nh_attrs.contains(&sym("HoldAllComplete"));
+
+                // step 7
+                let mut evaluated_args = vec![];
+                // which arguments came wrapped in Unevaluated
+                let mut unevaluated = vec![];
+
+                // hold_mask entry with a zero means "don't hold"
+                let mut hold_mask = vec![complete; ls.len() - 1];
+
+                // idk if it should be else ifs
+                if nh_attrs.contains(&sym("HoldAll")) {
+                    hold_mask.fill(true);
+                }
+                if nh_attrs.contains(&sym("HoldFirst")) {
+                    hold_mask[0] = true;
+                }
+                if nh_attrs.contains(&sym("HoldRest")) {
+                    hold_mask[1..].fill(true);
+                }
+                // println!("hold_mask: {:?}", hold_mask);
+
+                for (i, p) in ls[1..].iter().enumerate() {
+                    let wrapped = |h: &str| !complete && head(p) == sym(h);
+                    if wrapped("Unevaluated") && p.len() == 2 {
+                        // passed along as is, and put back if nothing uses it
+                        evaluated_args.push(p[1].clone());
+                        unevaluated.push(true);
+                        continue;
+                    }
+                    // (Evaluate x) gets evaluated even where the head holds
+                    if hold_mask[i] && !wrapped("Evaluate") {
+                        evaluated_args.push(p.clone());
+                    } else {
+                        let ev = evaluate(stack, ctx, p);
+
+                        evaluated_args.push(ev);
+                    }
+                    unevaluated.push(false);
+                }
+
+                if !complete && !nh_attrs.contains(&sym("SequenceHold")) {
+                    let mut arg_idx = 0;
+                    while arg_idx < evaluated_args.len() {
+                        if head(&evaluated_args[arg_idx]) == sym("Sequence") && !unevaluated[arg_idx] {
+                            let seq_args: Vec<_> = evaluated_args[arg_idx][1..].to_vec();
+                            let n = seq_args.len();
+                            evaluated_args.splice(arg_idx..arg_idx + 1, seq_args);
+                            unevaluated.splice(arg_idx..arg_idx + 1, std::iter::repeat_n(false, n));
+                        } else {
+                            arg_idx += 1;
+                        }
+                    }
+                }
+                let reconstructed_ex = share_unchanged(
+                    &ex,
+                    std::iter::once(nh.clone())
+                        .chain(evaluated_args.clone().to_owned())
+                        .collect(),
+                );
+                // println!("reconstructed_ex: {}", reconstructed_ex);
+
+                let memoize = nh_attrs.contains(&sym("Memoize"));
+                if memoize {
+                    if let Some(hit) = ctx
+                        .vars
+                        .get(&nh)
+                        .and_then(|te| te.memo.get(&reconstructed_ex))
+                    {
+                        ex = hit.clone();
+                        continue;
+                    }
+                }
+
+                // step 14: apply user defined downvalues and subvalues
+                let exprime = match nh.clone() {
+                    // we dont need to panic here "abc"[foo] doesn't
+                    Expr::Int(_) | Expr::Real(_) | Expr::Str(_) => {
+                        // note: WL doesn't give note in this case
+                        message!("head must be a symbol, got {nh}{}", location_note(stack, &ex));
+                        return reconstructed_ex;
+                    }
+                    // this is the down_value case, bcause the head
+                    Expr::Sym(_) => {
+                        let te = ctx.vars.entry(nh.clone()).or_insert_with(TableEntry::new);
+                        let dvs = &te.down;
+                        // println!("looking for user defined down_values for {} -> {}", nh, dvs);
+
+                        // should this be replace_all? or replace_repeated?
+
+                        let exprime = replace_all(&reconstructed_ex, dvs);
+                        // println!("before: {}", reconstructed_ex);
+                        // println!("after: {}", exprime);
+                        exprime
+                    }
+                    // subvalue
+                    Expr::List(_) => reconstructed_ex.clone(),
+                };
+
+                if unevaluated.contains(&true) && exprime == reconstructed_ex {
+                    // no rule took the Unevaluated arguments. a builtin still might, and if it
+                    // doesn't they go back in their wrappers and that's the result
+                    let applied = internal_functions_apply(stack, ctx, nh.clone(), evaluated_args.clone());
+                    if applied != reconstructed_ex {
+                        ex = applied;
+                        continue;
+                    }
+                    let mut items = reconstructed_ex.to_vec();
+                    for (i, _) in unevaluated.iter().enumerate().filter(|(_, u)| **u) {
+                        items[i + 1] = liste(vec![sym("Unevaluated"), items[i + 1].clone()]);
+                    }
+                    ex = liste(items);
+                    break;
+                }
+
+                // im not sure if this is correct, but it seems necesary,
+                // if we found a matching downvalue rule, then we need to re-evaluate the expression after replacement
+                if ex != exprime {
+                    ex = if memoize && exprime != reconstructed_ex {
+                        memoize_result(stack, ctx, &nh, reconstructed_ex, &exprime)
+                    } else {
+                        exprime
+                    };
+                    continue;
+                }
+
+                // note now that ex is not necesarily a List anymore
+                // so if we still have a list, then we do step 15, and apply internal down/subvalues
+
+                match ex {
+                    Expr::List(_) => {}
+                    _ => continue,
+                }
+                nh = head(&ex);
+                evaluated_args = ex[1..].to_vec();
+                // this corresponds to step 15 in Wagner's main eval loop section
+                // where we apply internal/builtin down and subvalues
+                let applied = internal_functions_apply(stack, ctx, nh.clone(), evaluated_args);
+                // builtins that don't apply hand back a fresh copy, keep the node we already have
+                if applied != ex {
+                    ex = if memoize {
+                        memoize_result(stack, ctx, &nh, ex.clone(), &applied)
+                    } else {
+                        applied
+                    };
+                }
+            }
+        }
+    }
+    // println!("exiting evaluate: {}", ex);
+    ex
+}
+
+fn named_rebuild_all(expr: Expr, map: &HashMap<Expr, Expr>) -> Expr {
+    // First, check if the entire expression exists in the map and replace it if it does
+    if let Some(replacement) = map.get(&expr) {
+        return replacement.clone();
+    }
+
+    // If the expression is not in the map, proceed with the recursion
+    match &expr {
+        Expr::List(list) => {
+            // Recursively rebuild all sub-expressions in the list
+            let new_list: Vec<Expr> = list
+                .iter()
+                .map(|e| named_rebuild_all(e.clone(), map))
+                .collect();
+            share_unchanged(&expr, new_list)
+        }
+        _ => expr,
+    }
+}
+
+/// rebuilds `orig` from `new_items`, unless every item is still the one `orig` already holds.
+/// keeping the old node means untouched subtrees stay shared and later equality checks hit the pointer fast path
+fn share_unchanged(orig: &Expr, new_items: Vec<Expr>) -> Expr {
+    let unchanged = orig.len() == new_items.len()
+        && orig
+            .iter()
+            .zip(new_items.iter())
+            .all(|(a, b)| a.ptr_eq(b) || (is_atom(a) && a == b));
+    if unchanged {
+        orig.clone()
+    } else {
+        liste(new_items)
+    }
+}
+
+// this adjusts lookups to the position map based on the sequences found in the current list
+fn final_pos_map_rebuild(pos: Vec<usize>, pat: Expr, pos_map: &HashMap<Vec<usize>, Expr>) -> Expr {
+    // println!("pos: {:?}, pat: {}", pos, pat);
+    if let Some(replacement) = pos_map.get(&pos) {
+        return replacement.clone();
+    }
+
+    match pat {
+        Expr::List(es) => {
+            let mut new_es = vec![];
+            // note in the case of blank_null_seq, this can be negative
+            let mut offset: isize = 0;
+            for (i, e) in es.iter().enumerate() {
+                let mut new_pos = pos.clone();
+                let pos_in_list = i as isize + offset;
+                // println!("{i}, pos_in_list: {} offset:{offset}", pos_in_list);
+                new_pos.push(pos_in_list as usize);
+                let new_e = final_pos_map_rebuild(new_pos, e.clone(), pos_map);
+                if head(&new_e) == sym("Sequence") {
+                    offset += new_e.len() as isize - 2; // its -2 becasue 1 for the head and 1 for the original (blank_seq) object
+                }
+                // so pos[0..i-1] is the position of the current list
+                // we want to then look for all the position keys that have thsi as a prefix and adjust them
+                // but i think we only want to adjust on a final rebuild but not in the middle.
+                // when we are at the end of a list we are confirming the map works but haven't rebuilt anything, so our pat is still the original
+                // println!("{i}, new_e: {} offest: {offset}", new_e);
+                new_es.push(new_e);
+            }
+            liste(new_es)
+        }
+        _ => pat,
+    }
+}
+
+/// to fix pos_map. as you iterate es, and you keep track of the offset for the current list.
+/// then you want to adjust lookups into the posmap based on the position - offset.
+/// the reason we still need this is for intermediate rebuilds where we have already spliced former sequences
+fn pos_map_rebuild(pos: Vec<usize>, pat: Expr, pos_map: &HashMap<Vec<usize>, Expr>) -> Expr {
+    if let Some(replacement) = pos_map.get(&pos) {
+        return replacement.clone();
+    }
+
+    match pat {
+        Expr::List(es) => {
+            let mut new_es = vec![];
+            // note in the case of blank_null_seq, this can be negative
+            for (i, e) in es.iter().enumerate() {
+                let mut new_pos = pos.clone();
+                new_pos.push(i);
+                let new_e = pos_map_rebuild(new_pos, e.clone(), pos_map);
+                new_es.push(new_e);
+            }
+            liste(new_es)
+        }
+        _ => pat,
+    }
+}
+
+fn splice_sequences(expr: Expr) -> Expr {
+    match expr {
+        Expr::List(mut list) => {
+            let mut i = 0;
+            while i < list.len() {
+                list[i] = splice_sequences(list[i].clone());
+                i += 1;
+            }
+
+            let mut new_list = Vec::new();
+            let mut i = 0;
+            while i < list.len() {
+                let item = list[i].clone();
+                if let Expr::List(ref sublist) = item {
+                    if let Some(Expr::Sym(head)) = sublist.first() {
+                        if head == "Sequence" {
+                            new_list.extend_from_slice(&sublist[1..]);
+                            i += 1;
+                            continue;
+                        }
+                    }
+                }
+                new_list.push(item);
+                i += 1;
+            }
+            liste(new_list)
+        }
+        _ => expr,
+    }
+}
+
+fn rebuild_and_splice(
+    pat: Expr,
+    pos: &Vec<usize>,
+    pos_map: &HashMap<Vec<usize>, Expr>,
+    named_map: &HashMap<Expr, Expr>,
+) -> Expr {
+    splice_sequences(named_rebuild_all(
+        pos_map_rebuild(pos.clone(), pat, pos_map),
+        named_map,
+    ))
+}
+
+fn final_rebuild_and_splice(
+    pat: Expr,
+    pos: &Vec<usize>,
+    pos_map: &HashMap<Vec<usize>, Expr>,
+    named_map: &HashMap<Expr, Expr>,
+) -> Expr {
+    splice_sequences(named_rebuild_all(
+        final_pos_map_rebuild(pos.clone(), pat, pos_map),
+        named_map,
+    ))
+}
+
+// we assume that p has a blank object for a head
+fn is_blank_match(e: Expr, p: Expr) -> bool {
+    if let Expr::List(ps) = p {
+        if ps.len() == 2 {
+            let p_head = &ps[1];
+            if p_head == &head(&e) {
+                true
+            } else {
+                false
+            }
+        } else {
+            true
+        }
+    } else {
+        panic!("is_blank_match needs a list for p")
+    }
+}
+
+fn my_match(
+    ex: Expr,
+    mut pat: Expr,
+    pos: &Vec<usize>,
+    pos_map: &mut HashMap<Vec<usize>, Expr>,
+    named_map: &mut HashMap<Expr, Expr>,
+) -> bool {
+    let pattern_expr = pat.clone();
+    if head(&pattern_expr) == sym("hold_pattern") {
+        pat = pattern_expr[1].clone();
+    }
+    // if head(&pat) != sym("attrs") && pat != sym("attrs") && ex != sym("matchq") {
+    //     println!("M: {pos:?} | {ex} | {pat} | {pos_map:?} | {named_map:?}");
+    // }
+
+    let pat_syms = vec![sym("blank"), sym("blank_seq"), sym("blank_null_seq")];
+    if head(&pat) == sym("Alternatives") {
+        for p in &pat[1..] {
+            if my_match(ex.clone(), p.clone(), pos, pos_map, named_map) {
+                pos_map.insert(pos.clone(), p.clone());
+                return true;
+            }
+        }
+        return false;
+    }
+
+    match (ex.clone(), pat.clone()) {
+        (Expr::List(es), Expr::List(ps)) => {
+            // this first block determines if the pattern matches the entire list
+            if ps[0] == sym("pattern") {
+                if let Some(from_map) = named_map.get(&pat) {
+                    return &ex == from_map;
+                } else {
+                    if is_blank_match(ex.clone(), ps[2].clone()) {
+                        named_map.insert(pat.clone(), ex.clone());
+                        return true;
+                    }
+                }
+            } else if pat_syms.contains(&ps[0]) {
+                if is_blank_match(ex.clone(), pat.clone()) {
+                    pos_map.insert(pos.clone(), ex);
+                    return true;
+                }
+            }
+
+            let mut new_pos = pos.clone();
+            new_pos.push(0); // we are at the head
+            if !my_match(es[0].clone(), ps[0].clone(), &new_pos, pos_map, named_map) {
+                return false;
+            }
+
+            'outer: for (i, pi) in ps.iter().enumerate().skip(1) {
+                let mut new_pos = pos.clone();
+                new_pos.push(i); // we are at the head
+                if head(pi) == sym("pattern") {
+                    // println!("in pattern pi ");
+                    if let Some(_from_map) = named_map.get(&pi) {
+                        // here is an example that contradicts the below i think
+                        //  (setd (Times (pattern x (blank Sym)) (pattern x (blank Sym))) (Pow x 2))
+                        println!("we should have rebuilt to remove this i think");
+                    }
+                    let b = &pi[2];
+                    let bt = &b[0];
+                    // let p_name = &pi[1];
+                    if bt == &sym("blank_seq") {
+                        for j in 1..=es[1..].len() {
+                            let mut elts = vec![sym("Sequence")];
+                            // im pretty sure this is not needed
+                            if i + j > es.len() {
+                                // println!("breaking news!");
+                                break 'outer;
+                            }
+                            for seq_e in &es[i..i + j] {
+                                if b.len() == 2 {
+                                    let b_head = &b[1];
+                                    if b_head != &head(seq_e) {
+                                        break;
+                                    }
+                                }
+                                elts.push(seq_e.clone());
+                            }
+                            let seq = liste(elts);
+                            named_map.insert(pi.clone(), seq.clone());
+
+                            let new_pat = rebuild_and_splice(pat.clone(), &pos, pos_map, named_map);
+                            // println!("new_pat in bs: at iter {j} {new_pat} {seq}");
+                            if my_match(ex.clone(), new_pat, pos, pos_map, named_map) {
+                                break 'outer;
+                            }
+                        }
+                    } else if bt == &sym("blank_null_seq") {
+                        for j in 0..=es[1..].len() {
+                            let mut elts = vec![sym("Sequence")];
+                            // im pretty sure this is not needed
+                            if i + j > es.len() {
+                                // println!("breaking news!");
+                                break 'outer;
+                            }
+                            for seq_e in &es[i..i + j] {
+                                if b.len() == 2 {
+                                    let b_head = &b[1];
+                                    if b_head != &head(seq_e) {
+                                        break;
+                                    }
+                                }
+                                elts.push(seq_e.clone());
+                            }
+                            let seq = liste(elts);
+                            named_map.insert(pi.clone(), seq.clone());
+
+                            let new_pat = rebuild_and_splice(pat.clone(), &pos, pos_map, named_map);
+                            // println!("new_pat in bs: at iter {j} {new_pat} {seq}");
+                            if my_match(ex.clone(), new_pat, pos, pos_map, named_map) {
+                                break 'outer;
+                            }
+                        }
+                    } else {
+                        if i >= es.len() {
+                            break 'outer;
+                        }
+                        // named blank case
+                        if !my_match(es[i].clone(), ps[i].clone(), &new_pos, pos_map, named_map) {
+                            break 'outer;
+                        }
+                    }
+                } else if head(pi) == sym("blank_seq") {
+                    for j in 1..=es[1..].len() {
+                        let mut elts = vec![sym("Sequence")];
+                        // im pretty sure this is not needed
+                        if i + j > es.len() {
+                            // println!("breaking news!");
+                            break 'outer;
+                        }
+                        for seq_e in &es[i..i + j] {
+                            if pi.len() == 2 {
+                                let b_head = &pi[1];
+                                if b_head != &head(seq_e) {
+                                    break;
+                                }
+                            }
+                            elts.push(seq_e.clone());
+                        }
+                        let seq = liste(elts);
+                        pos_map.insert(new_pos.clone(), seq.clone());
+
+                        let new_pat = rebuild_and_splice(pat.clone(), &pos, pos_map, named_map);
+                        // println!("new_pat in bs: at iter {j} {new_pat}");
+                        let mut copy = pos_map.clone();
+                        // this is to avoid double application of a pos rule
+                        copy.remove(&new_pos);
+                        // if my_match(ex.clone(), pat.clone(), pos, &mut copy) {
+                        if my_match(ex.clone(), new_pat, pos, &mut copy, named_map) {
+                            pos_map.clear();
+                            pos_map.extend(copy);
+
+                            pos_map.insert(new_pos.clone(), seq.clone());
+
+                            break 'outer;
+                        } else {
+                            // break 'outer;
+                            // i think we need to revert pos_map to whatever it was before this my_match call
+                        }
+                    }
+                } else if head(pi) == sym("blank_null_seq") {
+                    for j in 0..=es[1..].len() {
+                        let mut elts = vec![sym("Sequence")];
+                        // im pretty sure this is not needed
+                        if i + j > es.len() {
+                            println!("breaking news!");
+                            break 'outer;
+                        }
+                        for seq_e in &es[i..i + j] {
+                            if pi.len() == 2 {
+                                let b_head = &pi[1];
+                                if b_head != &head(seq_e) {
+                                    break;
+                                }
+                            }
+                            elts.push(seq_e.clone());
+                        }
+                        let seq = liste(elts);
+                        pos_map.insert(new_pos.clone(), seq.clone());
+
+                        let new_pat = rebuild_and_splice(pat.clone(), &pos, pos_map, named_map);
+                        // println!("new_pat in bs: at iter {j} {new_pat}");
+                        let mut copy = pos_map.clone();
+                        // this is to avoid double application of a pos rule
+                        copy.remove(&new_pos);
+                        // if my_match(ex.clone(), pat.clone(), pos, &mut copy) {
+                        if my_match(ex.clone(), new_pat, pos, &mut copy, named_map) {
+                            pos_map.clear();
+                            pos_map.extend(copy);
+
+                            pos_map.insert(new_pos.clone(), seq.clone());
+
+                            break 'outer;
+                        } else {
+                            // break 'outer;
+                            // i think we need to revert pos_map to whatever it was before this my_match call
+                        }
+                    }
+                } else {
+                    if i >= es.len() {
+                        break 'outer;
+                    }
+                    if !my_match(es[i].clone(), ps[i].clone(), &new_pos, pos_map, named_map) {
+                        break 'outer;
+                    }
+                }
+            }
+            let final_pat = final_rebuild_and_splice(pat.clone(), &pos, pos_map, named_map);
+            // let final_pat = rebuild_and_splice(pat.clone(), &pos, pos_map, named_map);
+
+            // todo remove these conditions when we fast path attribute lookup
+            // if head(&pat) != sym("attrs") && pat != sym("attrs") {
+            //     println!("final comparison: POS: {pos:?} | PAT: {pat} | NEW_PAT: {final_pat} | EX: {ex} || pos {pos_map:?} || named {named_map:?}");
+            // }
+            if final_pat == ex {
+                return true;
+            }
+            false
+        }
+        (_, Expr::List(ps)) => {
+            if ps[0] == sym("pattern") {
+                if let Some(from_map) = named_map.get(&pat) {
+                    return &ex == from_map;
+                } else {
+                    if is_blank_match(ex.clone(), ps[2].clone()) {
+                        named_map.insert(pat.clone(), ex.clone());
+                        true
+                    } else {
+                        false
+                    }
+                }
+            } else if pat_syms.contains(&ps[0]) {
+                if is_blank_match(ex.clone(), pat.clone()) {
+                    pos_map.insert(pos.clone(), ex);
+                    true
+                } else {
+                    false
+                }
+            } else {
+                false
+            }
+        }
+        _ => ex == pat,
+    }
+}
+
+pub fn bindings_to_rules(bindings: &HashMap<String, Expr>) -> Expr {
+    let mut rules = liste(vec![sym("List")]);
+    for (name, binding) in bindings.clone() {
+        rules.push(liste(vec![sym("rule"), sym(&name), binding.clone()]));
+    }
+    rules
+}
+
+pub fn pat_bindings_to_rules(bindings: &HashMap<Expr, Expr>) -> Expr {
+    let mut rules = liste(vec![sym("List")]);
+    for (pat, binding) in bindings.clone() {
+        if let Expr::List(ps) = pat {
+            let p_name = &ps[1]; // (pattern x (blank))
+            rules.push(liste(vec![
+                sym("rule"),
+                p_name.clone(),
+                binding.clone(),
+            ]));
+        }
+    }
+    rules
+}
+
+pub fn norm_rules(rules: &Expr) -> Vec<Expr> {
+    if head(rules) == sym("rule") || head(rules) == sym("rule_delayed") {
+        return vec![rules.clone()];
+    } else {
+        assert_eq!(head(rules), sym("List"));
+        return rules.clone()[1..].to_vec();
+    };
+}
+
+pub fn replace(expr: &Expr, rules: &Expr) -> Expr {
+    let rules_list = norm_rules(rules);
+
+    for rule in rules_list {
+        let pos = vec![];
+        let mut pos_map = HashMap::new();
+        let mut named_map = HashMap::new();
+        assert!(head(&rule) == sym("rule") || head(&rule) == sym("rule_delayed"));
+        if my_match(
+            expr.clone(),
+            rule[1].clone(),
+            &pos,
+            &mut pos_map,
+            &mut named_map,
+        ) {
+            let mut new_expr = rule[2].clone();
+            new_expr = replace_all(&new_expr, &pat_bindings_to_rules(&named_map));
+
+            return new_expr;
+        }
+    }
+    expr.clone()
+}
+
+pub fn replace_all(expr: &Expr, rules: &Expr) -> Expr {
+    let rules_list = norm_rules(rules);
+    for rule in rules_list {
+        let pos = vec![];
+        let mut pos_map = HashMap::new();
+        let mut named_map = HashMap::new();
+        assert!(head(&rule) == sym("rule") || head(&rule) == sym("rule_delayed"));
+        if my_match(
+            expr.clone(),
+            rule[1].clone(),
+            &pos,
+            &mut pos_map,
+            &mut named_map,
+        ) {
+            return replace(expr, &rule);
+        }
+    }
+
+    match expr {
+        Expr::List(list) => {
+            let new_list: Vec<Expr> = list
+                .iter()
+                .map(|sub_expr| replace_all(sub_expr, rules))
+                .collect();
+            share_unchanged(expr, new_list)
+        }
+        _ => replace(expr, rules),
+    }
+}
+
+pub const DEFAULT_MAX_ITERATIONS: usize = 1 << 16;
+
+/// applies `rules` until nothing changes, or `max_iterations` passes have been made
+/// (`None` means keep going forever). on hitting the limit we return where we got to
+pub fn replace_repeated(
+    ctx: &mut Context2,
+    expr: &Expr,
+    rules: &Expr,
+    max_iterations: Option<usize>,
+) -> Expr {
+    let mut current_expr = expr.clone();
+    let mut i = 0;
+    loop {
+        if ctx.should_stop() {
+            return sym("$Aborted");
+        }
+        let new_expr = replace_all(&current_expr, rules);
+        if new_expr == current_expr {
+            break;
+        }
+        current_expr = new_expr;
+        i += 1;
+        if max_iterations.is_some_and(|max| i >= max) {
+            message!("ReplaceRepeated::rrlim: Exiting after {expr} scanned {i} times.");
+            break;
+        }
+    }
+    current_expr
+}
+
+pub fn startup_attrs(ctx: &mut Context2) {
+    for (name, attrs) in [
+        ("hold_pattern", &["HoldAll"][..]),
+        ("attrs", &["HoldAll"]),
+        ("rule_delayed", &["HoldRest", "SequenceHold"]),
+        ("set", &["HoldFirst", "SequenceHold"]),
+        ("down_values", &["HoldAll"]),
+        ("Hold", &["HoldAll"]),
+        ("HoldForm", &["HoldAll"]),
+        ("HoldComplete", &["HoldAllComplete"]),
+        ("Unevaluated", &["HoldAllComplete"]),
+        ("Trace", &["HoldAll"]),
+        ("TimeConstrained", &["HoldAll"]),
+        ("MemoryConstrained", &["HoldAll"]),
+        ("TracePrint", &["HoldAll"]),
+        ("Function", &["HoldAll"]),
+        ("CompoundExpression", &["HoldAll"]),
+        ("Save", &["HoldRest"]),
+        ("DumpSave", &["HoldRest"]),
+        ("Context", &["HoldFirst"]),
+        ("Protect", &["HoldAll"]),
+        ("Unprotect", &["HoldAll"]),
+        ("Attributes", &["HoldAll"]),
+        ("SetAttributes", &["HoldFirst"]),
+        ("ClearAttributes", &["HoldFirst"]),
+    ] {
+        set_attributes(ctx, &sym(name), attrs.iter().map(|a| sym(a)).collect());
+    }
+}
+
+/// makes every system symbol Protected. runs once the prelude is in, since it adds rules to
+/// builtins like Plus
+pub fn protect_system(ctx: &mut Context2) {
+    for name in contexts::system_symbols(ctx) {
+        let s = sym(&name);
+        let te = ctx.vars.entry(s).or_insert_with(TableEntry::new);
+        if !te.attrs.contains(&sym("Protected")) {
+            te.attrs.push(sym("Protected"));
+        }
+    }
+    ctx.bump_version();
+}
+
+#[derive(Helper, Validator)]
+pub struct ReplHelper {
+    #[rustyline(Validator)]
+    validator: MatchingBracketValidator,
+    colored_prompt: String,
+    /// what tab completes to, refreshed before each prompt
+    names: Vec<String>,
+    /// symbols with definitions, which the highlighter shows differently from undefined ones
+    defined: HashSet<String>,
+    syntax: Syntax,
+}
+
+impl Default for ReplHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        Self {
+            colored_prompt: "".to_owned(),
+            validator: MatchingBracketValidator::new(),
+            names: vec![],
+            defined: HashSet::new(),
+            syntax: Syntax::Sexp,
+        }
+    }
+
+    /// picks up definitions and `$Syntax` changes since the last prompt
+    pub fn update(&mut self, ctx: &Context2) {
+        self.names = help::symbol_names(ctx);
+        self.defined = help::defined_names(ctx).collect();
+        self.syntax = Syntax::of_context(ctx);
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> Result<(usize, Vec<Pair>)> {
+        let start = help::word_start(line, pos, self.syntax);
+        let candidates = help::complete(&self.names, &line[start..pos])
+            .into_iter()
+            .map(|n| Pair { display: n.clone(), replacement: n.clone() })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+/// a builtin's signature shown after the cursor. it's only there to read, so right arrow
+/// doesn't paste it in
+pub struct ArgHint(String);
+
+impl Hint for ArgHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = ArgHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> Option<ArgHint> {
+        if pos < line.len() {
+            return None;
+        }
+        help::hint(line, self.syntax).map(ArgHint)
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        default: bool,
+    ) -> Cow<'b, str> {
+        if default {
+            Borrowed(&self.colored_prompt)
+        } else {
+            Borrowed(prompt)
+        }
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Owned("\x1b[1m".to_owned() + hint + "\x1b[m")
+    }
+
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        Owned(highlight::highlight(line, pos, self.syntax, &self.defined))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // colors depend on the whole line, so redraw on every change
+        true
+    }
+}
+
+pub fn run_file(ctx: &mut Context2, filepath: &Path) -> Result<Expr> {
+    run_file_with(ctx, filepath, Syntax::for_path(filepath))
+}
+
+pub fn run_file_with(ctx: &mut Context2, filepath: &Path, syntax: Syntax) -> Result<Expr> {
+    // let file = File::open(filepath)?;
+    // let reader = BufReader::new(file);
+    let bytes = std::fs::read(filepath)?;
+    // what DumpSave writes
+    if bytes.starts_with(save::DUMP_PREFIX) {
+        return match save::load(ctx, &bytes) {
+            Ok(_) => Ok(sym("Null")),
+            Err(err) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err).into()),
+        };
+    }
+    let file_contents = String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    // i dont love this because it's ambigious whether or not something failed in reading the file or sth
+    // or if the last expr in the file was a setd or something that returns a Null
+    let mut res = sym("Null");
+    // a syntax error only costs the form it's in, the rest of the file still runs
+    let (exprs, errors) = parse_source(&filepath.display().to_string(), &file_contents, syntax);
+    for err in &errors {
+        message!("{err}");
+    }
+    // for line in reader.lines() {
+    for expr in exprs {
+        // match line {
+        // Ok(content) => {
+        //     if content.starts_with("//") || content.starts_with(";") || content.is_empty() {
+        //         continue;
+        //     }
+        // if let Ok(ex) = &expr_parser::Expr(&content) {
+        let expr = contexts::resolve(ctx, &expr);
+        let mut stack = liste(vec![]);
+        res = evaluate(&mut stack, ctx, &expr);
+        // } else {
+        // eprintln!("Error parsing a line: {:?}", content);
+        // }
+    }
+    // Err(error) => {
+    // eprintln!("Error reading a line: {:?}", error);
+    // }
+    // }
+    // }
+
+    Ok(res)
+}
+
+/// where the REPL keeps the lines you've typed: `$CAS3_HISTORY`, or `~/.cas3_history`
+pub fn history_path() -> std::path::PathBuf {
+    if let Some(p) = std::env::var_os("CAS3_HISTORY") {
+        return p.into();
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => Path::new(&home).join(".cas3_history"),
+        None => "cas3_history.txt".into(),
+    }
+}
+
+pub fn run(
+    mut rl: rustyline::Editor<ReplHelper, rustyline::history::FileHistory>,
+    mut ctx: Context2,
+    history: &Path,
+) -> Result<()> {
+    loop {
+        let i = ctx.line();
+        let prompt = format!("(In {}) := ", i);
+        let helper = rl.helper_mut().expect("No helper");
+        helper.colored_prompt = format!("\x1b[1;32m{prompt}\x1b[0m");
+        helper.update(&ctx);
+
+        let line = rl.readline(&prompt); // read
+        match line {
+            Ok(l) => {
+                rl.add_history_entry(l.as_str()).unwrap(); // history
+                                                           // saving every line (even if slow, just until its more stable)
+                if let Err(err) = rl.save_history(history) {
+                    println!("couldn't save history to {}: {err}", history.display());
+                }
+
+                // ?f and ??f describe f instead of evaluating anything
+                if let Some(q) = l.trim().strip_prefix('?') {
+                    let (full, name) = match q.strip_prefix('?') {
+                        Some(name) => (true, name),
+                        None => (false, q),
+                    };
+                    println!("{}", help::describe(&mut ctx, name.trim(), full));
+                    continue;
+                }
+
+                let (exs, errors) = parse_source(&format!("(In {i})"), &l, Syntax::of_context(&ctx));
+
+                if !errors.is_empty() {
+                    for err in errors {
+                        message!("{err}");
+                    }
+                } else {
+                    for expr in exs {
+                        let expr = contexts::resolve(&mut ctx, &expr);
+                        let i = ctx.line();
+                        let mut stack = liste(vec![]);
+                        ctx.abort.take();
+                        let mut res = evaluate(&mut stack, &mut ctx, &expr);
+                        if ctx.abort.take() {
+                            res = sym("$Aborted");
+                        }
+                        ctx.record_history(&i, &expr, &res);
+
+                        let text = format_expr(&contexts::shorten(&ctx, &res), Form::of_context(&ctx));
+                        if text.contains('\n') {
+                            // 2d output gets its own lines so the label doesn't skew it
+                            println!("\x1B[1m(Out {i}) =\n{text}\x1B[0m");
+                        } else {
+                            println!("\x1B[1m(Out {i}) = {text}\x1B[0m");
+                        }
+
+                    }
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                continue;
+            }
+            Err(ReadlineError::Eof) => {
+                break;
+            }
+            Err(err) => {
+                println!("Error: {:?}", err);
+            }
+        }
+    } // loop
+    Ok(())
+}
+
+/// limits applied to everything a `Kernel` evaluates. `None` means unlimited
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelSettings {
+    pub time_limit: Option<Duration>,
+    /// heap growth allowed per evaluation, in bytes. needs `CountingAlloc` installed
+    pub memory_limit: Option<usize>,
+    pub recursion_limit: Option<usize>,
+    pub iteration_limit: Option<usize>,
+}
+
+impl Default for KernelSettings {
+    fn default() -> Self {
+        Self {
+            time_limit: None,
+            memory_limit: None,
+            recursion_limit: Some(DEFAULT_RECURSION_LIMIT),
+            iteration_limit: Some(DEFAULT_ITERATION_LIMIT),
+        }
+    }
+}
+
+/// cas3 as a library: a `Context2` plus the limits every evaluation runs under.
+/// an evaluation that runs out of time or memory gives back `$Aborted`
+pub struct Kernel {
+    pub ctx: Context2,
+    settings: KernelSettings,
+}
+
+impl Kernel {
+    pub fn new(settings: KernelSettings) -> Self {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        protect_system(&mut ctx);
+        Self::with_context(ctx, settings)
+    }
+
+    /// a kernel over a context that's already set up, like one `Cli::prepare` loaded the prelude into
+    pub fn with_context(ctx: Context2, settings: KernelSettings) -> Self {
+        let mut kernel = Self { ctx, settings };
+        kernel.set_settings(kernel.settings.clone());
+        kernel
+    }
+
+    pub fn settings(&self) -> &KernelSettings {
+        &self.settings
+    }
+
+    /// recursion and iteration limits are stored as `$RecursionLimit` and `$IterationLimit`,
+    /// so code running in the kernel sees (and can change) them
+    pub fn set_settings(&mut self, settings: KernelSettings) {
+        for (name, limit) in [
+            ("$RecursionLimit", settings.recursion_limit),
+            ("$IterationLimit", settings.iteration_limit),
+        ] {
+            let value = limit.map_or_else(|| sym("Infinity"), |n| Expr::Int(n.into()));
+            self.ctx
+                .vars
+                .entry(sym(name))
+                .or_insert_with(TableEntry::new)
+                .own = Some(value);
+        }
+        self.ctx.bump_version();
+        self.settings = settings;
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Expr {
+        let expr = contexts::resolve(&mut self.ctx, expr);
+        self.evaluate_resolved(&expr)
+    }
+
+    /// `evaluate` for an expression whose names `contexts::resolve` already went through
+    pub(crate) fn evaluate_resolved(&mut self, expr: &Expr) -> Expr {
+        let limited = self.settings.time_limit.is_some() || self.settings.memory_limit.is_some();
+        if limited {
+            self.ctx
+                .push_constraint(self.settings.time_limit, self.settings.memory_limit);
+        }
+        let mut stack = liste(vec![]);
+        let res = evaluate(&mut stack, &mut self.ctx, expr);
+        if limited && self.ctx.pop_constraint() {
+            return sym("$Aborted");
+        }
+        res
+    }
+
+    pub fn run_file(&mut self, filepath: &Path) -> Result<Expr> {
+        run_file(&mut self.ctx, filepath)
+    }
+}
+
+pub fn evalparse(s: &str) -> Expr {
+    let ex = expr_parser::Expr(s);
+    match ex {
+        Ok(expr) => {
+            let mut ctx = Context2::new();
+            let mut stack = liste(vec![]);
+            evaluate(&mut stack, &mut ctx, &expr)
+        }
+        Err(err) => panic!("Failed to parse: {s}: {err}"),
+    }
+}
+
+pub fn ctx_evalparse(ctx: &mut Context2, s: &str) -> Expr {
+    let ex = expr_parser::Expr(s);
+    match ex {
+        Ok(expr) => {
+            let expr = contexts::resolve(ctx, &expr);
+            let mut stack = liste(vec![]);
+            evaluate(&mut stack, ctx, &expr)
+        }
+        Err(err) => panic!("Failed to parse: {s}: {err}"),
+    }
+}
+
+#[cfg(test)]
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parser() {
+        assert_eq!(parse("(f (* *hi* *)  x)"), parse("(f x)"));
+    }
+
+    #[test]
+    fn infix_parser() {
+        let infix = |s: &str| infix_parser::Expr(s).unwrap();
+        for (i, s) in [
+            ("f[x, y]", "(f x y)"),
+            ("f[x][y]", "((f x) y)"),
+            ("{a, {b}, {}}", "(List a (List b) (List))"),
+            ("f[x_Int, xs__, ys___, _]", "(f (pattern x (blank Int)) (pattern xs (blank_seq)) (pattern ys (blank_null_seq)) (blank))"),
+            ("a -> b -> c", "(rule a (rule b c))"),
+            ("f[x_] :> x + 1", "(rule_delayed (f (pattern x (blank))) (Plus x 1))"),
+            ("x /. a -> b", "(replace_all x (rule a b))"),
+            ("x //. {a -> b, c :> d}", "(replace_repeated x (List (rule a b) (rule_delayed c d)))"),
+            ("1 + 2 * 3 ^ 4 ^ 5", "(Plus 1 (Times 2 (Power 3 (Power 4 5))))"),
+            ("a - b - 2", "(Plus (Plus a (Times -1 b)) -2)"),
+            ("-x^2", "(Times -1 (Power x 2))"),
+            ("a / b", "(Times a (Power b -1))"),
+            ("a == b && !c || d", "(Or (And (Equal a b) (Not c)) d)"),
+            ("a === b", "(sameq a b)"),
+            ("a = b; c := d;", "(CompoundExpression (set a b) (setd c d) Null)"),
+            ("# + #2 &", "(Function (Plus (Slot 1) (Slot 2)))"),
+            ("f @ g @ x", "(f (g x))"),
+            ("xs[[2]]", "(Part xs 2)"),
+            ("ReplaceAll[x, Rule[True, \"s\"]] (* hi *)", "(replace_all x (rule true \"s\"))"),
+        ] {
+            assert_eq!(infix(i), parse(s), "{i}");
+        }
+        assert_eq!(
+            parse_expressions("f[x]\ng[y]", Syntax::Infix).unwrap(),
+            parse_expressions("(f x) (g y)", Syntax::Sexp).unwrap()
+        );
+        assert_eq!(Syntax::for_path(Path::new("lang/startup.sexp")), Syntax::Sexp);
+        assert_eq!(Syntax::for_path(Path::new("foo.wl")), Syntax::Infix);
+
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        run_file(&mut ctx, Path::new("lang/attrs.sexp")).unwrap();
+        let mut infix_eval = |s: &str| evaluate(&mut liste(vec![]), &mut ctx, &infix(s));
+        assert_eq!(infix_eval("(#1 + #2 &)[1, 2]"), parse("3"));
+        assert_eq!(infix_eval("Function[{x, y}, y - x][1, 3]"), parse("2"));
+        assert_eq!(infix_eval("Function[x, Function[y, x + y]][1][2]"), parse("3"));
+        assert_eq!(infix_eval("Function[x, Function[x, x]][1][2]"), parse("2"));
+        assert_eq!(infix_eval("f[x_] := x + 1; f[2]"), parse("3"));
+        assert_eq!(infix_eval("{g[1], 2} /. g[x_] -> x"), parse("(List 1 2)"));
+        assert_eq!(infix_eval("(f[#, (# &)] &)[1]"), parse("(f 1 (Function (Slot 1)))"));
+        assert_eq!(infix_eval("1 + 1 == 2"), sym("true"));
+        assert_eq!(infix_eval("9007199254740993 == 9007199254740992"), sym("false"));
+        assert_eq!(infix_eval("{3/4 == 6/8, 1/2 == 0.5, 1/3 == 2, 2 == 2.}"), parse("(List true true false true)"));
+    }
+
+    #[test]
+    fn parse_errors_and_recovery() {
+        let src = "(set a 1)\n(f (g x)) )\n(set b \"unterminated)\n(set c 3) (* trailing comment *)\n";
+        let (exprs, errors) = parse_source("bad.sexp", src, Syntax::Sexp);
+        assert_eq!(
+            exprs,
+            vec![parse("(set a 1)"), parse("(f (g x))"), parse("(set c 3)")]
+        );
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (2, 11));
+        // an unterminated string runs to the end of what was given up on
+        assert_eq!((errors[1].line, errors[1].column), (3, 22));
+        assert_eq!(errors[1].message, "expected a closing quote");
+        assert_eq!(
+            errors[0].to_string(),
+            "error: expected one of \"(\", an atom\n --> bad.sexp:2:11\n  |\n2 | (f (g x)) )\n  |           ^"
+        );
+
+        // lists remember where they came from
+        assert_eq!(source_location(&exprs[1]), Some("bad.sexp:2:1".to_string()));
+        assert_eq!(source_location(&exprs[1][1]), Some("bad.sexp:2:4".to_string()));
+        assert_eq!(source_location(&parse("(f x)")), None);
+
+        let (exprs, errors) = parse_source("bad.wl", "f[x]\ng[x,,]\n  h[\ny + 1\n", Syntax::Infix);
+        assert_eq!(exprs, vec![parse("(f x)"), parse("(Plus y 1)")]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 5));
+        assert_eq!(source_location(&exprs[1]), Some("bad.wl:4:1".to_string()));
+
+        // nothing but whitespace and comments is fine
+        assert_eq!(parse_source("empty", "  (* nothing *)\n\n", Syntax::Sexp), (vec![], vec![]));
+
+        // a source is kept while something read from it is, copies included
+        let Expr::List(ls) = &exprs[1] else { unreachable!() };
+        let span = ls.span().unwrap();
+        let mut copy = exprs[1].clone();
+        copy[1] = sym("changed");
+        drop(exprs);
+        assert_eq!(span.describe(), Some("bad.wl:4:1".to_string()));
+        drop(copy);
+        assert_eq!(span.describe(), None);
+        let (exprs, _) = parse_source("(In 1)", "x", Syntax::Sexp);
+        assert_eq!(exprs, vec![sym("x")]);
+        assert!(SOURCES.with(|s| s.borrow().last().unwrap().is_none()));
+    }
+
+    #[test]
+    fn number_literals() {
+        let real = |r: f64| Expr::Real(NotNan::new(r).unwrap());
+        for (s, e) in [
+            ("-1.5", real(-1.5)),
+            (".5", real(0.5)),
+            ("1.5e-10", real(1.5e-10)),
+            ("1.5*^3", real(1500.0)),
+            ("1*^10", parse("10000000000")),
+            ("2*^-3", parse("(Rational 1 500)")),
+            ("16^^FF", parse("255")),
+            ("-2^^101", parse("-5")),
+            ("6/8", parse("(Rational 3 4)")),
+            ("-6/3", parse("-2")),
+        ] {
+            assert_eq!(expr_parser::Expr(s).map_err(|e| e.to_string()), Ok(e), "{s}");
+        }
+        for bad in [".", "-.", "1/0", "37^^1", "2^^3", "1e999", "1.5x"] {
+            assert!(expr_parser::Expr(bad).is_err(), "{bad}");
+        }
+        let infix = |s: &str| infix_parser::Expr(s).unwrap();
+        assert_eq!(infix("f[3/4, -3/4, 16^^ff, 1.5*^-3]"), parse("(f 3/4 -3/4 255 0.0015)"));
+        assert_eq!(infix("x/4 + 2^3/4"), parse("(Plus (Times x (Power 4 -1)) (Times (Power 2 3) (Power 4 -1)))"));
+
+        // reals keep their point when printed, so everything reads back as what it was
+        for s in ["3.0", "0.1", "1e-10", "1.5e300", "(Rational -3 4)", "123456789012345678901234567890"] {
+            assert_eq!(parse(s).to_string(), s);
+            assert_eq!(parse(&parse(s).to_string()), parse(s));
+        }
+        for (s, i) in [("3.0", "3."), ("1e-10", "1.*^-10"), ("-3/4", "-3/4")] {
+            assert_eq!(format::input_form(&parse(s)), i);
+            assert_eq!(infix_parser::Expr(i).unwrap(), parse(s));
+        }
+        // on its own a literal prints as its plain value
+        for (s, printed) in [("16^^FF", "255"), ("1*^10", "10000000000"), ("2*^-3", "(Rational 1 500)")] {
+            assert_eq!(parse(s).to_string(), printed);
+            assert_eq!(parse(printed), parse(s));
+        }
+        // inside a list it prints the way it was written, without changing what it equals
+        let s = "(f 16^^FF 1*^10 2*^-3 3/4 -1.50 7 (g 0.5e1))";
+        assert_eq!(parse(s).to_string(), s);
+        assert_eq!(parse(s), parse("(f 255 10000000000 (Rational 1 500) (Rational 3 4) -1.5 7 (g 5.0))"));
+        let mut ctx = Context2::new();
+        assert_eq!(ctx_evalparse(&mut ctx, "(Hold (f 16^^FF))").to_string(), "(Hold (f 16^^FF))");
+        // changing the list forgets it
+        assert_eq!(ctx_evalparse(&mut ctx, "(List 16^^FF (Plus 1 1))").to_string(), "(List 255 2)");
+
+        let mut ctx = Context2::new();
+        let mut ev = |s: &str| ctx_evalparse(&mut ctx, s);
+        assert_eq!(ev("(Plus 1/2 1/3)"), parse("5/6"));
+        assert_eq!(ev("(Plus 1/2 1/2)"), parse("1"));
+        assert_eq!(ev("(Times 2/3 (Times 3 1/4))"), parse("1/2"));
+    }
+
+    #[test]
+    fn repl_help() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        ctx_evalparse(&mut ctx, "(set (myfun 1) one)");
+        ctx_evalparse(&mut ctx, "(setd (myfun (pattern x (blank))) (Plus x 1))");
+        ctx_evalparse(&mut ctx, "(set myval 5)");
+
+        let names = help::symbol_names(&ctx);
+        assert_eq!(help::complete(&names, "myf"), vec!["myfun"]);
+        assert!(help::complete(&names, "blank").contains(&&"blank_seq".to_string()));
+        assert_eq!(help::word_start("(pattern x (blank In", 20, Syntax::Sexp), 18);
+        assert_eq!(help::word_start("f[x_In", 6, Syntax::Infix), 4);
+
+        assert_eq!(help::enclosing_head("(f (StringTake \"(a\" ", Syntax::Sexp), Some("StringTake"));
+        assert_eq!(help::enclosing_head("f[StringTake[s, ", Syntax::Infix), Some("StringTake"));
+        assert_eq!(help::enclosing_head("(g (h x) ", Syntax::Sexp), Some("g"));
+        assert_eq!(help::hint("(StringTake ", Syntax::Sexp).unwrap(), "  (StringTake s n) | (StringTake s (List m n))");
+        assert_eq!(help::hint("StringTake[", Syntax::Infix).unwrap(), "  StringTake[s, n] | StringTake[s, {m, n}]");
+        assert_eq!(help::hint("(myfun ", Syntax::Sexp), None);
+
+        let short = help::describe(&mut ctx, "myfun", false);
+        assert!(short.contains("down values:\n  (myfun 1)\n  (myfun (pattern x (blank)))\n  (??myfun shows the definitions)"), "{short}");
+        let full = help::describe(&mut ctx, "myfun", true);
+        assert!(full.contains("  (setd (myfun 1) one)\n  (setd (myfun (pattern x (blank))) (Plus x 1))"), "{full}");
+        assert!(help::describe(&mut ctx, "myval", false).contains("value: 5"));
+        assert!(help::describe(&mut ctx, "Trace", false).contains("attributes: (List HoldAll)"));
+        assert!(help::describe(&mut ctx, "nothing_here", false).contains("has no definitions"));
+        assert_eq!(help::describe(&mut ctx, "myf*", false), "myfun");
+    }
+
+    #[test]
+    fn syntax_highlighting() {
+        use highlight::Style::*;
+        let defined: HashSet<String> = ["f".to_string()].into();
+        let styled = |line: &str, syntax| -> Vec<(String, highlight::Style)> {
+            let (spans, _) = highlight::styles(line, syntax, &defined);
+            spans.into_iter().filter(|(r, _)| !line[r.clone()].trim().is_empty()).map(|(r, s)| (line[r].to_string(), s)).collect()
+        };
+        let owned = |v: &[(&str, highlight::Style)]| v.iter().map(|(t, s)| (t.to_string(), *s)).collect::<Vec<_>>();
+
+        assert_eq!(
+            styled("(f g 1.5 \"s\" (* hi *) (pattern x (blank)) (Plus 3/4))", Syntax::Sexp),
+            owned(&[
+                ("(", Plain), ("f", Defined), ("g", Undefined), ("1.5", Number), ("\"s\"", Str), ("(* hi *)", Comment),
+                ("(", Plain), ("pattern", Pattern), ("x", Undefined), ("(", Plain), ("blank", Pattern), (")", Plain), (")", Plain),
+                ("(", Plain), ("Plus", Builtin), ("3/4", Number), (")", Plain), (")", Plain),
+            ])
+        );
+        // an extra closer, an unclosed string, a number that isn't one
+        assert_eq!(styled("(f))", Syntax::Sexp)[3], (")".to_string(), Invalid));
+        assert_eq!(styled("(f \"ab", Syntax::Sexp)[2], ("\"ab".to_string(), Invalid));
+        assert_eq!(styled("(f 1.5x)", Syntax::Sexp)[2].1, Invalid);
+        // unclosed brackets are just unfinished
+        assert!(styled("(f (g", Syntax::Sexp).iter().all(|(_, s)| *s != Invalid));
+
+        assert_eq!(
+            styled("f[x_Int, #2] /. {a -> 16^^FF}", Syntax::Infix),
+            owned(&[
+                ("f", Defined), ("[", Plain), ("x_Int", Pattern), (",", Plain), ("#2", Pattern), ("]", Plain), ("/", Plain), (".", Plain),
+                ("{", Plain), ("a", Undefined), ("-", Plain), (">", Plain), ("16^^FF", Number), ("}", Plain),
+            ])
+        );
+        assert_eq!(styled("f[x}", Syntax::Infix)[3].1, Invalid);
+        // balanced but doesn't parse: red from where the parser gave up
+        let bad = styled("f[x,,y]", Syntax::Infix);
+        assert_eq!(bad.iter().map(|(_, s)| *s == Invalid).collect::<Vec<_>>(), [false, false, false, false, true, true, true]);
+        assert_eq!(styled("a +", Syntax::Infix)[1].1, Invalid);
+
+        let colored = highlight::highlight("(f x)", 5, Syntax::Sexp, &defined);
+        assert_eq!(colored, "\x1b[1;34m(\x1b[0mf \x1b[34mx\x1b[0m\x1b[1;34m)\x1b[0m");
+    }
+
+    #[test]
+    fn in_out_history() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        // what the repl does for each input
+        let line = |ctx: &mut Context2, s: &str| {
+            let ex = parse(s);
+            let n = ctx.line();
+            let res = evaluate(&mut liste(vec![]), ctx, &ex);
+            ctx.record_history(&n, &ex, &res);
+            res
+        };
+        line(&mut ctx, "(Plus 1 2)");
+        // strings with quotes used to break the print-and-reparse
+        line(&mut ctx, r#""say \"hi\"""#);
+        assert_eq!(line(&mut ctx, "%"), parse(r#""say \"hi\"""#));
+        assert_eq!(line(&mut ctx, "(List %%% (Out -2) %1 (In 1))"), parse(r#"(List 3 "say \"hi\"" 3 3)"#));
+        assert_eq!(ctx.line(), BigInt::from(5));
+        assert_eq!(ctx_evalparse(&mut ctx, "(down_values Out)").len(), 5);
+
+        // $Line can be moved, and the next input is numbered from it
+        line(&mut ctx, "(set $Line 10)");
+        assert_eq!(ctx.line(), BigInt::from(11));
+        line(&mut ctx, "eleven");
+        assert_eq!(ctx_evalparse(&mut ctx, "(Out 11)"), sym("eleven"));
+
+        // only the last $HistoryLength lines are kept
+        line(&mut ctx, "(set $HistoryLength 2)");
+        line(&mut ctx, "thirteen");
+        assert_eq!(ctx_evalparse(&mut ctx, "(down_values Out)").len(), 3);
+        assert_eq!(ctx_evalparse(&mut ctx, "(Out 11)"), parse("(Out 11)"));
+        assert_eq!(ctx_evalparse(&mut ctx, "(In 13)"), sym("thirteen"));
+
+        assert_eq!(infix_parser::Expr("% + %% + %3").unwrap(), parse("(Plus (Plus (Out) (Out -2)) (Out 3))"));
+    }
+
+    #[test]
+    fn command_line() {
+        use cli::{Cli, Prelude};
+        use std::path::PathBuf;
+        let args = |s: &str| Cli::parse(s.split_whitespace().map(String::from));
+        let c = args("--no-prelude -e (Fac_3) --load a.sexp --load=b.wl --syntax infix script.wl").unwrap();
+        assert_eq!(c.prelude, Prelude::None);
+        assert_eq!(c.exprs, vec!["(Fac_3)".to_string()]);
+        assert_eq!(c.loads, vec![PathBuf::from("a.sexp"), PathBuf::from("b.wl")]);
+        assert_eq!(c.syntax, Some(Syntax::Infix));
+        assert_eq!(c.script, Some(PathBuf::from("script.wl")));
+        assert!(!c.interactive());
+        assert_eq!(c.prelude_files(), Ok(vec![]));
+        assert_eq!(args("--prelude lang").unwrap().prelude_files().unwrap()[1], Path::new("lang").join("startup.sexp"));
+        assert_eq!(args("--prelude my.sexp").unwrap().prelude_files(), Ok(vec![PathBuf::from("my.sexp")]));
+        // the tests run in the crate, so the default finds lang/ in the current directory
+        if std::env::var_os("CAS3_PRELUDE").is_none() {
+            assert_eq!(Cli::default().prelude_files().unwrap()[0], Path::new("lang").join("attrs.sexp"));
+        }
+        assert!(args("-").unwrap().stdin);
+        assert!(args("--syntax lisp").is_err());
+        assert!(args("-e").is_err());
+        assert!(args("--frobnicate").is_err());
+        assert!(args("a.sexp b.sexp").is_err());
+
+        // messages are what make a batch run fail
+        let mut ctx = Context2::new();
+        let before = message_count();
+        assert!(cli::eval_source(&mut ctx, "test", "(StringTake \"ab\" 3)"));
+        assert!(message_count() > before);
+        // an abort stops the run instead of every form giving $Aborted
+        ctx.abort.raise();
+        assert!(!cli::eval_source(&mut ctx, "test", "(set x 1) (set y 2)"));
+        assert!(!ctx.abort.is_raised());
+        assert_eq!(get_ownvalue(&ctx, sym("x")), None);
+    }
+
+    #[test]
+    fn save_and_dump_save() {
+        let dir = std::env::temp_dir().join(format!("cas3-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| dir.join(name).display().to_string();
+        let define = |ctx: &mut Context2| {
+            startup_attrs(ctx);
+            ctx_evalparse(ctx, "(setd (sq (pattern x (blank))) (Times x (helper x)))");
+            ctx_evalparse(ctx, "(setd (helper (pattern x (blank))) x)");
+            ctx_evalparse(ctx, r#"(set greeting "say \"hi\"")"#);
+            ctx_evalparse(ctx, "(set (attrs sq) (list HoldAll))");
+            ctx_evalparse(ctx, "(set unrelated 1.5)");
+        };
+        let mut ctx = Context2::new();
+        define(&mut ctx);
+
+        // source: sq brings helper along, but not unrelated or builtins like Times
+        let src = file("sq.sexp");
+        ctx_evalparse(&mut ctx, &format!("(Save {src:?} sq greeting)"));
+        let text = std::fs::read_to_string(&src).unwrap();
+        assert!(text.contains("(setd (helper (pattern x (blank))) x)"), "{text}");
+        assert!(text.contains("(SetAttributes sq (List HoldAll))"), "{text}");
+        assert!(!text.contains("unrelated") && !text.contains("(setd (Times"), "{text}");
+        let mut fresh = Context2::new();
+        startup_attrs(&mut fresh);
+        ctx_evalparse(&mut fresh, &format!("(Get {src:?})"));
+        assert_eq!(ctx_evalparse(&mut fresh, "(sq 3)"), parse("9"));
+        assert_eq!(ctx_evalparse(&mut fresh, "greeting"), parse(r#""say \"hi\"""#));
+        assert_eq!(attributes_of(&fresh, &sym("sq")), Some(parse("(List HoldAll)")));
+        // protecting a function of ours doesn't make it a builtin
+        ctx_evalparse(&mut ctx, "(Protect helper)");
+        let protected = file("protected.sexp");
+        ctx_evalparse(&mut ctx, &format!("(Save {protected:?} sq)"));
+        let mut fresh = Context2::new();
+        startup_attrs(&mut fresh);
+        ctx_evalparse(&mut fresh, &format!("(Get {protected:?})"));
+        assert_eq!(ctx_evalparse(&mut fresh, "(sq 3)"), parse("9"));
+        assert!(has_attribute(&fresh, &sym("helper"), "Protected"));
+
+        // binary: everything comes back, and dumping again gives the same bytes
+        let bin = file("all.mx");
+        ctx_evalparse(&mut ctx, &format!("(DumpSave {bin:?})"));
+        let bytes = std::fs::read(&bin).unwrap();
+        assert!(bytes.starts_with(save::DUMP_MAGIC));
+        let mut fresh = Context2::new();
+        ctx_evalparse(&mut fresh, &format!("(Get {bin:?})"));
+        assert_eq!(ctx_evalparse(&mut fresh, "unrelated"), parse("1.5"));
+        assert_eq!(ctx_evalparse(&mut fresh, "(sq 4)"), parse("16"));
+        let mut again = Context2::new();
+        save::load(&mut again, &bytes).unwrap();
+        assert_eq!(save::dump(&again, None), bytes);
+
+        // just some symbols
+        let part = save::dump(&ctx, Some(&[sym("sq")]));
+        let mut fresh = Context2::new();
+        assert_eq!(save::load(&mut fresh, &part), Ok(2));
+        assert_eq!(ctx_evalparse(&mut fresh, "unrelated"), sym("unrelated"));
+        assert_eq!(attributes_of(&fresh, &sym("sq")), Some(parse("(List HoldAll)")));
+        assert!(save::load(&mut fresh, &part[..part.len() - 1]).is_err());
+        assert!(save::load(&mut fresh, b"(set x 1)").is_err());
+        let mut broken = Context2::new();
+        let mut te = TableEntry::new();
+        te.down = liste(vec![]);
+        broken.vars.insert(sym("broken"), te);
+        let bytes = save::dump(&broken, None);
+        assert_eq!(save::load(&mut fresh, &bytes), Err("down values that aren't a list".to_string()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn jupyter_kernel() {
+        use jupyter::{ConnectionInfo, Handler, Server, Session};
+        use serde_json::{json, Value};
+        use zmtp::Connection;
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let server = Server::bind(ConnectionInfo::local("secret")).unwrap();
+            tx.send(server.info.clone()).unwrap();
+            let settings = KernelSettings { iteration_limit: Some(1000), ..KernelSettings::default() };
+            server.run(&mut Handler::new(Kernel::new(settings)));
+        });
+        let info = rx.recv().unwrap();
+
+        // a stub client: a SUB on iopub and a DEALER on shell, signing with the same key
+        let mut iopub = Connection::connect(&info.addr(info.iopub_port), "SUB").unwrap();
+        iopub.subscribe(b"").unwrap();
+        let mut shell = Connection::connect(&info.addr(info.shell_port), "DEALER").unwrap();
+        let client = Session::new(&info.key);
+        let mut request = |msg_type: &str, content: Value| {
+            let req = client.message(None, msg_type, content);
+            shell.send(&client.encode(&req)).unwrap();
+            let reply = client.decode(&shell.recv().unwrap()).unwrap();
+            assert_eq!(reply.parent_header["msg_id"], req.header["msg_id"]);
+            // everything published for this request, up to it going idle again
+            let mut published = vec![];
+            loop {
+                let msg = client.decode(&iopub.recv().unwrap()).unwrap();
+                if msg.parent_header["msg_id"] != req.header["msg_id"] {
+                    continue;
+                }
+                if msg.msg_type() == "status" {
+                    if msg.content["execution_state"] == "idle" {
+                        break;
+                    }
+                    continue;
+                }
+                published.push((msg.msg_type().to_string(), msg.content));
+            }
+            (reply.msg_type().to_string(), reply.content, published)
+        };
+        let execute = |code: &str| json!({"code": code, "silent": false});
+
+        let (t, info_reply, _) = request("kernel_info_request", json!({}));
+        assert_eq!((t.as_str(), info_reply["protocol_version"].as_str()), ("kernel_info_reply", Some("5.3")));
+
+        let (t, reply, published) = request("execute_request", execute("(setd (f (pattern x (blank))) (Times x x))\n(f 3)"));
+        assert_eq!((t.as_str(), &reply["status"], &reply["execution_count"]), ("execute_reply", &json!("ok"), &json!(1)));
+        assert_eq!(published[0].0, "execute_input");
+        assert_eq!(published[1].0, "execute_result");
+        assert_eq!(published[1].1["data"]["text/plain"], "9");
+        assert_eq!(published[1].1["data"]["text/latex"], "$9$");
+
+        // cells run under the kernel's settings
+        let (_, _, published) = request("execute_request", execute("$IterationLimit"));
+        assert_eq!(published[1].1["data"]["text/plain"], "1000");
+
+        let (_, _, published) = request("execute_request", execute(r#"(Print "hi")"#));
+        assert_eq!(published[1], ("stream".to_string(), json!({"name": "stdout", "text": "hi\n"})));
+
+        let svg = std::env::temp_dir().join(format!("cas3-jupyter-{}.svg", std::process::id()));
+        std::fs::write(&svg, "<svg></svg>").unwrap();
+        let export = format!("(Export {:?} (list (list (list 1. 0. 0.))))", svg.display().to_string());
+        let (_, _, published) = request("execute_request", execute(&export));
+        std::fs::remove_file(&svg).unwrap();
+        assert_eq!(published[1].0, "display_data");
+        assert!(published[1].1["data"]["image/svg+xml"].as_str().unwrap().contains("<svg"));
+
+        let (_, reply, published) = request("execute_request", execute("(f 1"));
+        assert_eq!((&reply["status"], &reply["ename"]), (&json!("error"), &json!("SyntaxError")));
+        assert_eq!(published[1].0, "error");
+
+        let (t, reply, _) = request("complete_request", json!({"code": "(Prin", "cursor_pos": 5}));
+        assert_eq!(t, "complete_reply");
+        assert!(reply["matches"].as_array().unwrap().contains(&json!("Print")));
+        assert_eq!((&reply["cursor_start"], &reply["cursor_end"]), (&json!(1), &json!(5)));
+
+        let (_, reply, _) = request("inspect_request", json!({"code": "(f 2)", "cursor_pos": 2, "detail_level": 1}));
+        assert_eq!(reply["found"], true);
+        assert!(reply["data"]["text/plain"].as_str().unwrap().contains("(setd (f (pattern x (blank))) (Times x x))"));
+
+        for (code, status) in [("(f 1)", "complete"), ("(f (g 1)\n", "incomplete"), ("(f 1))", "invalid")] {
+            let (t, reply, _) = request("is_complete_request", json!({"code": code}));
+            assert_eq!((t.as_str(), &reply["status"]), ("is_complete_reply", &json!(status)), "{code}");
+        }
+
+        // a message signed with the wrong key is dropped, the next one still gets answered
+        let mut intruder = Connection::connect(&info.addr(info.shell_port), "DEALER").unwrap();
+        let forger = Session::new("not the key");
+        intruder.send(&forger.encode(&forger.message(None, "kernel_info_request", json!({})))).unwrap();
+        let (t, _, _) = request("shutdown_request", json!({"restart": false}));
+        assert_eq!(t, "shutdown_reply");
+    }
+
+    #[test]
+    fn language_server() {
+        use lsp::{path_uri, Server};
+        use serde_json::{json, Value};
+
+        let dir = std::env::temp_dir().join(format!("cas3-lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = "(* squares *)\n(set (attrs sq) (list Listable))\n(setd (sq (pattern x (blank))) (Times x x))\n(set limit 10)\n";
+        std::fs::write(dir.join("lib.sexp"), lib).unwrap();
+        let main = dir.join("main.sexp");
+        let uri = path_uri(&main);
+        let doc = json!({"uri": uri});
+
+        let mut server = Server::new();
+        let mut request = |method: &str, params: Value| server.handle(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}));
+        let init = request("initialize", json!({"rootUri": path_uri(&dir)}));
+        assert_eq!(init[0]["result"]["capabilities"]["hoverProvider"], true);
+
+        let text = "(Get \"lib.sexp\")\n(setd (cube (pattern x (blank))) (Times x (sq x)))\n(cube limit\n";
+        let opened = request("textDocument/didOpen", json!({"textDocument": {"uri": uri, "text": text}}));
+        let diagnostics = &opened[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["message"], "unclosed (");
+        assert_eq!(diagnostics[0]["range"]["start"], json!({"line": 2, "character": 0}));
+
+        let at = |line: u32, character: u32| json!({"textDocument": doc, "position": {"line": line, "character": character}});
+        // sq in (sq x) goes to lib.sexp, skipping the attrs line
+        let def = &request("textDocument/definition", at(1, 43))[0]["result"];
+        assert_eq!(def, &json!([{"uri": path_uri(&dir.join("lib.sexp")), "range": {"start": {"line": 2, "character": 7}, "end": {"line": 2, "character": 9}}}]));
+
+        let hover = request("textDocument/hover", at(1, 43))[0]["result"]["contents"]["value"].clone();
+        let hover = hover.as_str().unwrap();
+        assert!(hover.contains("attributes: `(list Listable)`"), "{hover}");
+        assert!(hover.contains("DownValues:\n```cas3\n(setd (sq (pattern x (blank))) (Times x x))\n```"), "{hover}");
+        let hover = request("textDocument/hover", at(1, 36))[0]["result"]["contents"]["value"].clone();
+        assert!(hover.as_str().unwrap().starts_with("**Times**\n\n```cas3\n(Times"), "{hover}");
+
+        let labels = |res: &Value| -> Vec<String> {
+            res.as_array().unwrap().iter().map(|i| i["label"].as_str().unwrap().to_string()).collect()
+        };
+        // the l in (cube limit
+        assert_eq!(labels(&request("textDocument/completion", at(2, 7))[0]["result"]), vec!["limit"]);
+
+        let changed = "(setd (g (pattern x (blank))) x)\n(set h ,)\n";
+        let published = request("textDocument/didChange", json!({"textDocument": doc, "contentChanges": [{"text": changed}]}));
+        assert_eq!(published[0]["params"]["diagnostics"][0]["range"]["start"], json!({"line": 1, "character": 7}));
+        let symbols = &request("textDocument/documentSymbol", json!({"textDocument": doc}))[0]["result"];
+        assert_eq!((&symbols[0]["name"], &symbols[1]["name"], &symbols[2]), (&json!("g"), &json!("h"), &Value::Null));
+        assert_eq!(symbols[0]["detail"], "(g (pattern x (blank)))");
+        assert_eq!(request("workspace/symbol", json!({}))[0]["error"]["code"], -32601);
+
+        // the framing over stdio
+        let mut input = vec![];
+        for msg in [json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}), json!({"jsonrpc": "2.0", "method": "exit"})] {
+            lsp::write_message(&mut input, &msg).unwrap();
+        }
+        let mut output = vec![];
+        assert!(lsp::run(&input[..], &mut output).unwrap());
+        let reply = lsp::read_message(&mut &output[..]).unwrap().unwrap();
+        assert_eq!(reply, json!({"jsonrpc": "2.0", "id": 1, "result": null}));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn formatter() {
+        use pretty::format_source;
+        let src = "(* squares *)\n(setd (sq (pattern x (blank))) (Times x x)) (* the usual *)\n\n\n\n(set u (List 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15))\n(set r\n  (rule (f (pattern x (blank)) (* any x *)) (g x 1.50 \"a  b\")))\n";
+        assert_eq!(
+            format_source(src, 40).unwrap(),
+            "(* squares *)
+(setd (sq (pattern x (blank)))
+  (Times x x)) (* the usual *)
+
+(set u
+  (List 1 2 3 4 5 6 7 8 9 10 11 12 13 14
+    15))
+(set r
+  (rule (f
+          (pattern x (blank)) (* any x *))
+    (g x 1.50 \"a  b\")))
+"
+        );
+        assert_eq!(format_source("(f\n   x)", 80).unwrap(), "(f x)\n");
+        assert!(format_source("(f x", 80).unwrap_err().starts_with("1:5: expected"));
+
+        // the same forms come back, and formatting again changes nothing
+        for file in ["attrs", "aliases", "startup", "calculus", "systems"] {
+            let src = std::fs::read_to_string(format!("lang/{file}.sexp")).unwrap();
+            for width in [30, 80, 120] {
+                let out = format_source(&src, width).unwrap();
+                assert_eq!(parse_expressions(&out, Syntax::Sexp), parse_expressions(&src, Syntax::Sexp), "{file}");
+                assert_eq!(format_source(&out, width).unwrap(), out, "{file} at {width}");
+            }
+        }
+
+        let mut ctx = Context2::new();
+        assert_eq!(
+            ctx_evalparse(&mut ctx, r#"(Format "(f (g x)   (* c *)\n)" 8)"#),
+            Expr::Str("(f\n  (g x) (* c *))".into())
+        );
+        assert_eq!(ctx_evalparse(&mut ctx, "(Format (hold (f (g x) (h y))) 12)"), Expr::Str("(hold\n  (f\n    (g x)\n    (h y)))".into()));
+    }
+
+    #[test]
+    fn contexts() {
+        let dir = std::env::temp_dir().join(format!("cas3_contexts_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("shapes.sexp"),
+            r#"(BeginPackage "shapes`")
+(List area)
+(Begin "`Private`")
+(setd (foo (pattern r (blank))) (Times r r))
+(SetAttributes foo (List Orderless NHoldAll))
+(set pi (N Pi))
+(setd (area (pattern r (blank))) (Times 3 (foo r)))
+(set Global`loads (Plus Global`loads 1))
+(End)
+(EndPackage)
+"#,
+        )
+        .unwrap();
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        let ev = |ctx: &mut Context2, s: &str| ctx_evalparse(ctx, s);
+        ev(&mut ctx, &format!("(set $Path (List {:?}))", dir.display().to_string()));
+        ev(&mut ctx, "(set foo 1)");
+        ev(&mut ctx, "(set loads 0)");
+        assert_eq!(ev(&mut ctx, r#"(Needs "shapes`")"#), sym("Null"));
+        assert_eq!(ev(&mut ctx, r#"(Needs "shapes`")"#), sym("Null"));
+        assert_eq!(ev(&mut ctx, "loads"), Expr::Int(1.into()));
+
+        // the package's helper doesn't touch ours
+        assert_eq!(ev(&mut ctx, "(area 2)"), Expr::Int(12.into()));
+        assert_eq!(ev(&mut ctx, "foo"), Expr::Int(1.into()));
+        assert_eq!(ev(&mut ctx, "(shapes`Private`foo 3)"), Expr::Int(9.into()));
+        assert_eq!(ev(&mut ctx, "(Attributes shapes`Private`foo)"), parse("(List Orderless NHoldAll)"));
+        assert_eq!(ev(&mut ctx, "shapes`Private`pi"), parse(&std::f64::consts::PI.to_string()));
+        assert_eq!(ev(&mut ctx, "(Context area)"), Expr::Str("shapes`".into()));
+        assert_eq!(ev(&mut ctx, "(Context foo)"), Expr::Str("Global`".into()));
+        assert_eq!(ev(&mut ctx, "(Context Plus)"), Expr::Str("System`".into()));
+        assert_eq!(ev(&mut ctx, "$Context"), Expr::Str("Global`".into()));
+        assert_eq!(ev(&mut ctx, "$ContextPath"), parse(r#"(List "shapes`" "System`" "Global`")"#));
+        assert_eq!(ev(&mut ctx, "(hold area)"), parse("(hold shapes`area)"));
+        // names print short when they'd read back as the same symbol
+        let printed = contexts::shorten(&ctx, &parse("(List shapes`area shapes`Private`foo)"));
+        assert_eq!(printed, parse("(List area shapes`Private`foo)"));
+        assert_eq!(parse_expr("shapes`area[2]", Syntax::Infix).unwrap(), parse("(shapes`area 2)"));
+
+        assert_eq!(ev(&mut ctx, r#"(Begin "`Scratch`")"#), Expr::Str("Global`Scratch`".into()));
+        assert_eq!(ev(&mut ctx, "(set bar 5)"), Expr::Int(5.into()));
+        assert_eq!(ev(&mut ctx, "(End)"), Expr::Str("Global`Scratch`".into()));
+        assert_eq!(ev(&mut ctx, "(List bar Global`Scratch`bar)"), parse("(List bar 5)"));
+        assert_eq!(ev(&mut ctx, "(End)"), sym("$Failed"));
+        assert_eq!(ev(&mut ctx, r#"(Needs "nowhere`")"#), sym("$Failed"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn builtins_are_system() {
+        // every head internal_functions_apply looks for, read out of its source
+        let src = include_str!("lib.rs");
+        let start = src.find("pub fn internal_functions_apply(").unwrap();
+        let body = &src[start..start + src[start..].find("\n}\n").unwrap()];
+        let heads: Vec<&str> = body
+            .split("nh == sym(\"")
+            .skip(1)
+            .chain(body.split("head(&nh) == sym(\"").skip(1))
+            .map(|rest| &rest[..rest.find('"').unwrap()])
+            .collect();
+        assert!(heads.len() > 50, "{heads:?}");
+        let ctx = Context2::new();
+        for name in heads.into_iter().chain(ATTRIBUTES.iter().copied()) {
+            assert_eq!(contexts::context_of(&ctx, name), contexts::SYSTEM, "{name}");
+        }
+    }
+
+    #[test]
+    fn protected() {
+        let mut kernel = Kernel::new(KernelSettings::default());
+        let ctx = &mut kernel.ctx;
+        let before = message_count();
+        // builtins are protected, and so is everything that'd let you get around it
+        assert_eq!(ctx_evalparse(ctx, "(set Plus 5)"), Expr::Int(5.into()));
+        assert_eq!(ctx_evalparse(ctx, "(Plus 1 2)"), Expr::Int(3.into()));
+        assert_eq!(ctx_evalparse(ctx, "(setd (Plus a b) 1)"), sym("$Failed"));
+        assert_eq!(ctx_evalparse(ctx, "(Plus a b)"), parse("(Plus a b)"));
+        assert_eq!(ctx_evalparse(ctx, "(clear Times)"), sym("$Failed"));
+        assert_eq!(ctx_evalparse(ctx, "(set (attrs Trace) (list))"), parse("(list)"));
+        assert!(has_attribute(ctx, &sym("Trace"), "HoldAll"));
+        assert_eq!(message_count() - before, 4);
+
+        assert_eq!(ctx_evalparse(ctx, "(Protect f g)"), parse(r#"(List "f" "g")"#));
+        assert_eq!(ctx_evalparse(ctx, "(Protect f)"), parse("(List)"));
+        ctx_evalparse(ctx, "(set f 1)");
+        assert_eq!(ctx_evalparse(ctx, "f"), sym("f"));
+        assert_eq!(ctx_evalparse(ctx, "(Unprotect f)"), parse(r#"(List "f")"#));
+        ctx_evalparse(ctx, "(set f 1)");
+        assert_eq!(ctx_evalparse(ctx, "f"), Expr::Int(1.into()));
+
+        // Locked is for good
+        ctx_evalparse(ctx, "(set (attrs t) (List Locked Protected))");
+        assert_eq!(ctx_evalparse(ctx, "(Unprotect t)"), parse("(List)"));
+        ctx_evalparse(ctx, "(set (attrs t) (List))");
+        assert!(has_attribute(ctx, &sym("t"), "Protected"));
+
+        // ReadProtected keeps the rules out of sight, not out of use
+        ctx_evalparse(ctx, "(setd (h (pattern x (blank))) (Times x 2))");
+        ctx_evalparse(ctx, "(set (attrs h) (List ReadProtected))");
+        assert_eq!(ctx_evalparse(ctx, "(h 4)"), Expr::Int(8.into()));
+        assert_eq!(ctx_evalparse(ctx, "(down_values h)"), sym("$Failed"));
+        assert!(!help::describe(ctx, "h", true).contains("Times"));
+    }
+
+    #[test]
+    fn attributes() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        let ev = |ctx: &mut Context2, s: &str| ctx_evalparse(ctx, s);
+        assert_eq!(ev(&mut ctx, "(Attributes Save)"), parse("(List HoldRest)"));
+        // attributes add up instead of replacing each other
+        ev(&mut ctx, "(SetAttributes f HoldAll)");
+        ev(&mut ctx, "(SetAttributes f (List Listable HoldAll))");
+        assert_eq!(ev(&mut ctx, "(Attributes f)"), parse("(List HoldAll Listable)"));
+        assert_eq!(ev(&mut ctx, "(f (Plus 1 2))"), parse("(f (Plus 1 2))"));
+        ev(&mut ctx, "(ClearAttributes f HoldAll)");
+        assert_eq!(ev(&mut ctx, "(attrs f)"), parse("(List Listable)"));
+        assert_eq!(ev(&mut ctx, "(f (Plus 1 2))"), parse("(f 3)"));
+        ev(&mut ctx, "(SetAttributes (List g h) HoldFirst)");
+        assert_eq!(ev(&mut ctx, "(List (Attributes g) (Attributes h) (Attributes k))"), parse("(List (List HoldFirst) (List HoldFirst) (List))"));
+        // set still replaces them all
+        ev(&mut ctx, "(set (attrs g) (List Orderless))");
+        assert_eq!(ev(&mut ctx, "(Attributes g)"), parse("(List Orderless)"));
+
+        let before = message_count();
+        assert_eq!(ev(&mut ctx, "(SetAttributes f Bogus)"), sym("$Failed"));
+        assert_eq!(ev(&mut ctx, "(set (attrs f) (List HoldAll Bogus))"), sym("$Failed"));
+        assert_eq!(ev(&mut ctx, "(Attributes f)"), parse("(List Listable)"));
+        ev(&mut ctx, "(SetAttributes t Locked)");
+        assert_eq!(ev(&mut ctx, "(SetAttributes t HoldAll)"), sym("$Failed"));
+        assert_eq!(ev(&mut ctx, "(ClearAttributes t Locked)"), sym("$Failed"));
+        assert_eq!(message_count() - before, 4);
+        // giving a symbol a value keeps its attributes and down values
+        ev(&mut ctx, "(set t 1)");
+        ev(&mut ctx, "(setd (f x) 2)");
+        ev(&mut ctx, "(setd f 3)");
+        assert_eq!(ev(&mut ctx, "(List (Attributes t) (Attributes f) (Length (down_values f)))"), parse("(List (List Locked) (List Listable) 1)"));
+    }
+
+    #[test]
+    fn evaluation_control() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        let ev = |ctx: &mut Context2, s: &str| ctx_evalparse(ctx, s);
+        assert_eq!(ev(&mut ctx, "(Hold (Plus 1 2) (Evaluate (Plus 1 2)))"), parse("(Hold (Plus 1 2) 3)"));
+        assert_eq!(ev(&mut ctx, "(Hold (Sequence a b))"), parse("(Hold a b)"));
+        // HoldComplete doesn't look inside at all
+        let complete = "(HoldComplete (Evaluate (Plus 1 2)) (Sequence a b) (Unevaluated c))";
+        assert_eq!(ev(&mut ctx, complete), parse(complete));
+        ev(&mut ctx, "(SetAttributes hc HoldAllComplete)");
+        assert_eq!(ev(&mut ctx, "(hc (Evaluate (Plus 1 2)))"), parse("(hc (Evaluate (Plus 1 2)))"));
+
+        assert_eq!(ev(&mut ctx, "(ReleaseHold (Hold (Plus 1 2)))"), Expr::Int(3.into()));
+        assert_eq!(ev(&mut ctx, "(ReleaseHold (List (Hold (Plus 1 1)) (HoldForm (Plus 2 3)) 4))"), parse("(List 2 5 4)"));
+        assert_eq!(ev(&mut ctx, "(ReleaseHold (Hold (Hold (Plus 1 2))))"), parse("(Hold (Plus 1 2))"));
+        assert_eq!(ev(&mut ctx, "(f (ReleaseHold (HoldComplete a b)))"), parse("(f a b)"));
+        assert_eq!(head(&ev(&mut ctx, "(ReleaseHold (Unevaluated (g ())))")), sym("g"));
+
+        // Unevaluated goes away when something uses what's in it, and stays otherwise
+        assert_eq!(ev(&mut ctx, "(Length (Unevaluated (Times 2 3)))"), Expr::Int(2.into()));
+        ev(&mut ctx, "(setd (held (pattern x (blank))) (Hold x))");
+        assert_eq!(ev(&mut ctx, "(held (Unevaluated (Plus 1 2)))"), parse("(Hold (Plus 1 2))"));
+        assert_eq!(ev(&mut ctx, "(held (Plus 1 2))"), parse("(Hold 3)"));
+        assert_eq!(ev(&mut ctx, "(k (Unevaluated (Plus 1 2)) (Plus 1 2))"), parse("(k (Unevaluated (Plus 1 2)) 3)"));
+
+        assert_eq!(ev(&mut ctx, "(N (f 1/2 (Rational 3 4) Pi x))"), parse(&format!("(f 0.5 0.75 {} x)", std::f64::consts::PI)));
+        ev(&mut ctx, "(SetAttributes f NHoldFirst)");
+        ev(&mut ctx, "(SetAttributes g NHoldAll)");
+        assert_eq!(ev(&mut ctx, "(N (List (f 1 2) (g 1 2)))"), parse("(List (f 1 2.) (g 1 2))"));
+        // what N gives is evaluated again, so rules on reals get a look at it
+        ev(&mut ctx, "(setd (r (pattern x (blank Real))) real)");
+        assert_eq!(ev(&mut ctx, "(N (r 1/2))"), sym("real"));
+    }
+
+    #[test]
+    fn strings() {
+        // escapes read and print back the same
+        let s = r#""say \"hi\"\\ \n\t\u{3bb}""#;
+        assert_eq!(parse(s), Expr::Str("say \"hi\"\\ \n\tλ".into()));
+        assert_eq!(parse(s).to_string(), r#""say \"hi\"\\ \n\tλ""#);
+        assert_eq!(parse(&parse(s).to_string()), parse(s));
+        assert!(expr_parser::Expr(r#""\q""#).is_err());
+        assert_eq!(infix_parser::Expr(r#"f["a\"b"]"#).unwrap(), parse(r#"(f "a\"b")"#));
+
+        let mut ctx = Context2::new();
+        let mut ev = |s: &str| ctx_evalparse(&mut ctx, s);
+        assert_eq!(ev(r#"(StringJoin "ab" "c" (StringJoin (List "d")))"#), parse(r#""abcd""#));
+        assert_eq!(ev(r#"(StringLength "λx")"#), parse("2"));
+        assert_eq!(ev(r#"(Characters "ab")"#), parse(r#"(List "a" "b")"#));
+        assert_eq!(ev(r#"(StringTake "abcdef" 2)"#), parse(r#""ab""#));
+        assert_eq!(ev(r#"(StringTake "abcdef" -2)"#), parse(r#""ef""#));
+        assert_eq!(ev(r#"(StringTake "abcdef" (List 2 4))"#), parse(r#""bcd""#));
+        assert_eq!(ev(r#"(StringTake "abcdef" (List -1))"#), parse(r#""f""#));
+        assert_eq!(ev(r#"(StringTake "ab" 3)"#), parse(r#"(StringTake "ab" 3)"#));
+        assert_eq!(ev(r#"(StringSplit " a b  c ")"#), parse(r#"(List "a" "b" "c")"#));
+        assert_eq!(ev(r#"(StringSplit "a,b,,c" ",")"#), parse(r#"(List "a" "b" "c")"#));
+        assert_eq!(
+            ev(r#"(StringReplace "abcab" (List (rule "ab" "X") (rule "c" "")))"#),
+            parse(r#""XX""#)
+        );
+        assert_eq!(ev(r#"(ToUpperCase "aλ")"#), parse(r#""AΛ""#));
+        assert_eq!(ev(r#"(StringMatchQ "hello.rs" "*.rs")"#), sym("true"));
+        assert_eq!(ev(r#"(StringMatchQ "hello.rs" "h*x*")"#), sym("false"));
+        assert_eq!(ev(r#"(ToString (f "a" 1))"#), parse(r#""(f \"a\" 1)""#));
+        assert_eq!(ev(r#"(ToString (Plus a (Times 2 b)) InputForm)"#), parse(r#""a + 2*b""#));
+        assert_eq!(ev("(ToString)"), sym("$Failed"));
+        assert_eq!(ev(r#"(ToExpression "(set x 2) (List x x)")"#), parse("(List 2 2)"));
+    }
+
+    #[test]
+    fn string_patterns() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        let mut ev = |s: &str| ctx_evalparse(&mut ctx, s);
+        assert_eq!(
+            ev(r#"(StringMatchQ "a123b" (StringExpression "a" (pattern x (blank_seq DigitCharacter)) "b"))"#),
+            sym("true")
+        );
+        assert_eq!(ev(r#"(StringMatchQ "a12cb" (StringExpression "a" (blank_seq DigitCharacter) "b"))"#), sym("false"));
+        assert_eq!(ev(r#"(StringMatchQ "2024-01" (RegularExpression "\\d{4}-\\d{2}"))"#), sym("true"));
+        assert_eq!(
+            ev(r#"(StringCases "a1 b22 c" (StringExpression (pattern l (blank LetterCharacter)) (pattern d (blank_seq DigitCharacter))))"#),
+            parse(r#"(List "a1" "b22")"#)
+        );
+        // bindings become the rhs, like replace
+        assert_eq!(
+            ev(r#"(StringCases "a1 b22 c" (rule_delayed (StringExpression (pattern l (blank LetterCharacter)) (pattern d (blank_seq DigitCharacter))) (List d l)))"#),
+            parse(r#"(List (List "1" "a") (List "22" "b"))"#)
+        );
+        assert_eq!(
+            ev(r#"(StringReplace "x=1, y=22" (rule_delayed (StringExpression (pattern k (blank WordCharacter)) "=" (pattern v (blank_seq DigitCharacter))) (StringJoin v ":" (ToUpperCase k))))"#),
+            parse(r#""1:X, 22:Y""#)
+        );
+        assert_eq!(
+            ev(r#"(StringReplace "aaa" (List (rule "aa" "b") (rule (RegularExpression "a") "c")))"#),
+            parse(r#""bc""#)
+        );
+        assert_eq!(ev(r#"(StringPosition "aaa" "aa")"#), parse("(List (List 1 2) (List 2 3))"));
+        assert_eq!(
+            ev(r#"(StringPosition "λab" (Alternatives "a" "b"))"#),
+            parse("(List (List 2 2) (List 3 3))")
+        );
+        // the same name has to bind the same text
+        assert_eq!(
+            ev(r#"(StringMatchQ "abab" (StringExpression (pattern x (blank_seq)) (pattern x (blank_seq))))"#),
+            sym("true")
+        );
+        assert_eq!(
+            ev(r#"(StringCases "ab-ab ab-cd" (StringExpression (pattern x (blank_seq WordCharacter)) "-" (pattern x (blank_seq WordCharacter))))"#),
+            parse(r#"(List "ab-ab")"#)
+        );
+    }
+
+    #[test]
+    fn interned_and_shared_exprs() {
+        // symbols with the same name are the same allocation
+        assert!(sym("foo").ptr_eq(&parse("foo")));
+        assert_ne!(sym("foo"), sym("bar"));
+
+        // equal but separately built lists still compare and hash equal
+        let a = parse("(f (g x) 1 \"s\")");
+        let b = parse("(f (g x) 1 \"s\")");
+        assert!(!a.ptr_eq(&b));
+        assert_eq!(a, b);
+        let mut set = HashSet::new();
+        set.insert(a.clone());
+        assert!(set.contains(&b));
+
+        // mutating through DerefMut invalidates the cached hash
+        let mut c = a.clone();
+        c.push(sym("y"));
+        assert_ne!(a, c);
+        assert!(!set.contains(&c));
+
+        // subtrees no rule touches are shared, not copied
+        let ex = parse("(List (g x) (h y))");
+        let res = replace_all(&ex, &parse("(rule y z)"));
+        assert_eq!(res, parse("(List (g x) (h z))"));
+        assert!(res[1].ptr_eq(&ex[1]));
+        assert!(replace_all(&ex, &parse("(rule q z)")).ptr_eq(&ex));
+    }
+
+    #[test]
+    fn evaluated_lists_are_skipped_until_redefinition() {
+        let mut ctx = Context2::new();
+        let mut stack = liste(vec![]);
+        let inert = parse("(List 1 2 (g 3) 4)");
+        let res = evaluate(&mut stack, &mut ctx, &inert);
+        assert!(res.ptr_eq(&inert));
+        if let Expr::List(ls) = &res {
+            assert!(ls.is_evaluated_at(ctx.version));
+        }
+
+        // a new definition makes the old stamp stale, so (g 3) gets rewritten
+        ctx_evalparse(&mut ctx, "(set (g 3) three)");
+        let res = evaluate(&mut stack, &mut ctx, &inert);
+        assert_eq!(res, parse("(List 1 2 three 4)"));
+
+        // stamps never leak between contexts
+        let mut other = Context2::new();
+        assert!(evaluate(&mut stack, &mut other, &res).ptr_eq(&res));
+        assert_eq!(
+            evaluate(&mut stack, &mut other, &inert),
+            parse("(List 1 2 (g 3) 4)")
+        );
+    }
+
+    #[test]
+    fn memoization() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        run_file(&mut ctx, Path::new("lang/attrs.sexp")).unwrap();
+        ctx_evalparse(&mut ctx, "(set (fib 0) 0)");
+        ctx_evalparse(&mut ctx, "(set (fib 1) 1)");
+        ctx_evalparse(
+            &mut ctx,
+            "(setd (fib (pattern n (blank Int))) (set (fib n) (Plus (fib (Plus n -2)) (fib (Plus n -1)))))",
+        );
+        assert_eq!(ctx_evalparse(&mut ctx, "(fib 10)"), parse("55"));
+        // every (fib k) got cached ahead of the general rule
+        let dvs = ctx_evalparse(&mut ctx, "(down_values fib)");
+        assert_eq!(dvs.len() - 1, 12);
+        assert_eq!(dvs[3], parse("(rule_delayed (hold_pattern (fib 2)) 1)"));
+        assert!(has_patterns(&dvs[12]));
+
+        // redefining the same lhs replaces it rather than shadowing it
+        ctx_evalparse(&mut ctx, "(set (fib 2) two)");
+        assert_eq!(ctx_evalparse(&mut ctx, "(fib 2)"), sym("two"));
+        assert_eq!(ctx_evalparse(&mut ctx, "(down_values fib)").len() - 1, 12);
+
+        // concrete lhs args are evaluated before storing
+        ctx_evalparse(&mut ctx, "(set (g (Plus 1 2)) x)");
+        assert_eq!(ctx_evalparse(&mut ctx, "(g 3)"), sym("x"));
+        assert_eq!(ctx_evalparse(&mut ctx, "(set (f ()) 1)"), parse("1"));
+
+        ctx_evalparse(&mut ctx, "(set (attrs slow) (list Memoize))");
+        ctx_evalparse(&mut ctx, "(setd (slow (pattern n (blank Int))) (Plus n 1))");
+        assert_eq!(ctx_evalparse(&mut ctx, "(slow 41)"), parse("42"));
+        assert_eq!(
+            ctx.vars[&sym("slow")].memo.get(&parse("(slow 41)")),
+            Some(&parse("42"))
+        );
+        ctx_evalparse(&mut ctx, "(clear_cache slow)");
+        assert!(ctx.vars[&sym("slow")].memo.is_empty());
+
+        // redefining slow forgets what the old definition gave
+        ctx_evalparse(&mut ctx, "(slow 1)");
+        ctx_evalparse(&mut ctx, "(setd (slow (pattern n (blank Int))) (Plus n 100))");
+        assert_eq!(ctx_evalparse(&mut ctx, "(List (slow 1) (slow 2))"), parse("(List 101 102)"));
+    }
+
+    #[test]
+    fn trace_and_stack() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(Trace (Plus (Plus 1 2) 3))"),
+            parse("(List (HoldForm (Plus (Plus 1 2) 3)) (List (HoldForm (Plus 1 2)) (HoldForm 3)) (HoldForm (Plus 3 3)) (HoldForm 6))")
+        );
+        assert_eq!(ctx_evalparse(&mut ctx, "(Trace 1)"), parse("(List)"));
+
+        ctx_evalparse(&mut ctx, "(set x 5)");
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(Trace (Plus x 1) (pattern i (blank Int)))"),
+            parse("(List (List (HoldForm 5)) (HoldForm 6))")
+        );
+        let (res, out) = capture(|| ctx_evalparse(&mut ctx, "(TracePrint (Plus x 1))"));
+        assert_eq!(res, parse("6"));
+        assert!(out.stdout.contains("(Plus x 1)\n"), "{}", out.stdout);
+        assert!(ctx.tracer.is_none());
+
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(h (k (Stack)))"),
+            parse("(h (k (List h k)))")
+        );
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(h (k (Stack (k (blank)))))"),
+            parse("(h (k (List (HoldForm (k (Stack (k (blank))))))))")
+        );
+    }
+
+    #[test]
+    fn limits_and_aborts() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        ctx_evalparse(&mut ctx, "(set $RecursionLimit 20)");
+        ctx_evalparse(&mut ctx, "(setd (g (pattern n (blank Int))) (h (g (Plus n 1))))");
+        let res = ctx_evalparse(&mut ctx, "(g 0)");
+        let mut inner = &res;
+        while head(inner) == sym("h") {
+            inner = &inner[1];
+        }
+        assert_eq!(head(inner), sym("g"));
+        assert_eq!(head(&inner[1]), sym("Hold"));
+
+        ctx_evalparse(&mut ctx, "(set $IterationLimit 100)");
+        ctx_evalparse(&mut ctx, "(setd (t (pattern n (blank Int))) (t (Plus n 1)))");
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(t 0)"),
+            parse("(Hold (t (Plus 99 1)))")
+        );
+
+        assert_eq!(
+            ctx_evalparse(
+                &mut ctx,
+                "(rr (f 1) (rule (f (pattern n (blank))) (f (Plus n 1))) (rule MaxIterations 5))"
+            ),
+            parse("(f 6)")
+        );
+
+        // a raised flag stops evaluation until someone takes it down
+        ctx.abort.raise();
+        assert_eq!(ctx_evalparse(&mut ctx, "(Plus 1 2)"), sym("$Aborted"));
+        assert!(ctx.abort.take());
+        assert_eq!(ctx_evalparse(&mut ctx, "(Plus 1 2)"), parse("3"));
+    }
+
+    #[test]
+    fn time_and_memory_constraints() {
+        let mut ctx = Context2::new();
+        startup_attrs(&mut ctx);
+        ctx_evalparse(&mut ctx, "(set $IterationLimit Infinity)");
+        ctx_evalparse(&mut ctx, "(setd (t (pattern n (blank Int))) (t (Plus n 1)))");
+        let t0 = Instant::now();
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(TimeConstrained (t 0) 0.2 gave_up)"),
+            sym("gave_up")
+        );
+        assert!(t0.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(TimeConstrained (List (t 0)) 0.1)"),
+            sym("$Aborted")
+        );
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(TimeConstrained (Plus 1 2) 10 gave_up)"),
+            parse("3")
+        );
+        // an outer limit running out unwinds through an inner one that still has time
+        assert_eq!(
+            ctx_evalparse(
+                &mut ctx,
+                "(TimeConstrained (f (TimeConstrained (t 0) 100 inner)) 0.1 outer)"
+            ),
+            sym("outer")
+        );
+        assert!(ctx.constraints.is_empty());
+        assert_eq!(ctx_evalparse(&mut ctx, "(TimeConstrained (Plus 1 2) 1.0e300)"), parse("3"));
+        assert_eq!(ctx_evalparse(&mut ctx, "(TimeConstrained (Plus 1 2) 18446744073709551615)"), parse("3"));
+        // the $Aborted a limit gives isn't memoized as the answer
+        ctx_evalparse(&mut ctx, "(SetAttributes m Memoize)");
+        ctx_evalparse(&mut ctx, "(setd (m (pattern n (blank Int))) (t n))");
+        assert_eq!(ctx_evalparse(&mut ctx, "(TimeConstrained (m 1) 0.1 fail)"), sym("fail"));
+        assert!(ctx.vars[&sym("m")].memo.is_empty());
+
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(MemoryConstrained (Table (List i i i) (List i 100000)) 100000 too_big)"),
+            sym("too_big")
+        );
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(MemoryConstrained (Plus 1 2) 100000000 too_big)"),
+            parse("3")
+        );
+
+        let mut kernel = Kernel::new(KernelSettings {
+            time_limit: Some(Duration::from_millis(100)),
+            iteration_limit: None,
+            ..Default::default()
+        });
+        assert_eq!(kernel.evaluate(&parse("$IterationLimit")), sym("Infinity"));
+        kernel.evaluate(&parse("(setd (t (pattern n (blank Int))) (t (Plus n 1)))"));
+        assert_eq!(kernel.evaluate(&parse("(t 0)")), sym("$Aborted"));
+        assert_eq!(kernel.evaluate(&parse("(Plus 1 2)")), parse("3"));
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        assert_eq!(evalparse("(matchq 1 (blank))"), sym("true"));
+        assert_eq!(evalparse("(matchq 1 (blank Int))"), sym("true"));
+        assert_eq!(evalparse("(matchq 1 (pattern x (blank)))"), sym("true"));
+        assert_eq!(evalparse("(matchq 1 (pattern x (blank Int)))"), sym("true"));
+        assert_eq!(
+            evalparse("(matchq 1 (pattern x (blank Sym)))"),
+            sym("false")
+        );
+        assert_eq!(
+            evalparse("(matchq ((k a) b) ((k (pattern x (blank Sym))) (pattern y (blank Sym))))"),
+            sym("true")
+        );
+        assert_eq!(
+            evalparse("(matchq ((k a) b) ((k (pattern x (blank))) (pattern y (blank))))"),
+            sym("true")
+        );
+        assert_eq!(
+            evalparse("(matchq (plus 1 2) (plus (blank) (blank)))"),
+            sym("true")
+        );
+
+        assert_eq!(evalparse("(matchq (list a b c) (blank))"), sym("true"));
+        assert_eq!(
+            evalparse("(matchq (list a b c) (pattern x (blank)))"),
+            sym("true")
+        );
+
+        assert_eq!(evalparse("(matchq (f (g 1)) (f (g (blank))))"), sym("true"));
+
+        // testing that patterns with the same name must equal the same matched expr
+        assert_eq!(
+            evalparse("(matchq (f a a) (f (pattern x (blank)) (pattern x (blank))))"),
+            sym("true")
+        );
+        assert_eq!(
+            evalparse("(matchq (f a b) (f (pattern x (blank)) (pattern x (blank))))"),
+            sym("false")
+        );
+
+        // nested patterns, head is pattern
+        assert_eq!(
+            evalparse("(matchq (foo x) ((pattern f (blank)) (pattern y (blank))))"),
+            sym("true")
+        );
+
+        // head matching
+        assert_eq!(
+            evalparse("(matchq (list x) (pattern x (blank list)))"),
+            sym("true")
+        );
+
+        // blank doesnt match the here, which is correct. triple_blank would match this
+        assert_eq!(evalparse("(matchq (f) (f (blank)))"), sym("false"));
+    }
+
+    #[test]
+    fn test_rules_and_replacement() {
+        assert_eq!(
+            evalparse("(replace ((k a) b) (rule ((k (pattern x (blank))) (pattern y (blank))) x))"),
+            sym("a")
+        );
+
+        // list of rules does first one that matches
+        assert_eq!(
+            evalparse("(replace x (List (rule a b) (rule x y)))"),
+            sym("y")
+        );
+        assert_eq!(
+            evalparse("(replace x (List (rule x y) (rule x z)))"),
+            sym("y")
+        );
+
+        // doesn't keep going
+        assert_eq!(
+            evalparse("(replace x (List (rule x y) (rule y z)))"),
+            sym("y")
+        );
+
+        // case where no rules apply
+        assert_eq!(
+            evalparse("(replace_all x (List (rule y a) (rule z b)))"),
+            sym("x")
+        );
+
+        // test for blank with head + nested List
+        assert_eq!(
+            evalparse(r#"(replace_all (List 1 1.5 Pi (List a 2)) (rule (blank Int) "hi"))"#),
+            expr_parser::Expr(r#"(List "hi" 1.5 Pi (List a "hi"))"#).unwrap()
+        );
+
+        assert_eq!(
+            evalparse("(replace_all (List x (power x 2) y z) (List (rule x 1)))"),
+            expr_parser::Expr("(List 1 (power 1 2) y z)").unwrap()
+        );
+
+        assert_eq!(
+            evalparse("(replace_all (List x (power x 2) y z) (List (rule x 1) (rule y 2)))"),
+            expr_parser::Expr("(List 1 (power 1 2) 2 z)").unwrap()
+        );
+
+        assert_eq!(
+            evalparse("(replace_all (plus 1 (pow x 2) (pow x 4)) (rule (pow x (pattern p (blank))) (f p)))"),
+            expr_parser::Expr("(plus 1 (f 2) (f 4))").unwrap()
+        );
+
+        let s = "(replace_repeated (List (f (f x)) (f x) (g (f x)) (f (g (f x)))) (List (rule (f (pattern x (blank))) x)))";
+        // todo test s above to give (List x x (g x) (g x))
+        assert_eq!(
+            evalparse(s),
+            expr_parser::Expr("(List x x (g x) (g x))").unwrap()
+        );
+        // (s k) is false, and k is true
+        // Combinator reduction of And for Tuples[{True, False}]
+        // in boolean logic, you need 3 things to "do everything"
+        // true, false, nand
+        // whats cool about combinators, is you only need 2 things
+        // s and k combinators. everything else is up to interpretation
+
+        let test_cases = vec![
+            ("((((s s) k) (s k)) (s k))", "(s k)"),
+            ("((((s s) k) (s k)) k)", "(s k)"),
+            ("((((s s) k) k) (s k))", "(s k)"),
+            ("((((s s) k) k) k)", "k"),
+        ];
+        let crules_str = "(List (rule (((s (pattern x (blank))) (pattern y (blank))) (pattern z (blank))) ((x z) (y z))) (rule ((k (pattern x (blank))) (pattern y (blank))) x))";
+        for (input, res) in test_cases.iter() {
+            assert_eq!(
+                evalparse(&format!("(rr {} {crules_str})", input)),
+                expr_parser::Expr(res).unwrap()
+            );
+        }
+
+        // let nand = "s[ s[ k[ s[ s[ s][s[k[k[k]]]]]]]][s]";
+        //            (s (s (k (s (s  s) (s (k (k k)))))))
+
+        // let nand = "(((s s) (s (k (k k)))) s)";
+        // let nand = "(((s s) (s (k (k k)))) s)";
+        // let nand = "(s (s (k (s (((s s) (s (k (k k)))))))) s)";
+        // let nand = "(((s (s (k (((s (s s)) (s (k (k k)))))))) s)";
+        // let a = "k";
+        // let b = "k";
+        // let s = format!("(({nand} {a}) {b})");
+        // println!("{}", s);
+    }
+
+    #[test]
+    fn list_ops() {
+        assert_eq!(
+            evalparse("(sameq (Part (f x y z) (List 1 2 3)) (List x y z))"),
+            sym("true")
+        );
+    }
+
+    #[test]
+    fn seqs_and_geeks() {
+        assert_eq!(
+            evalparse("(sameq (f (Sequence a b) c (Sequence d e)) (f a b c d e))"),
+            sym("true")
+        );
+
+        let mut ctx = Context2::new();
+        run_file(&mut ctx, Path::new("lang/attrs.sexp")).unwrap();
+        ctx_evalparse(
+            &mut ctx,
+            "(setd (listq (pattern x (blank))) (sameq list (head x)))",
+        );
+        println!("listq ctx {:?}", ctx);
+        assert_eq!(ctx_evalparse(&mut ctx, "(listq (list a b c))"), sym("true"));
+
+        run_file(&mut ctx, Path::new("lang/startup.sexp")).unwrap();
+        // issue #2
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(sameq (Nest (f) x 2) ((f) ((f) x)))"),
+            sym("true")
+        )
+    }
+
+    #[test]
+    fn new_pattern_matcher() {
+        let blank = list(vec!["blank"]);
+
+        let lhs = list(vec!["f", "a", "b"]);
+        let rhs = list(vec!["f", "blank"]);
+
+        let test_cases = vec![
+            (sym("1"), sym("1"), true),      // goes to "1" == "1" Sym, Sym arm
+            (sym("1"), blank.clone(), true), // Sym Sym arm with blank
+            (sym("1"), liste(vec![sym("1")]), false), // Sym List -> false
+            (liste(vec![sym("1")]), sym("1"), false), // List Sym
+            // (1) | (blank)
+            (liste(vec![sym("1")]), blank.clone(), true), // List, sym, with blank
+            (lhs.clone(), rhs.clone(), false),                 // List, sym, with blank
+            // (lhs.clone(), list(vec!["f", "blank", "blank"]), true), // List, sym, with blank
+            (
+                lhs.clone(),
+                liste(vec![sym("f"), blank.clone(), blank.clone()]),
+                true,
+            ), // List, sym, with blank
+            (sym("f"), list(vec!["blank", "Sym"]), true),
+            (sym("f"), list(vec!["blank", "f"]), false),
+            (list(vec!["f", "x"]), list(vec!["blank", "f"]), true),
+            (list(vec!["f", "x"]), list(vec!["blank", "g"]), false),
+            (parse("(f (a b))"), parse("(f (blank))"), true),
+            (parse("(f (a b))"), parse("(f (blank a))"), true),
+            (parse("(f x)"), parse("((blank) (blank))"), true),
+            (parse("f"), parse("(pattern x (blank))"), true),
+            (parse("(f)"), parse("(pattern x (blank))"), true),
+            (parse("(f x)"), parse("((pattern x (blank)) (blank))"), true),
+            (
+                parse("(f a b c)"),
+                parse("(f (pattern x (blank_seq)))"),
+                true,
+            ),
+            (
+                parse("(f a b c)"),
+                parse("(f (pattern x (blank_seq)) (pattern y (blank_seq)))"),
+                true,
+            ),
+            (
+                parse("(f a a)"),
+                parse("(f (pattern x (blank_seq)) (pattern x (blank_seq)))"),
+                true,
+            ),
+            (
+                parse("(f a (g b))"),
+                parse("(f (pattern x (blank_seq)))"),
+                true,
+            ),
+            (
+                parse("(f a)"),
+                parse("(f (pattern x (blank_null_seq)))"),
+                true,
+            ),
+            (
+                parse("(f a)"),
+                parse("(f (pattern x (blank_null_seq)) a)"),
+                true,
+            ),
+            (
+                parse("(f a b c a b)"),
+                parse("(f (pattern x (blank_seq)) c (pattern x (blank_seq)))"),
+                true,
+            ),
+            (
+                parse("(f (a b) c a b)"),
+                parse("(f (pattern x (blank b)) (pattern y (blank_seq)))"),
+                false,
+            ),
+            (
+                parse("(f (a b) c a b)"),
+                parse("(f (pattern x (blank a)) (pattern y (blank_seq)))"),
+                true,
+            ),
+            (
+                parse("(f a b c d)"),
+                parse("(f (blank_seq) (pattern y (blank_seq)))"),
+                true,
+            ),
+            // fails todo fix blank_seq with head
+            (
+                parse("(f (a b) (a c) (b d))"),
+                parse("(f (pattern x (blank_seq a)))"),
+                false,
+            ),
+            (
+                parse("(f (a b) (a c) (b d))"),
+                parse("(f (pattern x (blank_seq a)) (b d))"),
+                true,
+            ),
+            // pos : Vec<usize> where are we in the pattern Expr
+            (
+                parse("(f (a b) (a c) (b d))"),
+                parse("(f (blank_seq a) (b d))"),
+                true,
+            ),
+            (
+                parse("(f (a b) (a c) (b d))"),
+                parse("(f (blank_seq a))"),
+                false,
+            ),
+        ];
+
+        // list(vec!["f", "a", "b", "c"]), list(vec!["f", sym("blank_sequence")])
+        for (i, (ex, pat, expected)) in test_cases.iter().enumerate() {
+            println!("testing case {i}: {ex} | {pat} ");
+            let pos = vec![];
+            let mut pos_map = HashMap::new();
+            let mut named_map = HashMap::new();
+            let m = my_match(ex.clone(), pat.clone(), &pos, &mut pos_map, &mut named_map);
+            let rebuilt_ex = final_rebuild_and_splice(pat.clone(), &vec![], &pos_map, &named_map);
+            // let rebuilt_ex = rebuild_and_splice(pat.clone(), &vec![], &pos_map, &named_map);
+            println!("rebuilt:{rebuilt_ex:?}\n\npos:\n{pos_map:?}\nnamed:\n{named_map:?}\n\n");
+
+            assert_eq!(m, *expected);
+
+            if *expected {
+                assert_eq!(rebuilt_ex, ex.clone());
+            }
+        }
+    }
+
+    /// https://github.com/anandijain/cas3.rs/issues/1
+    #[test]
+    fn issue_1() {
+        assert_eq!(
+            evalparse("(matchq (f a b 0 c) (f (blank_seq) 0 (blank_seq)))"),
+            sym("true")
+        )
+    }
+    #[test]
+    fn table_tests() {
+        let mut ctx = Context2::new();
+        run_file(&mut ctx, Path::new("lang/attrs.sexp")).unwrap();
+        ctx_evalparse(&mut ctx, "(set xs (List 1 2 3 4 5))");
+
+        let cases = vec![
+            ("(Table f 3)", "(List f f f)"),
+            ("(Table i (List i 3))", "(List 1 2 3)"),
+            ("(Table i (List i 2 4))", "(List 2 3 4)"),
+            ("(Table i (List i 1 6 2))", "(List 1 3 5)"),
+            ("(Table i (List i (List 1.5 3.5)))", "(List 1.5 3.5)"),
+            (
+                "(Table (List i j) (List i 2) (List j 2))",
+                "(List (List (List 1 1) (List 1 2)) (List (List 2 1) (List 2 2)))",
+            ),
+            (
+                "(Table (Part xs (List i (Plus i 1) (Plus i 2))) (List i (Plus (Length xs) -2)))",
+                "(List (List 1 2 3) (List 2 3 4) (List 3 4 5))",
+            ),
+        ];
+        for (lhs, rhs) in cases {
+            let res = ctx_evalparse(&mut ctx, lhs);
+            assert_eq!(res, expr_parser::Expr(rhs).unwrap());
+        }
+    }
+
+    #[test]
+    fn issue_2() {
+        let mut ctx = Context2::new();
+        run_file(&mut ctx, Path::new("lang/attrs.sexp")).unwrap();
+        run_file(&mut ctx, Path::new("lang/startup.sexp")).unwrap();
+        assert_eq!(
+            ctx_evalparse(&mut ctx, "(sameq (Nest (f) x 2) ((f) ((f) x)))"),
+            sym("true")
+        )
+    }
+
+    #[test]
+    fn alternatives_test() {
+        let mut ctx = Context2::new();
+        run_file(&mut ctx, Path::new("lang/attrs.sexp")).unwrap();
+        run_file(&mut ctx, Path::new("lang/startup.sexp")).unwrap();
+        let cases = vec![
+            ("(matchq a (Alternatives a b))", sym("true")),
+            ("(matchq (f a) (f (Alternatives a b)))", sym("true")),
+            (
+                "(matchq (f a b c) (Alternatives a (f (blank_seq))))",
+                sym("true"),
+            ),
+            (
+                "(matchq (f a b c) (Alternatives a (f (pattern xs (blank_seq)))))",
+                sym("true"),
+            ),
+            (
+                "(matchq (f a b c) (Alternatives a (f (blank_seq))))",
+                sym("true"),
+            ),
+            (
+                "(matchq (f) (Alternatives a (f (blank_seq))))",
+                sym("false"),
+            ),
+            // ("(matchq a (Alternatives a b))", sym("true")),
+        ];
+
+        for (c, e) in cases {
+            assert_eq!(ctx_evalparse(&mut ctx, c), e)
+        }
+    }
+}
+
+/*
+exprs/programs to make work
+1.
+
+(set x 1)
+x
+(set y 2)
+(+ x y) => (+ 1 2). I don't think i want/need to implement arithmetic yet
+2.
+k[x_][y_] := x
+(SetDelayed (k (pattern x (blank)) (pattern y (blank))) x)
+
+f[x_] := {x, x^2, x^3}
+f[y] # gives {x, x^2, x^3}
+(SetDelayed (f (pattern x (blank)) (list x (pow x 2) (pow x 3)))
+
+3.
+(matchq x x) # true
+(matchq x y) # false
+(matchq x (pattern (blank))) # true
+(matchq (list a) (pattern (blank))) # true
+
+4. (most important right now)
+SetDelayed[fib[Pattern[n, Blank[]]], Plus[fib[Plus[n, -1]], fib[Plus[n, -2]]]]]
+
+(set (fib 1) (fib 0))
+(set (fib 0) 1)
+(set_delayed (fib (pattern n (blank))) (plus (fib (minus n 1)) (fib (minus n 2))))
+(set_delayed (fib (pattern n (blank Int))) (plus (fib (minus n 1)) (fib (minus n 2))))
+(fib 5)
+
+okay so we make a new hashmap called DownValues that is a HashMap of symbol to list of exprs
+this list of expr is all the downvalues.
+so
+f[x_][y_] := x
+f[x_] := 1
+
+f[x][y] # 1
+
+k[x_][y_] := x
+k[x][y] # x
+
+so it does the recursive thing, doesn't find any pattern matching (k x), which it looks to find first, shown by the f example
+then goes out to see if there is a more nested pattern that matches, which is the k example
+
+
+one thing that mathematica does is it actually stores (fib 2, 3,... ) in the evaluation of fib(5)
+
+
+notes:
+currently this crashes the interpreter because it goes into an infinite loop (no fixed point)
+(set (f) (f f)
+(set (f f) (f))
+
+f[x_] := x
+f[1]
+
+(setd (f (pattern x (blank))) (f x))
+(f 1) so basically wh
+
+(f (list 1))
+
+
+------
+TODO actually make testing
+
+(set (a b) c)
+(a b) == c
+(set b 1)
+(a b) == (a 1)
+
+------
+need to make
+(set x (plus x 1))
+crash the program
+and
+(setd x (plus x 1)) not
+but (setd x (plus x 1)), (x) should crash the program
+
+
+
+f[x_]:=g[y_]:=y
+f[1] === Null # True
+but note that if you try to call g before f, then g is undefined
+so
+ff[x_]:=gg[y_]:=y
+gg[1] # gives gg[1]
+but then
+ff[1]
+gg[1] # now gives 1
+
+also note that
+x=1
+x=2
+works because Set is HoldFirst
+
+
+https://mathematica.stackexchange.com/questions/176732/can-a-symbol-have-more-than-one-ownvalue
+i will keep TableEntry.own as a list expr, but since I am not going to do conditional evaluation, it will only have one element
+if set manually by the user, through OwnValues[x] = ..., i panic if more than one
+
+one interesting thing is how to set Set attributes to HoldFirst and Setd before calling Set and Setd.
+maybe have to manually put in those DownValues of Attributes manually in rust and not in startup
+can also just hardcode it in evaluate to never evaluate the first argument of Set and the rest
+
+
+apply just replaces list with arg[1]
+apply[f, {a, b, c}] # f[a, b, c]
+
+
+*/
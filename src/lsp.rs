@@ -0,0 +1,468 @@
+//! a language server for .sexp files, so editors get diagnostics, go to definition, hover,
+//! completion and an outline. `cas3-lsp` runs it over stdin and stdout.
+//!
+//! nothing gets evaluated. a file is split into forms with the highlighter's tokens, and the
+//! definitions are the top level `(set lhs rhs)` and `(setd lhs rhs)` forms. go to definition
+//! and hover look through the open files and whatever they `(Get "...")`, relative to the
+//! workspace root or the file itself.
+
+use crate::highlight::{tokens, Token};
+use crate::{help, parse_errors, unescape, Syntax};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// a token, or a bracketed list of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tree {
+    Atom(Range<usize>, Token),
+    /// an unclosed list ends at the end of the file
+    List(Range<usize>, Vec<Tree>),
+}
+
+impl Tree {
+    fn range(&self) -> Range<usize> {
+        match self {
+            Tree::Atom(r, _) | Tree::List(r, _) => r.clone(),
+        }
+    }
+
+    fn symbol<'a>(&self, text: &'a str) -> Option<&'a str> {
+        match self {
+            Tree::Atom(r, Token::Symbol) => Some(&text[r.clone()]),
+            _ => None,
+        }
+    }
+
+    fn children(&self) -> &[Tree] {
+        match self {
+            Tree::List(_, children) => children,
+            Tree::Atom(..) => &[],
+        }
+    }
+}
+
+/// the top level forms, and the brackets that don't pair up
+fn trees(text: &str) -> (Vec<Tree>, Vec<(Range<usize>, String)>) {
+    let mut stack: Vec<(usize, Vec<Tree>)> = vec![(0, vec![])];
+    let mut errors = vec![];
+    for (range, token) in tokens(text, Syntax::Sexp) {
+        match token {
+            Token::Space | Token::Comment => {}
+            Token::Open => stack.push((range.start, vec![])),
+            Token::Close if stack.len() == 1 => errors.push((range, "unmatched )".to_string())),
+            Token::Close => {
+                let (start, children) = stack.pop().unwrap();
+                stack.last_mut().unwrap().1.push(Tree::List(start..range.end, children));
+            }
+            t => stack.last_mut().unwrap().1.push(Tree::Atom(range, t)),
+        }
+    }
+    while stack.len() > 1 {
+        let (start, children) = stack.pop().unwrap();
+        errors.push((start..start + 1, "unclosed (".to_string()));
+        stack.last_mut().unwrap().1.push(Tree::List(start..text.len(), children));
+    }
+    (stack.pop().unwrap().1, errors)
+}
+
+/// a top level `(set lhs rhs)`, `(setd lhs rhs)` or `(SetAttributes f attrs)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    /// the whole form
+    pub range: Range<usize>,
+    pub name_range: Range<usize>,
+    pub lhs: Range<usize>,
+    pub rhs: Option<Range<usize>>,
+    /// `(set (attrs f) ...)` or `(SetAttributes f ...)`, which give f attributes rather than a value
+    pub attrs: bool,
+    /// `(set (f ...) ...)` rather than `(set f ...)`
+    pub down: bool,
+}
+
+fn definition(text: &str, form: &Tree) -> Option<Definition> {
+    let [head, lhs, rest @ ..] = form.children() else { return None };
+    let (attrs, mut name) = match head.symbol(text) {
+        Some("SetAttributes") if lhs.symbol(text).is_some() => (true, lhs),
+        Some("set" | "setd") if lhs.children().first().and_then(|h| h.symbol(text)) == Some("attrs") => {
+            (true, lhs.children().get(1)?)
+        }
+        Some("set" | "setd") => (false, lhs),
+        _ => return None,
+    };
+    // the symbol the rule ends up on: f in f, (f x) and ((f x) y)
+    while let Some(first) = name.children().first() {
+        name = first;
+    }
+    Some(Definition {
+        name: name.symbol(text)?.to_string(),
+        range: form.range(),
+        name_range: name.range(),
+        lhs: lhs.range(),
+        rhs: rest.first().map(Tree::range),
+        attrs,
+        down: matches!(lhs, Tree::List(..)),
+    })
+}
+
+/// what the server knows about one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    pub text: String,
+    pub definitions: Vec<Definition>,
+    /// the paths of the top level `(Get "...")`s
+    pub gets: Vec<String>,
+    /// byte ranges and messages
+    pub diagnostics: Vec<(Range<usize>, String)>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let (forms, mut diagnostics) = trees(&text);
+        // with the brackets all paired up the parser's complaints are the interesting ones
+        if diagnostics.is_empty() {
+            for (offset, message) in parse_errors(&text, Syntax::Sexp) {
+                let end = text[offset..].chars().next().map_or(offset, |c| offset + c.len_utf8());
+                diagnostics.push((offset..end, message));
+            }
+        }
+        let definitions = forms.iter().filter_map(|f| definition(&text, f)).collect();
+        let gets = forms
+            .iter()
+            .filter_map(|f| match f.children() {
+                [head, Tree::Atom(r, Token::Str), ..] if head.symbol(&text) == Some("Get") => {
+                    unescape(&text[r.start + 1..r.end - 1]).ok()
+                }
+                _ => None,
+            })
+            .collect();
+        Document { text, definitions, gets, diagnostics }
+    }
+
+    /// the symbol under or just before byte `offset`
+    pub fn symbol_at(&self, offset: usize) -> Option<(Range<usize>, &str)> {
+        tokens(&self.text, Syntax::Sexp)
+            .into_iter()
+            .rev()
+            .find(|(r, t)| *t == Token::Symbol && r.start <= offset && offset <= r.end)
+            .map(|(r, _)| (r.clone(), &self.text[r]))
+    }
+}
+
+/// an LSP position, a line and a column in utf-16 units
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let col: usize = before.rsplit('\n').next().unwrap_or("").chars().map(char::len_utf16).sum();
+    json!({"line": line, "character": col})
+}
+
+fn range(text: &str, r: &Range<usize>) -> Value {
+    json!({"start": position(text, r.start), "end": position(text, r.end)})
+}
+
+/// the byte offset of an LSP position
+fn offset(text: &str, pos: &Value) -> usize {
+    let line = pos["line"].as_u64().unwrap_or(0) as usize;
+    let character = pos["character"].as_u64().unwrap_or(0) as usize;
+    let start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+pub fn uri_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (b, hex) {
+            (b'%', Some(h)) => {
+                bytes.push(h);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+pub fn path_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for b in path.display().to_string().bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(b as char),
+            b => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+    uri
+}
+
+#[derive(Default)]
+pub struct Server {
+    /// the open files, by uri
+    docs: HashMap<String, Document>,
+    root: Option<PathBuf>,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// where `(Get path)` in the file at `uri` points
+    fn resolve(&self, uri: &str, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        let dir = uri_path(uri).and_then(|p| p.parent().map(Path::to_path_buf));
+        let candidates = [self.root.clone(), dir].into_iter().flatten().map(|d| d.join(path));
+        std::iter::once(path.to_path_buf()).filter(|p| p.is_absolute()).chain(candidates).find(|p| p.is_file())
+    }
+
+    /// the open files and everything they `Get`, read from disk unless they're open too
+    fn workspace(&self) -> Vec<(String, Document)> {
+        let mut out: Vec<(String, Document)> = self.docs.iter().map(|(u, d)| (u.clone(), d.clone())).collect();
+        let mut seen: HashSet<String> = self.docs.keys().cloned().collect();
+        let mut i = 0;
+        while i < out.len() {
+            let (uri, doc) = &out[i];
+            let found: Vec<PathBuf> = doc.gets.iter().filter_map(|g| self.resolve(uri, g)).collect();
+            for path in found {
+                let uri = path_uri(&path);
+                if seen.insert(uri.clone()) {
+                    if let Ok(text) = std::fs::read_to_string(&path) {
+                        out.push((uri, Document::new(text)));
+                    }
+                }
+            }
+            i += 1;
+        }
+        out
+    }
+
+    /// the document and byte offset a `textDocument/...` request is about
+    fn at(&self, params: &Value) -> Option<(&Document, usize)> {
+        let doc = self.docs.get(params["textDocument"]["uri"].as_str()?)?;
+        Some((doc, offset(&doc.text, &params["position"])))
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let diagnostics: Vec<Value> = self.docs.get(uri).map_or(vec![], |doc| {
+            doc.diagnostics
+                .iter()
+                .map(|(r, message)| json!({"range": range(&doc.text, r), "severity": 1, "source": "cas3", "message": message}))
+                .collect()
+        });
+        notification("textDocument/publishDiagnostics", json!({"uri": uri, "diagnostics": diagnostics}))
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((doc, offset)) = self.at(params) else { return Value::Null };
+        let Some((_, name)) = doc.symbol_at(offset) else { return Value::Null };
+        let locations: Vec<Value> = self
+            .workspace()
+            .iter()
+            .flat_map(|(uri, d)| {
+                d.definitions
+                    .iter()
+                    .filter(|def| def.name == name && !def.attrs)
+                    .map(move |def| json!({"uri": uri, "range": range(&d.text, &def.name_range)}))
+            })
+            .collect();
+        json!(locations)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((doc, offset)) = self.at(params) else { return Value::Null };
+        let Some((r, name)) = doc.symbol_at(offset) else { return Value::Null };
+        let mut lines = vec![format!("**{name}**")];
+        if let Some((_, usages, summary)) = help::builtin(name) {
+            lines.push(format!("```cas3\n{}\n```", usages.join("\n")));
+            lines.push(summary.to_string());
+        }
+        let workspace = self.workspace();
+        let defs: Vec<(&Document, &Definition)> = workspace
+            .iter()
+            .flat_map(|(_, d)| d.definitions.iter().filter(|def| def.name == name).map(move |def| (d, def)))
+            .collect();
+        for (d, def) in defs.iter().filter(|(_, def)| def.attrs) {
+            if let Some(rhs) = &def.rhs {
+                lines.push(format!("attributes: `{}`", &d.text[rhs.clone()]));
+            }
+        }
+        for (what, down) in [("OwnValues", false), ("DownValues", true)] {
+            let rules: Vec<&str> = defs
+                .iter()
+                .filter(|(_, def)| !def.attrs && def.down == down)
+                .map(|(d, def)| &d.text[def.range.clone()])
+                .collect();
+            if !rules.is_empty() {
+                lines.push(format!("{what}:\n```cas3\n{}\n```", rules.join("\n")));
+            }
+        }
+        if lines.len() == 1 {
+            return Value::Null;
+        }
+        json!({"contents": {"kind": "markdown", "value": lines.join("\n\n")}, "range": range(&doc.text, &r)})
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let Some((doc, offset)) = self.at(params) else { return json!([]) };
+        let start = help::word_start(&doc.text, offset, Syntax::Sexp);
+        let prefix = &doc.text[start..offset];
+        let mut items: Vec<Value> = help::BUILTINS
+            .iter()
+            .filter(|(n, _, _)| n.starts_with(prefix))
+            .map(|(n, _, summary)| json!({"label": n, "kind": 3, "detail": summary}))
+            .collect();
+        let mut defined: Vec<(String, bool)> = vec![];
+        for (_, d) in self.workspace() {
+            defined.extend(d.definitions.into_iter().filter(|def| !def.attrs).map(|def| (def.name, def.down)));
+        }
+        defined.sort();
+        defined.dedup_by(|a, b| a.0 == b.0);
+        for (name, down) in defined {
+            if name.starts_with(prefix) && help::builtin(&name).is_none() {
+                // functions and variables
+                items.push(json!({"label": name, "kind": if down { 3 } else { 6 }}));
+            }
+        }
+        json!(items)
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let Some(doc) = params["textDocument"]["uri"].as_str().and_then(|u| self.docs.get(u)) else {
+            return json!([]);
+        };
+        let symbols: Vec<Value> = doc
+            .definitions
+            .iter()
+            .map(|def| {
+                json!({
+                    "name": def.name,
+                    "detail": &doc.text[def.lhs.clone()],
+                    "kind": if def.attrs { 7 } else if def.down { 12 } else { 13 },
+                    "range": range(&doc.text, &def.range),
+                    "selectionRange": range(&doc.text, &def.name_range),
+                })
+            })
+            .collect();
+        json!(symbols)
+    }
+
+    /// handles one message, giving the responses and notifications to send back
+    pub fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+        let result = match msg["method"].as_str().unwrap_or("") {
+            "initialize" => {
+                self.root = params["rootUri"].as_str().and_then(uri_path);
+                json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "hoverProvider": true,
+                        "completionProvider": {},
+                        "documentSymbolProvider": true,
+                    },
+                    "serverInfo": {"name": "cas3-lsp", "version": env!("CARGO_PKG_VERSION")},
+                })
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.docs.insert(uri.clone(), Document::new(text.to_string()));
+                return vec![self.diagnostics(&uri)];
+            }
+            // we only ask for whole documents
+            "textDocument/didChange" => {
+                let Some(text) = params["contentChanges"].as_array().and_then(|c| c.last()?["text"].as_str()) else {
+                    return vec![];
+                };
+                self.docs.insert(uri.clone(), Document::new(text.to_string()));
+                return vec![self.diagnostics(&uri)];
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(&uri);
+                return vec![self.diagnostics(&uri)];
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            method => {
+                // notifications we don't care about need no answer
+                if msg.get("id").is_none() {
+                    return vec![];
+                }
+                let error = json!({"code": -32601, "message": format!("{method} isn't supported")});
+                return vec![json!({"jsonrpc": "2.0", "id": msg["id"], "error": error})];
+            }
+        };
+        vec![json!({"jsonrpc": "2.0", "id": msg["id"], "result": result})]
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+/// the next message, or None at the end of the input
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub fn write_message(output: &mut impl Write, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// serves until an exit notification or the end of the input. gives whether that was after
+/// a shutdown request, which is what the exit status should say
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::new();
+    while let Some(msg) = read_message(&mut input)? {
+        if msg["method"] == "exit" {
+            break;
+        }
+        for reply in server.handle(&msg) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    Ok(server.shutdown)
+}
@@ -0,0 +1,234 @@
+//! `cas3 fmt` and the `Format` builtin: lays s-expression source out to fit a width.
+//!
+//! a form that fits on the rest of its line stays on one line. one that doesn't keeps its head
+//! on the first line and puts each argument on its own line, two spaces in. definitions (`set`,
+//! `setd`, `rule`, `rule_delayed`) keep their left hand side up with the head, so the rule reads
+//! as `(setd lhs` then the body. literals are kept exactly as written, a comment stays in front
+//! of the form after it or at the end of the line it was on, and one blank line between top
+//! level forms is kept. formatting formatted source changes nothing.
+
+use crate::highlight::{tokens, Token};
+use crate::{parse_errors, Syntax};
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+pub const USAGE: &str = "usage: cas3 fmt [--width N] [--check] [file...]
+
+formats s-expression source. files are rewritten in place, with none it reads stdin and
+writes stdout.
+
+  --width N    fit lines in N columns (default 80)
+  --check      only list the files that would change, and exit 1 if any would
+";
+
+/// heads whose first argument stays on the head's line
+const DEFINITION_HEADS: &[&str] = &["set", "setd", "rule", "rule_delayed"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Atom(String),
+    /// `trailing` if it was on the same line as what came before it
+    Comment { text: String, trailing: bool },
+    List(Vec<Node>),
+    /// empty lines between top level forms
+    Blank,
+}
+
+fn nodes(text: &str) -> Vec<Node> {
+    let mut stack: Vec<Vec<Node>> = vec![vec![]];
+    let mut prev_end = None;
+    for (r, token) in tokens(text, Syntax::Sexp) {
+        if token == Token::Space {
+            continue;
+        }
+        let newlines = prev_end.map(|e| text[e..r.start].matches('\n').count());
+        let top = stack.len() == 1;
+        let level = stack.last_mut().unwrap();
+        if top && newlines >= Some(2) {
+            level.push(Node::Blank);
+        }
+        match token {
+            Token::Open => stack.push(vec![]),
+            Token::Close => {
+                let list = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Node::List(list));
+            }
+            Token::Comment => {
+                let trailing = newlines == Some(0) && !level.is_empty();
+                level.push(Node::Comment { text: text[r.clone()].to_string(), trailing });
+            }
+            _ => level.push(Node::Atom(text[r.clone()].to_string())),
+        }
+        prev_end = Some(r.end);
+    }
+    stack.pop().unwrap()
+}
+
+/// on one line, if it can be. lists with comments in them never are
+fn flat(node: &Node) -> Option<String> {
+    match node {
+        Node::Atom(a) if !a.contains('\n') => Some(a.clone()),
+        Node::List(children) => {
+            let parts: Option<Vec<String>> = children.iter().map(flat).collect();
+            Some(format!("({})", parts?.join(" ")))
+        }
+        _ => None,
+    }
+}
+
+fn column(out: &str) -> usize {
+    out.rsplit('\n').next().unwrap_or("").chars().count()
+}
+
+/// writes `node` starting at the current end of `out`. `trail` closing parens will follow it
+fn layout(node: &Node, trail: usize, width: usize, out: &mut String) {
+    let indent = column(out);
+    let children = match node {
+        Node::Atom(text) | Node::Comment { text, .. } => return out.push_str(text),
+        Node::Blank => return,
+        Node::List(children) => children,
+    };
+    if let Some(line) = flat(node) {
+        if indent + line.chars().count() + trail <= width {
+            return out.push_str(&line);
+        }
+    }
+    let definition = matches!(children.first(), Some(Node::Atom(h)) if DEFINITION_HEADS.contains(&h.as_str()));
+    // arguments go under a head that's a list itself, like ((f x) y)
+    let body = if matches!(children.first(), Some(Node::List(_))) { indent + 1 } else { indent + 2 };
+    out.push('(');
+    // nothing but atoms, like a long list of numbers, fills each line
+    if children.iter().all(|c| flat(c).is_some() && matches!(c, Node::Atom(_))) {
+        for (i, child) in children.iter().enumerate() {
+            let text = flat(child).unwrap();
+            let end = if i + 1 == children.len() { trail + 1 } else { 0 };
+            if i > 0 && column(out) + 1 + text.chars().count() + end > width && column(out) > body {
+                out.push('\n');
+                out.push_str(&" ".repeat(body));
+            } else if i > 0 {
+                out.push(' ');
+            }
+            out.push_str(&text);
+        }
+        return out.push(')');
+    }
+    let mut args = 0;
+    for (i, child) in children.iter().enumerate() {
+        let is_comment = matches!(child, Node::Comment { .. });
+        let same_line = i == 0
+            || matches!(child, Node::Comment { trailing: true, .. })
+            || (definition && args == 1 && !is_comment && !matches!(children[i - 1], Node::Comment { .. }));
+        if i > 0 && same_line {
+            out.push(' ');
+        } else if i > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(body));
+        }
+        let last = i + 1 == children.len();
+        layout(child, if last { trail + 1 } else { 0 }, width, out);
+        if !is_comment {
+            args += 1;
+        }
+    }
+    out.push(')');
+}
+
+/// `text` laid out to fit in `width` columns. source that doesn't parse is an error rather than
+/// getting mangled
+pub fn format_source(text: &str, width: usize) -> Result<String, String> {
+    if let Some((offset, message)) = parse_errors(text, Syntax::Sexp).into_iter().next() {
+        let before = &text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        return Err(format!("{line}:{col}: {message}"));
+    }
+    let mut out = String::new();
+    for node in nodes(text) {
+        match node {
+            Node::Blank => out.push('\n'),
+            Node::Comment { text, trailing: true } => {
+                out.pop();
+                out.push(' ');
+                out.push_str(&text);
+                out.push('\n');
+            }
+            node => {
+                layout(&node, 0, width, &mut out);
+                out.push('\n');
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// `cas3 fmt ...`, given what comes after `fmt`. gives the exit status
+pub fn run(args: &[String]) -> i32 {
+    let mut width = DEFAULT_WIDTH;
+    let mut check = false;
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return 0;
+            }
+            "--check" => check = true,
+            "--width" => match args.next().and_then(|w| w.parse().ok()) {
+                Some(w) => width = w,
+                None => {
+                    eprintln!("--width needs a number\n\n{USAGE}");
+                    return 2;
+                }
+            },
+            f if f.starts_with('-') => {
+                eprintln!("unknown option {f}\n\n{USAGE}");
+                return 2;
+            }
+            file => files.push(file),
+        }
+    }
+
+    if files.is_empty() {
+        let mut src = String::new();
+        if let Err(err) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut src) {
+            eprintln!("cannot read stdin: {err}");
+            return 2;
+        }
+        return match format_source(&src, width) {
+            Ok(out) if check => (out != src) as i32,
+            Ok(out) => {
+                print!("{out}");
+                0
+            }
+            Err(err) => {
+                eprintln!("stdin:{err}");
+                2
+            }
+        };
+    }
+    let mut status = 0;
+    for file in files {
+        let formatted = std::fs::read_to_string(file)
+            .map_err(|e| format!("cannot read {file}: {e}"))
+            .and_then(|src| Ok((format_source(&src, width).map_err(|e| format!("{file}:{e}"))?, src)));
+        match formatted {
+            Ok((out, src)) if out == src => {}
+            Ok(_) if check => {
+                println!("{file}");
+                status = status.max(1);
+            }
+            Ok((out, _)) => {
+                if let Err(err) = std::fs::write(file, out) {
+                    eprintln!("cannot write {file}: {err}");
+                    status = 2;
+                }
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                status = 2;
+            }
+        }
+    }
+    status
+}
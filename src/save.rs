@@ -0,0 +1,274 @@
+//! keeping definitions around between sessions.
+//!
+//! `(Save "file" f ...)` appends the definitions of f, and of the symbols they use, as source
+//! that `Get` reads back. `(DumpSave "file")` writes the whole `Context2` in a binary format
+//! instead, which `Get` recognizes by its first bytes.
+//!
+//! the binary format is a magic string, then a table of every symbol name, then each table entry
+//! as its key, its own, down and sub values and its attributes. expressions are a tag byte followed by
+//! the value, with lengths and symbol indices as LEB128 varints.
+
+use crate::{attributes_of, contexts, help, liste, sym, Context2, Expr, TableEntry};
+use num_bigint::BigInt;
+use ordered_float::NotNan;
+use std::collections::{HashMap, HashSet};
+
+/// the magic without the version byte at the end
+pub const DUMP_PREFIX: &[u8] = b"cas3dump";
+pub const DUMP_MAGIC: &[u8] = b"cas3dump\x02";
+
+/// symbols a saved definition can lean on without them being saved too: builtins and the rest of
+/// System`. a user's function is saved even if it's Protected
+fn is_system(ctx: &Context2, s: &Expr) -> bool {
+    let Expr::Sym(name) = s else { return true };
+    help::builtin(name).is_some() || contexts::context_of(ctx, name) == contexts::SYSTEM
+}
+
+fn collect_symbols(ex: &Expr, out: &mut Vec<Expr>) {
+    match ex {
+        Expr::Sym(_) => out.push(ex.clone()),
+        Expr::List(ls) => ls.iter().for_each(|e| collect_symbols(e, out)),
+        _ => {}
+    }
+}
+
+/// `roots` and every symbol with definitions their definitions mention, roots first
+pub fn dependencies(ctx: &Context2, roots: &[Expr]) -> Vec<Expr> {
+    let mut seen: HashSet<Expr> = roots.iter().cloned().collect();
+    let mut order = roots.to_vec();
+    let mut i = 0;
+    while i < order.len() {
+        let mut mentioned = vec![];
+        if let Some(te) = ctx.vars.get(&order[i]) {
+            te.own.iter().chain([&te.down, &te.sub]).for_each(|e| collect_symbols(e, &mut mentioned));
+        }
+        for s in mentioned {
+            if ctx.vars.contains_key(&s) && !is_system(ctx, &s) && seen.insert(s.clone()) {
+                order.push(s);
+            }
+        }
+        i += 1;
+    }
+    order
+}
+
+/// the definitions of `roots` and their dependencies as s-expressions that recreate them exactly.
+/// everything is written with setd so nothing gets evaluated again on the way back in
+pub fn save_source(ctx: &Context2, roots: &[Expr]) -> String {
+    let mut out = String::new();
+    for s in dependencies(ctx, roots) {
+        let Some(te) = ctx.vars.get(&s) else { continue };
+        if let Some(own) = &te.own {
+            out.push_str(&format!("(setd {s} {own})\n"));
+        }
+        for dv in &te.down[1..] {
+            out.push_str(&format!("(setd {} {})\n", dv[1][1], dv[2]));
+        }
+        // after the rules, which a Protected symbol wouldn't take
+        if let Some(attrs) = attributes_of(ctx, &s) {
+            out.push_str(&format!("(SetAttributes {s} {attrs})\n"));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+struct Writer {
+    out: Vec<u8>,
+    symbols: HashMap<Expr, usize>,
+}
+
+impl Writer {
+    fn expr(&mut self, ex: &Expr) {
+        match ex {
+            Expr::Int(n) => {
+                self.out.push(0);
+                let bytes = n.to_signed_bytes_le();
+                write_varint(&mut self.out, bytes.len());
+                self.out.extend(bytes);
+            }
+            Expr::Real(r) => {
+                self.out.push(1);
+                self.out.extend(r.into_inner().to_le_bytes());
+            }
+            Expr::Sym(_) => {
+                self.out.push(2);
+                write_varint(&mut self.out, self.symbols[ex]);
+            }
+            Expr::Str(s) => {
+                self.out.push(3);
+                write_varint(&mut self.out, s.len());
+                self.out.extend(s.as_bytes());
+            }
+            Expr::List(ls) => {
+                self.out.push(4);
+                write_varint(&mut self.out, ls.len());
+                ls.iter().for_each(|e| self.expr(e));
+            }
+        }
+    }
+}
+
+fn number_symbols(ex: &Expr, symbols: &mut HashMap<Expr, usize>, names: &mut Vec<String>) {
+    match ex {
+        Expr::Sym(s) if !symbols.contains_key(ex) => {
+            symbols.insert(ex.clone(), names.len());
+            names.push(s.to_string());
+        }
+        Expr::List(ls) => ls.iter().for_each(|e| number_symbols(e, symbols, names)),
+        _ => {}
+    }
+}
+
+/// the whole context, or just `only` and their dependencies, as bytes
+pub fn dump(ctx: &Context2, only: Option<&[Expr]>) -> Vec<u8> {
+    let entry = |k: &Expr, te: &TableEntry| {
+        let attrs = liste(std::iter::once(sym("List")).chain(te.attrs.iter().cloned()).collect());
+        (k.clone(), te.own.clone(), te.down.clone(), te.sub.clone(), attrs)
+    };
+    let mut entries: Vec<(Expr, Option<Expr>, Expr, Expr, Expr)> = match only {
+        None => ctx.vars.iter().map(|(k, te)| entry(k, te)).collect(),
+        Some(roots) => dependencies(ctx, roots).iter().filter_map(|k| Some(entry(k, ctx.vars.get(k)?))).collect(),
+    };
+    // a stable order makes equal contexts dump to equal bytes
+    entries.sort_by_cached_key(|(k, ..)| k.to_string());
+
+    let mut symbols = HashMap::new();
+    let mut names = vec![];
+    for (k, own, down, sub, attrs) in &entries {
+        for ex in [k, down, sub, attrs].into_iter().chain(own) {
+            number_symbols(ex, &mut symbols, &mut names);
+        }
+    }
+    let mut w = Writer { out: DUMP_MAGIC.to_vec(), symbols };
+    write_varint(&mut w.out, names.len());
+    for name in &names {
+        write_varint(&mut w.out, name.len());
+        w.out.extend(name.as_bytes());
+    }
+    write_varint(&mut w.out, entries.len());
+    for (k, own, down, sub, attrs) in &entries {
+        w.expr(k);
+        match own {
+            Some(own) => {
+                w.out.push(1);
+                w.expr(own);
+            }
+            None => w.out.push(0),
+        }
+        w.expr(down);
+        w.expr(sub);
+        w.expr(attrs);
+    }
+    w.out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    names: Vec<Expr>,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let b = *self.bytes.get(self.pos).ok_or("the dump ends early")?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        let end = self.pos.checked_add(n).filter(|&e| e <= self.bytes.len()).ok_or("the dump ends early")?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<usize, String> {
+        let mut n = 0usize;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            n |= ((b & 0x7f) as usize) << shift;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err("a length that doesn't fit".into())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let n = self.varint()?;
+        String::from_utf8(self.take(n)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        Ok(match self.byte()? {
+            0 => {
+                let n = self.varint()?;
+                Expr::Int(BigInt::from_signed_bytes_le(self.take(n)?))
+            }
+            1 => {
+                let bytes = self.take(8)?.try_into().unwrap();
+                Expr::Real(NotNan::new(f64::from_le_bytes(bytes)).map_err(|e| e.to_string())?)
+            }
+            2 => {
+                let i = self.varint()?;
+                self.names.get(i).cloned().ok_or("a symbol that isn't in the table")?
+            }
+            3 => Expr::Str(self.string()?),
+            4 => {
+                let n = self.varint()?;
+                let items: Result<Vec<Expr>, String> = (0..n).map(|_| self.expr()).collect();
+                liste(items?)
+            }
+            t => return Err(format!("unknown tag {t}")),
+        })
+    }
+}
+
+/// puts what `dump` wrote back into `ctx`, replacing what's there. gives how many entries it read
+pub fn load(ctx: &mut Context2, bytes: &[u8]) -> Result<usize, String> {
+    let body = match bytes.strip_prefix(DUMP_MAGIC) {
+        Some(body) => body,
+        None if bytes.starts_with(DUMP_PREFIX) => return Err("a dump from another version of cas3".into()),
+        None => return Err("not a cas3 dump".into()),
+    };
+    let mut r = Reader { bytes: body, pos: 0, names: vec![] };
+    let n = r.varint()?;
+    for _ in 0..n {
+        let name = r.string()?;
+        r.names.push(sym(&name));
+    }
+    let n = r.varint()?;
+    for _ in 0..n {
+        let key = r.expr()?;
+        let own = match r.byte()? {
+            0 => None,
+            _ => Some(r.expr()?),
+        };
+        // down and sub values and attributes are all (List ...), the head is always there
+        let mut headed = |what: &str| match r.expr()? {
+            Expr::List(ls) if !ls.is_empty() => Ok(Expr::List(ls)),
+            _ => Err(format!("{what} that aren't a list")),
+        };
+        let (down, sub, attrs) = (headed("down values")?, headed("sub values")?, headed("attributes")?);
+        let attrs = attrs[1..].to_vec();
+        ctx.vars.insert(key, TableEntry { own, down, sub, memo: HashMap::new(), attrs });
+    }
+    if r.pos != body.len() {
+        return Err("junk after the dump".into());
+    }
+    ctx.bump_version();
+    Ok(n)
+}
@@ -0,0 +1,200 @@
+//! string patterns for `StringMatchQ`, `StringCases`, `StringReplace` and `StringPosition`.
+//!
+//! a pattern like `(StringExpression "a" (pattern x (blank_seq)) "b")` gets compiled down to a
+//! regex, with a named group for every `pattern` so the bindings can be turned back into rules
+//! the way `replace` does it. a name used twice becomes a backreference, so it has to match the
+//! same text both times.
+
+use crate::{head, liste, pat_bindings_to_rules, replace_all, sym, Expr};
+use fancy_regex::{Captures, Regex};
+use std::collections::HashMap;
+
+pub struct StringPattern {
+    re: Regex,
+    /// the same thing anchored at both ends
+    whole: Regex,
+    /// regex group name -> the `(pattern x ...)` it came from
+    groups: Vec<(String, Expr)>,
+}
+
+/// a match starting at some position: where it ends (in bytes) and what the named parts bound to
+pub struct StringMatch {
+    pub end: usize,
+    pub bindings: HashMap<Expr, Expr>,
+}
+
+impl StringPattern {
+    pub fn compile(pat: &Expr) -> Result<Self, String> {
+        let mut groups = vec![];
+        let body = to_regex(pat, &mut groups)?;
+        let re = Regex::new(&body).map_err(|e| e.to_string())?;
+        let whole = Regex::new(&format!(r"\A(?:{body})\z")).map_err(|e| e.to_string())?;
+        Ok(StringPattern { re, whole, groups })
+    }
+
+    /// the leftmost match starting at byte `pos` of `s` or later, with where it starts.
+    /// a regex that blows the backtracking limit counts as not matching
+    pub fn find_from(&self, s: &str, pos: usize) -> Option<(usize, StringMatch)> {
+        let caps = self.re.captures_from_pos(s, pos).ok()??;
+        let m = caps.get(0)?;
+        Some((m.start(), self.bind(&caps, m.end())))
+    }
+
+    fn bind(&self, caps: &Captures, end: usize) -> StringMatch {
+        let bindings = self
+            .groups
+            .iter()
+            .filter_map(|(group, pat)| Some((pat.clone(), Expr::Str(caps.name(group)?.as_str().to_string()))))
+            .collect();
+        StringMatch { end, bindings }
+    }
+
+    pub fn matches_whole(&self, s: &str) -> bool {
+        matches!(self.whole.is_match(s), Ok(true))
+    }
+}
+
+fn class(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "DigitCharacter" => r"\d",
+        "LetterCharacter" => r"\p{L}",
+        "WhitespaceCharacter" => r"\s",
+        "Whitespace" => r"\s+",
+        "WordCharacter" => r"\w",
+        "StartOfString" => r"\A",
+        "EndOfString" => r"\z",
+        _ => return None,
+    })
+}
+
+fn to_regex(pat: &Expr, groups: &mut Vec<(String, Expr)>) -> Result<String, String> {
+    let h = head(pat);
+    Ok(match pat {
+        Expr::Str(s) => fancy_regex::escape(s).into_owned(),
+        Expr::Sym(s) => match class(s) {
+            Some(c) => c.to_string(),
+            None => return Err(format!("{s} is not a string pattern")),
+        },
+        Expr::List(_) if h == sym("StringExpression") => {
+            let parts: Result<Vec<String>, String> = pat[1..].iter().map(|p| to_regex(p, groups)).collect();
+            parts?.concat()
+        }
+        Expr::List(_) if h == sym("RegularExpression") => match &pat[1..] {
+            [Expr::Str(re)] => format!("(?:{re})"),
+            _ => return Err(format!("{pat} should be (RegularExpression \"...\")")),
+        },
+        Expr::List(_) if h == sym("Alternatives") => {
+            let parts: Result<Vec<String>, String> = pat[1..].iter().map(|p| to_regex(p, groups)).collect();
+            format!("(?:{})", parts?.join("|"))
+        }
+        Expr::List(_) if h == sym("blank") || h == sym("blank_seq") || h == sym("blank_null_seq") => {
+            // a blank's head names a character class, like (blank_seq DigitCharacter)
+            let one = match &pat[1..] {
+                [] => "(?s:.)",
+                [Expr::Sym(s)] => class(s).ok_or_else(|| format!("{s} is not a character class"))?,
+                _ => return Err(format!("{pat} is not a string pattern")),
+            };
+            let rep = if h == sym("blank") {
+                ""
+            } else if h == sym("blank_seq") {
+                "+"
+            } else {
+                "*"
+            };
+            format!("(?:{one}){rep}")
+        }
+        Expr::List(_) if h == sym("pattern") && pat.len() == 3 => {
+            if let Some((name, _)) = groups.iter().find(|(_, p)| p[1] == pat[1]) {
+                return Ok(format!("\\k<{name}>"));
+            }
+            let name = format!("cas3p{}", groups.len());
+            groups.push((name.clone(), pat.clone()));
+            format!("(?P<{name}>{})", to_regex(&pat[2], groups)?)
+        }
+        _ => return Err(format!("{pat} is not a string pattern")),
+    })
+}
+
+/// the rhs of a string rule with the match's bindings filled in
+pub fn instantiate(rhs: &Expr, m: &StringMatch) -> Expr {
+    if m.bindings.is_empty() {
+        return rhs.clone();
+    }
+    replace_all(rhs, &pat_bindings_to_rules(&m.bindings))
+}
+
+/// 1 based character positions of byte offsets, for `StringPosition`
+pub fn char_span(s: &str, start: usize, end: usize) -> Expr {
+    let from = s[..start].chars().count() + 1;
+    let to = from + s[start..end].chars().count() - 1;
+    liste(vec![sym("List"), Expr::Int(from.into()), Expr::Int(to.into())])
+}
+
+/// a string pattern, a rule from one, or a list of either. rules come with their rhs
+pub fn string_rules(rules: &Expr) -> Result<Vec<(StringPattern, Option<Expr>)>, String> {
+    let rules = if head(rules) == sym("List") { rules[1..].to_vec() } else { vec![rules.clone()] };
+    rules
+        .iter()
+        .map(|r| {
+            if (head(r) == sym("rule") || head(r) == sym("rule_delayed")) && r.len() == 3 {
+                Ok((StringPattern::compile(&r[1])?, Some(r[2].clone())))
+            } else {
+                Ok((StringPattern::compile(r)?, None))
+            }
+        })
+        .collect()
+}
+
+/// non overlapping matches of any of `rules`, leftmost first, and the earliest rule when two start at
+/// the same place. gives (start, index of the rule, match)
+pub fn scan(s: &str, rules: &[(StringPattern, Option<Expr>)]) -> Vec<(usize, usize, StringMatch)> {
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos <= s.len() {
+        let best = rules
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (p, _))| {
+                let (start, m) = p.find_from(s, pos)?;
+                // an empty match can't make progress, look for a real one a char later
+                let (start, m) = if m.end > start { (start, m) } else { next_nonempty(p, s, start)? };
+                Some((start, i, m))
+            })
+            .min_by_key(|(start, i, _)| (*start, *i));
+        match best {
+            Some((start, i, m)) => {
+                pos = m.end;
+                out.push((start, i, m));
+            }
+            None => break,
+        }
+    }
+    out
+}
+
+fn next_nonempty(p: &StringPattern, s: &str, mut start: usize) -> Option<(usize, StringMatch)> {
+    loop {
+        start += s[start..].chars().next()?.len_utf8();
+        let (st, m) = p.find_from(s, start)?;
+        if m.end > st {
+            return Some((st, m));
+        }
+        start = st;
+    }
+}
+
+/// every place the pattern matches, overlapping ones included
+pub fn positions(s: &str, p: &StringPattern) -> Vec<Expr> {
+    let mut out = vec![];
+    let mut pos = 0;
+    while let Some((start, m)) = p.find_from(s, pos) {
+        if m.end > start {
+            out.push(char_span(s, start, m.end));
+        }
+        match s[start..].chars().next() {
+            Some(c) => pos = start + c.len_utf8(),
+            None => break,
+        }
+    }
+    out
+}
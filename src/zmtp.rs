@@ -0,0 +1,254 @@
+//! just enough of ZMTP 3.0, the zeromq wire protocol, for the jupyter kernel to talk to real
+//! zeromq peers over tcp without linking libzmq.
+//!
+//! only the NULL security mechanism. a `Router` prefixes what it receives with the identity of
+//! the peer it came from and sends to the peer named by the first frame, a `Publisher` sends to
+//! every subscriber whatever they subscribed to (jupyter subscribes to everything anyway), and
+//! a `Connection` is one end of a connection, enough for a DEALER or SUB client.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// the frames of one message
+pub type Message = Vec<Vec<u8>>;
+
+const MORE: u8 = 1;
+const LONG: u8 = 2;
+const COMMAND: u8 = 4;
+
+fn greeting(as_server: bool) -> [u8; 64] {
+    let mut g = [0u8; 64];
+    g[0] = 0xff;
+    g[9] = 0x7f;
+    g[10] = 3;
+    g[12..16].copy_from_slice(b"NULL");
+    g[32] = as_server as u8;
+    g
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+fn write_frame(out: &mut Vec<u8>, flags: u8, body: &[u8]) {
+    if body.len() > 255 {
+        out.push(flags | LONG);
+        out.extend((body.len() as u64).to_be_bytes());
+    } else {
+        out.push(flags);
+        out.push(body.len() as u8);
+    }
+    out.extend(body);
+}
+
+fn ready(socket_type: &str) -> Vec<u8> {
+    let mut body = b"\x05READY".to_vec();
+    body.push(11);
+    body.extend(b"Socket-Type");
+    body.extend((socket_type.len() as u32).to_be_bytes());
+    body.extend(socket_type.as_bytes());
+    let mut out = vec![];
+    write_frame(&mut out, COMMAND, &body);
+    out
+}
+
+/// the properties of a READY command, like `Socket-Type` and `Identity`
+fn properties(mut body: &[u8]) -> io::Result<HashMap<String, Vec<u8>>> {
+    let mut props = HashMap::new();
+    while !body.is_empty() {
+        let n = body[0] as usize;
+        let name = body.get(1..1 + n).ok_or_else(|| invalid("short READY"))?;
+        let len = body.get(1 + n..5 + n).ok_or_else(|| invalid("short READY"))?;
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        let value = body.get(5 + n..5 + n + len).ok_or_else(|| invalid("short READY"))?;
+        props.insert(String::from_utf8_lossy(name).into_owned(), value.to_vec());
+        body = &body[5 + n + len..];
+    }
+    Ok(props)
+}
+
+pub struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    /// swaps greetings. the READY commands come after
+    fn greet(mut stream: TcpStream, as_server: bool) -> io::Result<Self> {
+        stream.write_all(&greeting(as_server))?;
+        let mut theirs = [0u8; 64];
+        stream.read_exact(&mut theirs)?;
+        if theirs[0] != 0xff || theirs[9] & 1 != 1 || theirs[10] < 3 {
+            return Err(invalid("not a ZMTP 3 peer"));
+        }
+        if &theirs[12..16] != b"NULL" {
+            return Err(invalid("only the NULL mechanism is supported"));
+        }
+        Ok(Connection { stream })
+    }
+
+    /// connects to a zeromq socket as a `socket_type` socket, like "DEALER" or "SUB"
+    pub fn connect(addr: &str, socket_type: &str) -> io::Result<Self> {
+        let mut conn = Self::greet(TcpStream::connect(addr)?, false)?;
+        conn.stream.write_all(&ready(socket_type))?;
+        conn.read_ready()?;
+        Ok(conn)
+    }
+
+    fn read_ready(&mut self) -> io::Result<HashMap<String, Vec<u8>>> {
+        let (flags, body) = self.frame()?;
+        match body.strip_prefix(b"\x05READY") {
+            Some(props) if flags & COMMAND != 0 => properties(props),
+            _ => Err(invalid("expected READY")),
+        }
+    }
+
+    fn frame(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut flags = [0u8];
+        self.stream.read_exact(&mut flags)?;
+        let len = if flags[0] & LONG != 0 {
+            let mut len = [0u8; 8];
+            self.stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len) as usize
+        } else {
+            let mut len = [0u8];
+            self.stream.read_exact(&mut len)?;
+            len[0] as usize
+        };
+        let mut body = vec![0u8; len];
+        self.stream.read_exact(&mut body)?;
+        Ok((flags[0], body))
+    }
+
+    /// the next message, skipping commands like PING
+    pub fn recv(&mut self) -> io::Result<Message> {
+        let mut msg = vec![];
+        loop {
+            let (flags, body) = self.frame()?;
+            if flags & COMMAND != 0 {
+                continue;
+            }
+            msg.push(body);
+            if flags & MORE == 0 {
+                return Ok(msg);
+            }
+        }
+    }
+
+    pub fn send(&mut self, msg: &[Vec<u8>]) -> io::Result<()> {
+        send(&mut self.stream, msg)
+    }
+
+    /// for a SUB connection, `topic` is a prefix of the first frame
+    pub fn subscribe(&mut self, topic: &[u8]) -> io::Result<()> {
+        self.send(&[[&[1u8][..], topic].concat()])
+    }
+}
+
+fn send(stream: &mut TcpStream, msg: &[Vec<u8>]) -> io::Result<()> {
+    let mut out = vec![];
+    for (i, frame) in msg.iter().enumerate() {
+        let more = if i + 1 < msg.len() { MORE } else { 0 };
+        write_frame(&mut out, more, frame);
+    }
+    stream.write_all(&out)
+}
+
+type Peers = Arc<Mutex<HashMap<Vec<u8>, TcpStream>>>;
+
+/// accepts connections on `listener` forever, handing each one, once it has said READY, to
+/// `serve` with its identity. the peer is in `peers` before it hears our READY, so as soon as
+/// its connect returns we can send to it
+fn listen(
+    listener: TcpListener,
+    socket_type: &'static str,
+    peers: Peers,
+    serve: impl Fn(Vec<u8>, Connection) + Send + Sync + 'static,
+) {
+    let serve = Arc::new(serve);
+    thread::spawn(move || {
+        let mut next_id = 0u32;
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            next_id += 1;
+            let (peers, serve) = (peers.clone(), serve.clone());
+            let id = next_id;
+            thread::spawn(move || -> io::Result<()> {
+                let mut conn = Connection::greet(stream, true)?;
+                let props = conn.read_ready()?;
+                // like libzmq, peers without an identity get a 5 byte one starting with 0
+                let identity = match props.get("Identity") {
+                    Some(i) if !i.is_empty() => i.clone(),
+                    _ => [&[0u8][..], &id.to_be_bytes()].concat(),
+                };
+                peers.lock().unwrap().insert(identity.clone(), conn.stream.try_clone()?);
+                conn.stream.write_all(&ready(socket_type))?;
+                serve(identity.clone(), conn);
+                peers.lock().unwrap().remove(&identity);
+                Ok(())
+            });
+        }
+    });
+}
+
+pub struct Router {
+    pub port: u16,
+    peers: Peers,
+}
+
+impl Router {
+    /// listens on `addr` ("127.0.0.1:0" picks a port), sending what comes in to `incoming`
+    /// as `(tag, [identity, frames...])`
+    pub fn bind<T: Clone + Send + Sync + 'static>(
+        addr: &str,
+        tag: T,
+        incoming: Sender<(T, Message)>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let port = listener.local_addr()?.port();
+        let peers = Peers::default();
+        listen(listener, "ROUTER", peers.clone(), move |identity, mut conn| {
+            while let Ok(msg) = conn.recv() {
+                let msg = std::iter::once(identity.clone()).chain(msg).collect();
+                if incoming.send((tag.clone(), msg)).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Router { port, peers })
+    }
+
+    /// sends `[identity, frames...]` to that peer. like zeromq, a peer that's gone is silently
+    /// dropped
+    pub fn send(&self, msg: &[Vec<u8>]) {
+        let Some((identity, frames)) = msg.split_first() else { return };
+        if let Some(stream) = self.peers.lock().unwrap().get_mut(identity) {
+            let _ = send(stream, frames);
+        }
+    }
+}
+
+pub struct Publisher {
+    pub port: u16,
+    peers: Peers,
+}
+
+impl Publisher {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let port = listener.local_addr()?.port();
+        let peers = Peers::default();
+        // subscriptions are all that comes in, and everyone gets everything
+        listen(listener, "PUB", peers.clone(), |_, mut conn| while conn.recv().is_ok() {});
+        Ok(Publisher { port, peers })
+    }
+
+    pub fn send(&self, msg: &[Vec<u8>]) {
+        for stream in self.peers.lock().unwrap().values_mut() {
+            let _ = send(stream, msg);
+        }
+    }
+}